@@ -0,0 +1,188 @@
+use crate::product::Item;
+use log::info;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use store_protocol::OrderId;
+
+// Estado de uno de los envíos en los que se repartió un pedido del cliente.
+//
+// Variantes:
+// * `InDelivery`: El store reservó los productos y los está entregando.
+// * `Delivered`: El store avisó que se entregaron.
+// * `Failed`: El store avisó que no se pudieron entregar. Sus productos se vuelven a pedir en otro store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShipmentState {
+    InDelivery,
+    Delivered,
+    Failed,
+}
+
+// Parte de un pedido del cliente que tomó un store.
+//
+// Atributos:
+// * `store`: Store que tomó la parte.
+// * `order_id`: Id de pedido que le asignó ese store.
+// * `items`: Productos que reservó el store.
+// * `state`: Estado del envío.
+#[derive(Debug)]
+pub struct Shipment {
+    pub store: String,
+    pub order_id: OrderId,
+    pub items: Vec<Item>,
+    pub state: ShipmentState,
+}
+
+// Seguimiento de un pedido del cliente.
+//
+// Atributos:
+// * `requested`: Unidades pedidas en total.
+// * `delivered`: Unidades que ya se entregaron.
+// * `lost`: Unidades que ningún store pudo tomar o entregar.
+// * `shipments`: Envíos en los que se repartió el pedido.
+#[derive(Debug, Default)]
+struct CustomerOrder {
+    requested: i32,
+    delivered: i32,
+    lost: i32,
+    shipments: Vec<Shipment>,
+}
+
+impl CustomerOrder {
+    fn is_finished(&self) -> bool {
+        self.delivered + self.lost >= self.requested
+    }
+}
+
+// Registro de los pedidos de los clientes, compartido por todas las conexiones con los stores.
+//
+// Un pedido del cliente puede terminar repartido en varios envíos de distintos stores cuando se
+// permiten reservas parciales, o cuando una entrega falla y se vuelve a pedir en otro store.
+// Cuando todas sus unidades se entregaron o se perdieron se muestra un resumen.
+pub struct CustomerOrders {
+    orders: Mutex<HashMap<usize, CustomerOrder>>,
+}
+
+impl CustomerOrders {
+    // Crea un registro vacío.
+    pub fn new() -> Self {
+        CustomerOrders {
+            orders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Registra un pedido del cliente con los productos pedidos.
+    pub fn register(&self, customer_order: usize, items: &[Item]) {
+        let mut orders = self.orders.lock().unwrap();
+        let order = orders.entry(customer_order).or_default();
+        order.requested += units(items);
+    }
+
+    // Registra que el store `store` tomó una parte del pedido con el id `order_id`.
+    pub fn shipped(&self, customer_order: usize, store: &str, order_id: OrderId, items: Vec<Item>) {
+        let mut orders = self.orders.lock().unwrap();
+        let order = orders.entry(customer_order).or_default();
+        order.shipments.push(Shipment {
+            store: store.to_string(),
+            order_id,
+            items,
+            state: ShipmentState::InDelivery,
+        });
+        if order.shipments.len() > 1 {
+            info!(
+                customer_order = customer_order, shipments = order.shipments.len();
+                "El pedido del cliente va en varios envíos"
+            );
+        }
+    }
+
+    // Registra que el envío `order_id` del store `store` se entregó.
+    pub fn delivered(&self, customer_order: usize, store: &str, order_id: OrderId) {
+        let mut orders = self.orders.lock().unwrap();
+        let Some(order) = orders.get_mut(&customer_order) else {
+            return;
+        };
+        if let Some(shipment) = find_shipment(order, store, order_id) {
+            shipment.state = ShipmentState::Delivered;
+            let delivered = units(&shipment.items);
+            order.delivered += delivered;
+        }
+        finish_if_complete(customer_order, order);
+    }
+
+    // Registra que el envío `order_id` del store `store` no se pudo entregar.
+    pub fn failed(&self, customer_order: usize, store: &str, order_id: OrderId) {
+        let mut orders = self.orders.lock().unwrap();
+        if let Some(order) = orders.get_mut(&customer_order) {
+            if let Some(shipment) = find_shipment(order, store, order_id) {
+                shipment.state = ShipmentState::Failed;
+            }
+        }
+    }
+
+    // Registra productos del pedido que ningún store pudo tomar o entregar.
+    pub fn lost(&self, customer_order: usize, items: &[Item]) {
+        let mut orders = self.orders.lock().unwrap();
+        let Some(order) = orders.get_mut(&customer_order) else {
+            return;
+        };
+        order.lost += units(items);
+        finish_if_complete(customer_order, order);
+    }
+}
+
+fn units(items: &[Item]) -> i32 {
+    items.iter().map(|item| item.amount).sum()
+}
+
+fn find_shipment<'a>(
+    order: &'a mut CustomerOrder,
+    store: &str,
+    order_id: OrderId,
+) -> Option<&'a mut Shipment> {
+    order
+        .shipments
+        .iter_mut()
+        .find(|shipment| shipment.store == store && shipment.order_id == order_id)
+}
+
+fn finish_if_complete(customer_order: usize, order: &CustomerOrder) {
+    if order.is_finished() {
+        let shipments = order
+            .shipments
+            .iter()
+            .filter(|shipment| shipment.state == ShipmentState::Delivered)
+            .count();
+        info!(
+            customer_order = customer_order,
+            delivered = order.delivered,
+            requested = order.requested,
+            shipments = shipments;
+            "Pedido del cliente terminado"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_an_order_split_across_stores() {
+        let orders = CustomerOrders::new();
+        orders.register(1, &[Item { id: 1, amount: 5 }, Item { id: 2, amount: 1 }]);
+        orders.shipped(1, "1", 10, vec![Item { id: 1, amount: 3 }]);
+        orders.shipped(1, "2", 4, vec![Item { id: 1, amount: 2 }]);
+        orders.failed(1, "2", 4);
+        orders.shipped(1, "1", 11, vec![Item { id: 1, amount: 2 }]);
+        orders.delivered(1, "1", 10);
+        orders.delivered(1, "1", 11);
+        orders.lost(1, &[Item { id: 2, amount: 1 }]);
+
+        let order = &orders.orders.lock().unwrap()[&1];
+        assert_eq!(order.shipments.len(), 3);
+        assert_eq!(order.shipments[1].state, ShipmentState::Failed);
+        assert_eq!(order.delivered, 5);
+        assert_eq!(order.lost, 1);
+        assert!(order.is_finished());
+    }
+}
@@ -1,72 +1,64 @@
-use crate::product::Product;
+use crate::product::{Item, Priority, Product};
+use order_file::{parse_orders, ErrorPolicy, OrderLine, ParseSummary};
 use std::path::Path;
-use std::sync::Arc;
 use std::vec::Vec;
-use tokio::fs::File;
-use tokio::io::{self, AsyncBufReadExt, BufReader};
+use tokio::io;
 use tokio::sync::Mutex;
 
-// Función para procesar una línea del archivo.
-//
-// Asume que cada línea del archivo está en el formato "id,cantidad".
-// Descompone la línea en sus componentes y crea un producto.
+// Convierte una línea ya validada del archivo en un pedido.
 //
 // Argumentos:
-// * `line`: Una línea del archivo como `String`.
+// * `order`: Línea del archivo con los productos y la prioridad del pedido.
 //
 // Retorna:
-// Un `io::Result<Product>` que es `Ok` con un `Product` si la línea se procesa correctamente,
-// o un error en caso contrario.
-async fn process_line(line: String) -> io::Result<Product> {
-    let parts: Vec<&str> = line.split(',').collect();
-    let id = parts[0].parse::<i32>().unwrap();
-    let amount = parts[1].parse::<i32>().unwrap();
-    Ok(Product {
-        id,
-        amount,
+// El `Product` correspondiente, numerado según la línea del archivo en la que está, a partir de 1
+// para la primera línea después del encabezado.
+fn to_product(order: OrderLine) -> Product {
+    let items = order
+        .items
+        .into_iter()
+        .map(|item| Item {
+            id: item.id,
+            amount: item.amount,
+        })
+        .collect();
+    let priority = match order.priority {
+        order_file::Priority::Express => Priority::Express,
+        order_file::Priority::Standard => Priority::Standard,
+    };
+    Product {
+        items,
         stores: Vec::new(),
-    })
+        priority,
+        allow_partial: false,
+        customer_order: order.line - 1,
+    }
 }
 
 // Lee y procesa un archivo para crear una lista de productos.
 //
-// Abre el archivo especificado en `file_path`, lo lee línea por línea,
-// y procesa cada línea para crear un `Product`.
-// La primera línea del archivo se asume que es un encabezado y se omite.
-// Cada producto se encapsula en un `Mutex` para un manejo seguro en un entorno concurrente.
+// Cada línea está en el formato "id,cantidad[,id,cantidad...][,prioridad]" y se valida con el mismo
+// parser que usa el store. La primera línea del archivo se asume que es un encabezado y se omite.
+// Las líneas inválidas se tratan según `policy`: se saltean, cortan la lectura o se copian a otro
+// archivo. Cada producto se encapsula en un `Mutex` para un manejo seguro en un entorno concurrente.
 //
 // Argumentos:
 // * `file_path`: Una referencia a un `Path` que representa la ruta del archivo a leer.
+// * `policy`: Qué hacer con las líneas inválidas.
 //
 // Retorna:
-// Un `io::Result<Vec<Mutex<Product>>>` que es `Ok` con un vector de `Mutex<Product>` si el archivo
-// se lee y procesa correctamente, o un error en caso contrario.
-pub async fn read_and_process_file(file_path: &Path) -> io::Result<Vec<Mutex<Product>>> {
-    let file = File::open(file_path).await?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
-    lines.next_line().await?;
-
-    let products = Arc::new(Mutex::new(Vec::new()));
-
-    let mut tasks = vec![];
-
-    while let Some(line) = lines.next_line().await? {
-        let products_clone = Arc::clone(&products);
-        let task = tokio::spawn(async move {
-            let product = process_line(line).await.unwrap();
-            let mut products = products_clone.lock().await;
-            products.push(Mutex::new(product));
-        });
-        tasks.push(task);
-    }
-
-    for task in tasks {
-        task.await?;
-    }
-
-    let final_products = Arc::try_unwrap(products)
-        .expect("Arc still has multiple owners")
-        .into_inner();
-    Ok(final_products)
+// Un `io::Result` que es `Ok` con los pedidos, en el orden del archivo, y el resumen de la lectura,
+// o un error si no se pudo leer el archivo o hay una línea inválida y la política es cortar.
+pub async fn read_and_process_file(
+    file_path: &Path,
+    policy: &ErrorPolicy,
+) -> io::Result<(Vec<Mutex<Product>>, ParseSummary)> {
+    let contents = tokio::fs::read_to_string(file_path).await?;
+    let order_file = parse_orders(&contents, policy)?;
+    let products = order_file
+        .orders
+        .into_iter()
+        .map(|order| Mutex::new(to_product(order)))
+        .collect();
+    Ok((products, order_file.summary))
 }
@@ -1,20 +1,31 @@
-use async_std::task;
+use customer_orders::CustomerOrders;
 use file_reader::read_and_process_file;
+use log::{error, info, LevelFilter};
+use logger::{LogFormat, Logger};
+use metrics::{serve_metrics, EcommerceMetrics};
+use order_file::ErrorPolicy;
+use product::Product;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 use rand::Rng;
 use shared_state::SharedState;
+use simulation::{Clock, Simulation, ASSIGNMENT_STREAM};
 use std::collections::HashMap;
+use std::env;
 use std::path::Path;
-use std::sync::{Arc, Condvar, Mutex};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use store_connection::handle_store_connection;
+use store_connection::{handle_store_connection, ConnectionConfig, ConnectionContext};
 use tokio::io;
+use tokio::sync::Notify;
 
+mod customer_orders;
 mod file_reader;
-mod messages;
+mod metrics;
 mod product;
 mod read_stores;
 mod shared_state;
+mod simulation;
 mod store_connection;
 
 // Punto de entrada principal del programa.
@@ -23,79 +34,246 @@ mod store_connection;
 // establece conexiones con las tiendas y asigna productos a estas tiendas de manera aleatoria.
 //
 // La función realiza las siguientes operaciones:
-// 1. Lee los productos del archivo "pedidos.csv" y las tiendas del archivo "stores.csv".
+// 1. Lee los productos del archivo "pedidos.csv" y las tiendas del archivo "stores.csv", junto con
+//    los tokens de los stores que piden autenticación.
 // 2. Crea un estado compartido para cada tienda y lanza una tarea asincrónica para manejar
 //    la conexión con cada tienda.
 // 3. Asigna los productos leídos a las tiendas de manera aleatoria.
 // 4. Espera a que todas las conexiones de tiendas completen su procesamiento.
 //
+// Con la opción `--partial` los stores pueden tomar solo la parte del pedido que tengan en stock,
+// y el resto se pide en otros stores. Con `--metrics-port <puerto>` se exponen las métricas en
+// formato Prometheus. Con `--log-format <text|json>` y `--log-level <nivel>` se elige el formato y el
+// nivel mínimo de los logs. Con `--seed <número>` los pedidos se reparten siempre igual y con
+// `--virtual-time` las esperas no pasan en tiempo real. Con `--on-error <skip|abort|file:<ruta>>` se
+// elige qué hacer con las líneas inválidas del archivo de pedidos. Las opciones de las conexiones con
+// los stores se leen con `connection_config`.
+//
 // Retorna:
 // Un `io::Result<()>` que indica el resultado de la ejecución del programa.
 // Retorna `Ok(())` si el programa se ejecuta correctamente o un error en caso de fallos.
 #[tokio::main]
 async fn main() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let allow_partial = args.iter().any(|arg| arg == "--partial");
+    let metrics_port = option_value(&args, "--metrics-port");
+    let log_format = match option_value(&args, "--log-format") {
+        Some(value) => value.parse().map_err(invalid_option)?,
+        None => LogFormat::Text,
+    };
+    let log_level = match option_value(&args, "--log-level") {
+        Some(value) => value
+            .parse()
+            .map_err(|_| invalid_option(format!("Nivel de log inválido: {}", value)))?,
+        None => LevelFilter::Info,
+    };
+    Logger::new(log_format, log_level, Vec::new())
+        .init()
+        .map_err(io::Error::other)?;
+    let seed = match option_value(&args, "--seed") {
+        Some(value) => Some(
+            value
+                .parse()
+                .map_err(|_| invalid_option(format!("Semilla inválida: {}", value)))?,
+        ),
+        None => None,
+    };
+    let clock = if args.iter().any(|arg| arg == "--virtual-time") {
+        Clock::virtual_time()
+    } else {
+        Clock::real()
+    };
+    let simulation = Simulation { seed, clock };
+    let connection_config = connection_config(&args)?;
     let file_path = Path::new("./pedidos.csv");
-    let products = read_and_process_file(file_path).await?;
-    println!("[E-COMMERCE] {} products read", products.len());
+    let on_error = match option_value(&args, "--on-error") {
+        Some(value) => value.parse().map_err(invalid_option)?,
+        None => ErrorPolicy::Skip,
+    };
+    let (mut products, summary) = match read_and_process_file(file_path, &on_error).await {
+        Ok(result) => result,
+        Err(e) => {
+            error!(error:% = e; "No se pudo leer el archivo de pedidos");
+            return Err(e);
+        }
+    };
+    info!(
+        orders = summary.orders, rejected = summary.rejected_total();
+        "Pedidos leídos: {}", summary
+    );
+    let metrics = Arc::new(EcommerceMetrics::new());
+    metrics.orders_received(products.len());
+
+    let customer_orders = Arc::new(CustomerOrders::new());
+    for product in products.iter_mut() {
+        let product = product.get_mut();
+        product.allow_partial = allow_partial;
+        customer_orders.register(product.customer_order, &product.items);
+    }
 
-    let stores = Arc::new(read_stores::read_stores("./stores.csv").unwrap());
+    let stores = match read_stores::read_stores("./stores.csv") {
+        Ok(stores) => Arc::new(stores),
+        Err(e) => {
+            error!(error:% = e; "Error al leer los stores");
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+        }
+    };
     let mut store_ids = Vec::new();
     let mut connections = Vec::new();
     let mut store_states = HashMap::new();
-    
-    // Crear la estructura compartida con Mutex y Condvar para cada tienda.
-    for (id, address) in stores.iter() {
+
+    // Crear la estructura compartida con Mutex y Notify para cada tienda.
+    for id in stores.keys() {
         store_ids.push(id.clone());
-        let shared_state = Arc::new((Mutex::new(SharedState::new()), Condvar::new()));
+        let shared_state = Arc::new((Mutex::new(SharedState::new()), Notify::new()));
         store_states.insert(id.clone(), shared_state.clone());
     }
-    
-    for (id, address) in stores.iter(){
-        println!("[E-COMMERCE] Intentando conectar al Store {}: {}", id, address);
-        let id_clone = id.clone();
-        let address_clone = address.clone();
-        let shared_state = Arc::clone(&store_states.get(id).unwrap());  
-        let stores_id_clone = store_ids.clone();
-        let stores_states_clone = store_states.clone();
-        let connection = tokio::spawn(async move {
-            handle_store_connection(
-                id_clone,
-                address_clone,
-                shared_state,
-                stores_id_clone,
-                stores_states_clone,
-            )
-            .await;
+    //Ordeno los ids para que el reparto con una misma semilla no dependa del orden del HashMap
+    store_ids.sort();
+
+    if let Some(metrics_port) = metrics_port {
+        let server = serve_metrics(metrics_port, metrics.clone(), store_states.clone())?;
+        tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!(error:% = e; "Error en el servidor de métricas");
+            }
         });
+    }
+
+    for (id, store) in stores.iter() {
+        info!(
+            store = id, address = store.address, auth = store.token.is_some();
+            "Intentando conectar al store"
+        );
+        let context = ConnectionContext {
+            shared_state: Arc::clone(store_states.get(id).unwrap()),
+            stores_ids: store_ids.clone(),
+            stores_states: store_states.clone(),
+            customer_orders: customer_orders.clone(),
+            metrics: metrics.clone(),
+            clock: simulation.clock.clone(),
+        };
+        let config = ConnectionConfig {
+            token: store.token.clone(),
+            ..connection_config.clone()
+        };
+        let connection = tokio::spawn(handle_store_connection(
+            id.clone(),
+            store.address.clone(),
+            context,
+            config,
+        ));
         connections.push(connection);
     }
-    
-    let mut rng = rand::thread_rng();
-    // Asignar productos a las conexiones de manera aleatoria
+
+    let mut rng = simulation.rng(ASSIGNMENT_STREAM);
+    assign_orders(
+        products,
+        &store_ids,
+        &store_states,
+        &mut rng,
+        &simulation.clock,
+    )
+    .await;
+    info!("No tengo mas productos para enviar");
+
+    // Esperar a que todas las tareas asincrónicas se completen
+    for connection in connections {
+        let _ = connection.await;
+    }
+
+    Ok(())
+}
+
+// Asigna cada pedido a un store elegido al azar, esperando entre 1 y 4 segundos entre uno y otro.
+//
+// Argumentos:
+// * `products`: Pedidos a repartir.
+// * `store_ids`: Identificadores de los stores, en un orden fijo para que el reparto dependa solo de `rng`.
+// * `store_states`: Estado compartido de cada store, donde se encola el pedido y se avisa por su `Notify`
+//   a la conexión con el store que hay un producto nuevo.
+// * `rng`: Generador con el que se eligen el store y la espera.
+// * `clock`: Reloj con el que se espera entre pedidos.
+async fn assign_orders(
+    products: Vec<tokio::sync::Mutex<Product>>,
+    store_ids: &[String],
+    store_states: &HashMap<String, Arc<(Mutex<SharedState>, Notify)>>,
+    rng: &mut StdRng,
+    clock: &Clock,
+) {
     for product in products {
-        if let Some(random_id) = store_ids.choose(&mut rng) {
-            let store_id_str = random_id.to_string();
-            if let Some(shared_state_arc) = store_states.get(&store_id_str) {
-                let (shared_state_mutex, cvar) = &**shared_state_arc;
+        if let Some(random_id) = store_ids.choose(rng) {
+            if let Some(shared_state_arc) = store_states.get(random_id) {
+                let (shared_state_mutex, notify) = &**shared_state_arc;
                 let mut shared_state = shared_state_mutex.lock().unwrap();
                 shared_state.products_to_deliver.push(product);
-                cvar.notify_one();
+                notify.notify_one();
             }
         }
 
-        let sleep_time = rand::thread_rng().gen_range(1..5);
-        task::sleep(Duration::from_secs(sleep_time)).await;
+        let sleep_time = rng.gen_range(1..5);
+        clock.sleep(Duration::from_secs(sleep_time)).await;
     }
-    println!("No tengo mas productos para enviar");
+}
 
-    // Esperar a que todas las tareas asincrónicas se completen
-    for connection in connections {
-        let _ = connection.await;
+// Arma la configuración de las conexiones con los stores. Con `--window <cantidad>` se elige cuántos
+// pedidos sin respuesta puede tener cada conexión, con `--heartbeat-interval <segundos>` cada cuánto
+// se envían heartbeats, con `--heartbeat-timeout <segundos>` cuánto puede pasar sin recibir nada del
+// store y con `--response-timeout <segundos>` cuánto puede tardar la respuesta a un pedido.
+//
+// Retorna:
+// La configuración, o un error si algún valor es inválido o si los heartbeats no alcanzan a llegar
+// antes de que se dé la conexión por muerta.
+fn connection_config(args: &[String]) -> io::Result<ConnectionConfig> {
+    let mut config = ConnectionConfig::default();
+    if let Some(value) = option_value(args, "--window") {
+        config.window = match value.parse() {
+            Ok(window) if window > 0 => window,
+            _ => return Err(invalid_option(format!("Ventana inválida: {}", value))),
+        };
+    }
+    if let Some(interval) = seconds_option(args, "--heartbeat-interval")? {
+        config.heartbeat_interval = interval;
+    }
+    if let Some(timeout) = seconds_option(args, "--heartbeat-timeout")? {
+        config.heartbeat_timeout = timeout;
+    }
+    if let Some(timeout) = seconds_option(args, "--response-timeout")? {
+        config.response_timeout = timeout;
+    }
+    if config.heartbeat_timeout <= config.heartbeat_interval {
+        return Err(invalid_option(
+            "El tiempo sin heartbeats tiene que ser mayor al intervalo entre heartbeats"
+                .to_string(),
+        ));
     }
+    Ok(config)
+}
 
-    Ok(())
+// Duración en segundos que acompaña a la opción `option`, si está. Tiene que ser positiva.
+fn seconds_option(args: &[String], option: &str) -> io::Result<Option<Duration>> {
+    match option_value(args, option) {
+        Some(value) => match value.parse::<f64>() {
+            Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(Some(Duration::from_secs_f64(secs))),
+            _ => Err(invalid_option(format!(
+                "Valor inválido para {}: {}",
+                option, value
+            ))),
+        },
+        None => Ok(None),
+    }
 }
 
+// Valor que acompaña a la opción `option` en los argumentos, si está.
+fn option_value<'a>(args: &'a [String], option: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == option)
+        .and_then(|index| args.get(index + 1))
+}
+
+fn invalid_option(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
 
 #[cfg(test)]
 mod tests {
@@ -104,67 +282,75 @@ mod tests {
     #[tokio::test]
     async fn read_orders_csv() {
         let file_path = Path::new("./pedidos.csv");
-        let products = match read_and_process_file(file_path).await {
-            Ok(products) => products,
+        let products = match read_and_process_file(file_path, &ErrorPolicy::Abort).await {
+            Ok((products, _)) => products,
             Err(err) => {
                 panic!("Failed to read and process file: {:?}", err);
             }
         };
-        assert_eq!(
-            products.len(),
-            100
-        );
+        assert_eq!(products.len(), 100);
     }
 
-    #[tokio::test]
-    async fn giving_orders_to_stores() {
-
+    // Reparte los pedidos del archivo con la semilla `seed` y devuelve los pedidos del cliente
+    // que quedaron en la cola de cada store.
+    async fn assign_with_seed(seed: u64) -> Vec<Vec<usize>> {
         let file_path = Path::new("./pedidos.csv");
-        let products = match read_and_process_file(file_path).await {
-            Ok(products) => products,
+        let products = match read_and_process_file(file_path, &ErrorPolicy::Abort).await {
+            Ok((products, _)) => products,
             Err(err) => {
                 panic!("Failed to read and process file: {:?}", err);
             }
         };
-   
+
         let stores = Arc::new(read_stores::read_stores("./stores.csv").unwrap());
-        let mut store_ids = Vec::new();
+        let mut store_ids: Vec<String> = stores.keys().cloned().collect();
+        store_ids.sort();
         let mut store_states = HashMap::new();
-    
-        // Crear la estructura compartida con Mutex y Condvar para cada tienda.
-        for (id, address) in stores.iter() {
-            store_ids.push(id.clone());
-            let shared_state = Arc::new((Mutex::new(SharedState::new()), Condvar::new()));
-            store_states.insert(id.clone(), shared_state.clone());
-        }
-
-        let mut rng = rand::thread_rng();
-        // Asignar productos a las conexiones de manera aleatoria
-        for product in products {
-            if let Some(random_id) = store_ids.choose(&mut rng) {
-                let store_id_str = random_id.to_string();
-                if let Some(shared_state_arc) = store_states.get(&store_id_str) {
-                    let (shared_state_mutex, cvar) = &**shared_state_arc;
-                    let mut shared_state = shared_state_mutex.lock().unwrap();
-                    shared_state.products_to_deliver.push(product);
-                }
-            }
 
-            let sleep_time = rand::thread_rng().gen_range(1..5);
-            task::sleep(Duration::from_secs(sleep_time)).await;
+        // Crear la estructura compartida con Mutex y Notify para cada tienda.
+        for id in &store_ids {
+            let shared_state = Arc::new((Mutex::new(SharedState::new()), Notify::new()));
+            store_states.insert(id.clone(), shared_state);
         }
 
-        let (shared_state_mutex, cvar) = &**store_states.get("1").unwrap();
-        let shared_state = shared_state_mutex.lock().unwrap();
-        let mut orders = shared_state.products_to_deliver.len();
+        let simulation = Simulation {
+            seed: Some(seed),
+            clock: Clock::virtual_time(),
+        };
+        let mut rng = simulation.rng(ASSIGNMENT_STREAM);
+        assign_orders(
+            products,
+            &store_ids,
+            &store_states,
+            &mut rng,
+            &simulation.clock,
+        )
+        .await;
+        // Entre pedido y pedido se esperan de 1 a 4 segundos virtuales
+        assert!(simulation.clock.now() >= Duration::from_secs(100));
 
-        let (shared_state_mutex, cvar) = &**store_states.get("2").unwrap();
-        let shared_state = shared_state_mutex.lock().unwrap();
-        orders += shared_state.products_to_deliver.len();
+        let mut queues = Vec::new();
+        for id in &store_ids {
+            let (shared_state_mutex, _notify) = &*store_states[id];
+            let shared_state = shared_state_mutex.lock().unwrap();
+            let queue = shared_state
+                .products_to_deliver
+                .iter()
+                .map(|product| product.try_lock().unwrap().customer_order)
+                .collect();
+            queues.push(queue);
+        }
+        queues
+    }
 
-        assert_eq!(
-            orders,
-            100
-        );
+    #[tokio::test]
+    async fn giving_orders_to_stores() {
+        let started = std::time::Instant::now();
+        let queues = assign_with_seed(42).await;
+        let orders: usize = queues.iter().map(|queue| queue.len()).sum();
+        assert_eq!(orders, 100);
+        // Con la misma semilla el reparto se repite y con reloj virtual no se espera nada
+        assert_eq!(assign_with_seed(42).await, queues);
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 }
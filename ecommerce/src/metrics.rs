@@ -0,0 +1,225 @@
+use crate::shared_state::SharedState;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::info;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+// Métricas del ecommerce en formato Prometheus.
+//
+// Los contadores y la latencia los actualizan las conexiones con los stores. La cantidad de pedidos
+// en la cola de cada store se mide recién cuando se piden las métricas.
+//
+// Atributos:
+// * `registry`: Registro con todas las métricas del ecommerce.
+// * `orders_received`: Pedidos leídos del archivo.
+// * `orders_accepted`: Pedidos que tomó cada store, total o parcialmente.
+// * `orders_rejected`: Pedidos que rechazó cada store, por motivo.
+// * `deliveries`: Avisos de entrega de cada store, por resultado (`delivered` o `failed`).
+// * `reconnects`: Intentos de reconexión con cada store.
+// * `queue_depth`: Pedidos en `products_to_deliver` de cada store.
+// * `order_latency`: Segundos desde que se le envía un pedido a un store hasta que responde.
+pub struct EcommerceMetrics {
+    registry: Registry,
+    orders_received: IntCounter,
+    orders_accepted: IntCounterVec,
+    orders_rejected: IntCounterVec,
+    deliveries: IntCounterVec,
+    reconnects: IntCounterVec,
+    queue_depth: IntGaugeVec,
+    order_latency: HistogramVec,
+}
+
+impl EcommerceMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let orders_received = IntCounter::new(
+            "ecommerce_orders_received_total",
+            "Pedidos leídos del archivo",
+        )
+        .unwrap();
+        registry
+            .register(Box::new(orders_received.clone()))
+            .unwrap();
+        let orders_accepted = counter(
+            &registry,
+            "ecommerce_orders_accepted_total",
+            "Pedidos que tomó cada store",
+            &["store"],
+        );
+        let orders_rejected = counter(
+            &registry,
+            "ecommerce_orders_rejected_total",
+            "Pedidos que rechazó cada store",
+            &["store", "reason"],
+        );
+        let deliveries = counter(
+            &registry,
+            "ecommerce_deliveries_total",
+            "Avisos de entrega de cada store",
+            &["store", "result"],
+        );
+        let reconnects = counter(
+            &registry,
+            "ecommerce_store_reconnects_total",
+            "Intentos de reconexión con cada store",
+            &["store"],
+        );
+        let queue_depth = IntGaugeVec::new(
+            Opts::new(
+                "ecommerce_queue_depth",
+                "Pedidos esperando para enviarse a cada store",
+            ),
+            &["store"],
+        )
+        .unwrap();
+        registry.register(Box::new(queue_depth.clone())).unwrap();
+        let order_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "ecommerce_order_latency_seconds",
+                "Tiempo desde que se envía un pedido hasta que el store responde",
+            ),
+            &["store"],
+        )
+        .unwrap();
+        registry.register(Box::new(order_latency.clone())).unwrap();
+        EcommerceMetrics {
+            registry,
+            orders_received,
+            orders_accepted,
+            orders_rejected,
+            deliveries,
+            reconnects,
+            queue_depth,
+            order_latency,
+        }
+    }
+
+    // Registra los pedidos leídos del archivo.
+    pub fn orders_received(&self, count: usize) {
+        self.orders_received.inc_by(count as u64);
+    }
+
+    // Registra la respuesta de un store a un pedido y cuánto tardó. `rejection` es el motivo si el
+    // store no lo tomó.
+    pub fn order_answered(&self, store: &str, rejection: Option<&str>, latency: Duration) {
+        match rejection {
+            None => self.orders_accepted.with_label_values(&[store]).inc(),
+            Some(reason) => self
+                .orders_rejected
+                .with_label_values(&[store, reason])
+                .inc(),
+        }
+        self.order_latency
+            .with_label_values(&[store])
+            .observe(latency.as_secs_f64());
+    }
+
+    // Registra un aviso de entrega de un store.
+    pub fn delivery_reported(&self, store: &str, delivered: bool) {
+        let result = if delivered { "delivered" } else { "failed" };
+        self.deliveries.with_label_values(&[store, result]).inc();
+    }
+
+    // Registra un intento de reconexión con un store.
+    pub fn reconnect(&self, store: &str) {
+        self.reconnects.with_label_values(&[store]).inc();
+    }
+
+    // Mide las colas de los stores y devuelve todas las métricas en el formato de texto de Prometheus.
+    fn render(&self, stores_states: &HashMap<String, Arc<(Mutex<SharedState>, Notify)>>) -> String {
+        for (store, shared_state) in stores_states {
+            let (lock, _notify) = &**shared_state;
+            let depth = lock.lock().unwrap().products_to_deliver.len();
+            self.queue_depth
+                .with_label_values(&[store])
+                .set(depth as i64);
+        }
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+fn counter(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+    registry.register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+// Levanta el servidor HTTP que expone las métricas en `http://127.0.0.1:<port>/metrics`.
+//
+// Retorna:
+// El futuro que atiende los pedidos hasta que se lo cancele, o un error si no se pudo abrir el puerto.
+pub fn serve_metrics(
+    port: &str,
+    metrics: Arc<EcommerceMetrics>,
+    stores_states: HashMap<String, Arc<(Mutex<SharedState>, Notify)>>,
+) -> io::Result<impl Future<Output = hyper::Result<()>>> {
+    let listener = std::net::TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    listener.set_nonblocking(true)?;
+    let stores_states = Arc::new(stores_states);
+    let make_service = make_service_fn(move |_conn| {
+        let metrics = metrics.clone();
+        let stores_states = stores_states.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let response = if req.method() == Method::GET && req.uri().path() == "/metrics" {
+                    Response::builder()
+                        .header("Content-Type", TextEncoder::new().format_type())
+                        .body(Body::from(metrics.render(&stores_states)))
+                } else {
+                    Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                };
+                async move { Ok::<_, Infallible>(response.unwrap()) }
+            }))
+        }
+    });
+    let server = Server::from_tcp(listener).map_err(io::Error::other)?;
+    info!(port = port; "Métricas en http://127.0.0.1:{}/metrics", port);
+    Ok(server.serve(make_service))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_counters_latency_and_queue_depth() {
+        let metrics = EcommerceMetrics::new();
+        metrics.orders_received(3);
+        metrics.order_answered("1", None, Duration::from_millis(20));
+        metrics.order_answered("2", Some("insufficient_stock"), Duration::from_millis(5));
+        metrics.delivery_reported("1", true);
+        metrics.reconnect("2");
+        let mut stores_states = HashMap::new();
+        stores_states.insert(
+            "1".to_string(),
+            Arc::new((Mutex::new(SharedState::new()), Notify::new())),
+        );
+
+        let text = metrics.render(&stores_states);
+        assert!(text.contains("ecommerce_orders_received_total 3"));
+        assert!(text.contains("ecommerce_orders_accepted_total{store=\"1\"} 1"));
+        assert!(text.contains(
+            "ecommerce_orders_rejected_total{reason=\"insufficient_stock\",store=\"2\"} 1"
+        ));
+        assert!(text.contains("ecommerce_deliveries_total{result=\"delivered\",store=\"1\"} 1"));
+        assert!(text.contains("ecommerce_store_reconnects_total{store=\"2\"} 1"));
+        assert!(text.contains("ecommerce_queue_depth{store=\"1\"} 0"));
+        assert!(text.contains("ecommerce_order_latency_seconds_count{store=\"1\"} 1"));
+    }
+}
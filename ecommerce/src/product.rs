@@ -1,21 +1,34 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use store_protocol::{CartRequest, StoreRequest};
+// Los productos de un pedido y su prioridad se envían tal cual al store
+pub use store_protocol::{Item, Priority};
 
-// Representa un producto en el sistema.
+// Representa un pedido en el sistema.
 //
-// Esta estructura se utiliza para almacenar información sobre un producto,
-// incluyendo su identificador, la cantidad solicitada y una lista de tiendas
-// donde se ha intentado enviar el pedido.
+// Esta estructura se utiliza para almacenar información sobre un pedido,
+// incluyendo los productos con la cantidad solicitada de cada uno y una lista de tiendas
+// donde se ha intentado enviar el pedido. El store reserva todos los productos o ninguno
+// y los entrega en un mismo envío.
 //
 // Atributos:
-// * `id`: Identificador del producto, representado por un entero de 32 bits.
-// * `amount`: Cantidad del producto solicitada, representada por un entero de 32 bits.
+// * `items`: Productos pedidos, cada uno con su cantidad.
 // * `stores`: Vector que contiene las tiendas a las cuales se ha
 //   intentado enviar el pedido.
+// * `priority`: Prioridad con la que se pide que el store entregue el pedido.
+// * `allow_partial`: Si el store puede reservar solo lo que tenga y avisar lo que falta.
+// * `customer_order`: Pedido del cliente al que pertenece. Si el pedido se reparte entre varios
+//   stores, todas las partes tienen el mismo. No se envía al store.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Product {
-    pub id: i32,
-    pub amount: i32,
+    pub items: Vec<Item>,
     pub stores: Vec<String>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub allow_partial: bool,
+    #[serde(skip)]
+    pub customer_order: usize,
 }
 
 impl Product {
@@ -40,4 +53,92 @@ impl Product {
     pub fn get_stores(&self) -> Vec<String> {
         self.stores.clone()
     }
+
+    // Separa del pedido los productos que el store no tenía.
+    //
+    // El pedido se queda con lo que el store reservó y se devuelve un pedido nuevo con lo que falta,
+    // que pertenece al mismo pedido del cliente y conserva las tiendas ya intentadas.
+    //
+    // Argumentos:
+    // * `remaining`: Productos que faltaron, tal como los informó el store.
+    //
+    // Retorna:
+    // El pedido con los productos que faltan.
+    pub fn split_off(&mut self, remaining: Vec<Item>) -> Product {
+        let mut missing: HashMap<i32, i32> = HashMap::new();
+        for item in &remaining {
+            *missing.entry(item.id).or_insert(0) += item.amount;
+        }
+        self.items = self
+            .items
+            .iter()
+            .filter_map(|item| {
+                let left = missing.entry(item.id).or_insert(0);
+                let taken = item.amount.min(*left);
+                *left -= taken;
+                let amount = item.amount - taken;
+                (amount > 0).then_some(Item {
+                    id: item.id,
+                    amount,
+                })
+            })
+            .collect();
+        Product {
+            items: remaining,
+            stores: self.stores.clone(),
+            priority: self.priority,
+            allow_partial: self.allow_partial,
+            customer_order: self.customer_order,
+        }
+    }
+
+    // Saca del pedido los productos con id `product_id`.
+    //
+    // Retorna:
+    // Los productos que se sacaron.
+    pub fn remove_product(&mut self, product_id: i32) -> Vec<Item> {
+        let (removed, kept) = self.items.drain(..).partition(|item| item.id == product_id);
+        self.items = kept;
+        removed
+    }
+}
+
+// El pedido que se le envía al store es un carrito con los productos, sin las tiendas ya intentadas
+// ni el pedido del cliente.
+impl From<&Product> for StoreRequest {
+    fn from(product: &Product) -> StoreRequest {
+        StoreRequest::Cart(CartRequest {
+            items: product.items.clone(),
+            priority: product.priority,
+            allow_partial: product.allow_partial,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_off_keeps_the_reserved_items() {
+        let mut product = Product {
+            items: vec![Item { id: 1, amount: 5 }, Item { id: 2, amount: 1 }],
+            stores: vec!["1".to_string()],
+            priority: Priority::Express,
+            allow_partial: true,
+            customer_order: 7,
+        };
+        let rest = product.split_off(vec![Item { id: 1, amount: 2 }]);
+        assert_eq!(
+            product.items,
+            vec![Item { id: 1, amount: 3 }, Item { id: 2, amount: 1 }]
+        );
+        assert_eq!(rest.items, vec![Item { id: 1, amount: 2 }]);
+        assert_eq!(rest.customer_order, 7);
+        assert_eq!(rest.stores, product.stores);
+
+        let unknown = product.remove_product(2);
+        assert_eq!(unknown, vec![Item { id: 2, amount: 1 }]);
+        assert_eq!(product.items, vec![Item { id: 1, amount: 3 }]);
+    }
 }
@@ -1,31 +1,87 @@
 use csv::ReaderBuilder;
 use std::collections::HashMap;
 use std::error::Error;
+use std::path::Path;
+use store_protocol::AuthToken;
+
+// Datos de conexión de un store.
+//
+// Atributos:
+// * `address`: Dirección IP y puerto del store.
+// * `token`: Token con el que el ecommerce se autentica ante el store, si el store lo pide.
+#[derive(Debug, Clone)]
+pub struct StoreEntry {
+    pub address: String,
+    pub token: Option<AuthToken>,
+}
 
 // Lee un archivo CSV y crea un mapa de tiendas.
 //
 // Esta función lee un archivo CSV cuya ruta se especifica en `file_path`.
 // Cada línea del archivo CSV se espera que contenga un identificador de tienda y una dirección IP,
-// separados por una coma. La función crea y devuelve un mapa donde cada identificador
-// se asocia con su correspondiente dirección IP.
+// separados por una coma, y opcionalmente la ruta del archivo con el token del store. La función
+// crea y devuelve un mapa donde cada identificador se asocia con los datos de conexión del store.
 //
 // Argumentos:
 // * `file_path`: Una referencia a una cadena de texto que representa la ruta del archivo CSV a leer.
 //
 // Retorna:
-// Un `Result` que contiene un `HashMap<String, String>` si la lectura es exitosa.
-// Cada clave del `HashMap` es el identificador de una tienda y su valor es la dirección IP correspondiente.
-// En caso de error en la lectura del archivo o en el procesamiento de los datos, retorna un `Error`.
-pub fn read_stores(file_path: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
-    let mut rdr = ReaderBuilder::new().from_path(file_path)?;
+// Un `Result` que contiene un `HashMap<String, StoreEntry>` si la lectura es exitosa.
+// Cada clave del `HashMap` es el identificador de una tienda y su valor es su dirección y su token.
+// En caso de error en la lectura del archivo, de alguno de los tokens o en el procesamiento de los
+// datos, retorna un `Error`.
+pub fn read_stores(file_path: &str) -> Result<HashMap<String, StoreEntry>, Box<dyn Error>> {
+    //La columna del token es opcional, así que no todas las líneas tienen la misma cantidad de campos
+    let mut rdr = ReaderBuilder::new().flexible(true).from_path(file_path)?;
     let mut stores = HashMap::new();
 
     for result in rdr.records() {
         let record = result?;
         let id = record.get(0).unwrap().to_string();
         let ip = record.get(1).unwrap().to_string();
-        stores.insert(id, ip);
+        let token = match record.get(2).map(str::trim) {
+            Some(token_file) if !token_file.is_empty() => Some(
+                AuthToken::read(Path::new(token_file))
+                    .map_err(|e| format!("No se pudo leer el token del store {}: {}", id, e))?,
+            ),
+            _ => None,
+        };
+        stores.insert(id, StoreEntry { address: ip, token });
     }
 
     Ok(stores)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn the_token_column_is_optional() {
+        let dir = env::temp_dir();
+        let token_file = dir.join(format!("store_token_test_{}", std::process::id()));
+        let stores_file = dir.join(format!("stores_test_{}.csv", std::process::id()));
+        fs::write(&token_file, "secreto\n").unwrap();
+        fs::write(
+            &stores_file,
+            format!(
+                "id,address,token_file\n1,127.0.0.1:8080,{}\n2,127.0.0.1:8081\n",
+                token_file.display()
+            ),
+        )
+        .unwrap();
+
+        let stores = read_stores(stores_file.to_str().unwrap()).unwrap();
+        fs::remove_file(&token_file).unwrap();
+        fs::remove_file(&stores_file).unwrap();
+
+        assert_eq!(stores["1"].address, "127.0.0.1:8080");
+        assert!(stores["1"]
+            .token
+            .as_ref()
+            .is_some_and(|token| token.matches("secreto")));
+        assert!(stores["2"].token.is_none());
+    }
+}
@@ -1,36 +1,37 @@
 use crate::product::Product;
-use std::sync::Condvar;
+use std::collections::HashMap;
+use store_protocol::OrderId;
 use tokio::sync::Mutex;
 
 // Representa el estado compartido dentro de una conexión de tienda.
 //
-// Esta estructura almacena una lista de productos a entregar y una variable de condición
-// para la sincronización entre hilos. Se utiliza para gestionar los productos que deben
-// ser procesados por un store específico y para sincronizar la entrega de productos
-// entre diferentes partes del sistema.
+// Esta estructura almacena una lista de productos a entregar y los que el store ya aceptó
+// y está entregando. Se utiliza para gestionar
+// los productos que deben ser procesados por un store específico. La sincronización
+// entre tareas se hace con el `Notify` que acompaña a este estado dentro del `Arc`, con el que
+// se avisa que hay productos nuevos.
 //
 // Atributos:
 // * `products_to_deliver`: Un vector de `Mutex<Product>`. Cada `Mutex` envuelve un `Product`,
 //   permitiendo el acceso seguro y concurrente a cada producto.
-// * `condvar`: Una `Condvar` para la sincronización entre hilos. Se utiliza para notificar
-//   a los hilos cuando hay productos disponibles para procesar.
+// * `products_in_delivery`: Productos que el store aceptó, indexados por el id de pedido que
+//   les asignó, a la espera del aviso de si se entregaron o no.
 pub struct SharedState {
     pub products_to_deliver: Vec<Mutex<Product>>,
-    pub condvar: Condvar,
+    pub products_in_delivery: HashMap<OrderId, Mutex<Product>>,
 }
 
 impl SharedState {
     // Crea una nueva instancia de `SharedState`.
     //
-    // Inicializa el vector de productos a entregar como vacío y crea una nueva
-    // variable de condición para la sincronización.
+    // Inicializa los productos a entregar y en delivery como vacíos.
     //
     // Retorna:
     // Una nueva instancia de `SharedState`.
     pub fn new() -> Self {
         SharedState {
             products_to_deliver: Vec::new(),
-            condvar: Condvar::new(),
+            products_in_delivery: HashMap::new(),
         }
     }
 }
@@ -0,0 +1,24 @@
+use rand::rngs::StdRng;
+pub use sim_core::Clock;
+
+// Secuencia de números aleatorios con la que se reparten los pedidos entre los stores.
+pub const ASSIGNMENT_STREAM: u64 = 1;
+
+// Parámetros que hacen reproducible una ejecución del ecommerce.
+//
+// Atributos:
+// * `seed`: Semilla de los generadores de números aleatorios. Si no se indica, cada ejecución usa
+//   una semilla al azar.
+// * `clock`: Reloj real o virtual.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub seed: Option<u64>,
+    pub clock: Clock,
+}
+
+impl Simulation {
+    // Crea el generador de números aleatorios de la secuencia `stream`.
+    pub fn rng(&self, stream: u64) -> StdRng {
+        sim_core::seeded_rng(self.seed, stream)
+    }
+}
@@ -1,12 +1,98 @@
+use crate::customer_orders::CustomerOrders;
+use crate::metrics::EcommerceMetrics;
+use crate::product::{Item, Product};
 use crate::shared_state::SharedState;
-use async_std::task;
-use serde_json::to_string;
+use crate::simulation::Clock;
+use log::{debug, error, info, warn};
 use std::collections::HashMap;
-use std::io::ErrorKind;
-use std::sync::{Arc, Condvar, Mutex};
+use std::io;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use store_protocol::{
+    connect_handshake, write_message, AuthToken, Envelope, MessageCodec, OrderId, OrderState,
+    RejectReason, RequestId, StoreRequest, StoreResponse, NO_REQUEST,
+};
+use tokio::net::tcp::OwnedReadHalf;
 use tokio::net::TcpStream;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::Notify;
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tokio_stream::StreamExt;
+use tokio_util::codec::FramedRead;
+
+// Resultado de un pedido: el id que le asignó el store y los productos que le faltaron si lo aceptó,
+// o el motivo si lo rechazó.
+type OrderResult = Result<(OrderId, Vec<Item>), RejectReason>;
+
+// Espera antes de volver a intentar la conexión con un store. Como depende de la red, siempre es en
+// tiempo real aunque el ecommerce use un reloj virtual.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+// Configuración de las conexiones con los stores.
+//
+// Los heartbeats y los tiempos máximos se miden siempre en tiempo real, aunque el ecommerce use un
+// reloj virtual, porque dependen de lo que tarda la red y no de la simulación.
+//
+// Atributos:
+// * `window`: Pedidos enviados a un store que pueden estar esperando su respuesta al mismo tiempo.
+// * `heartbeat_interval`: Cada cuánto se le envía un heartbeat al store.
+// * `heartbeat_timeout`: Tiempo sin recibir nada del store (ni siquiera la respuesta a los heartbeats)
+//   tras el cual se da la conexión por muerta. También es lo máximo que se espera el saludo.
+// * `response_timeout`: Tiempo máximo que puede tardar la respuesta a un pedido antes de dar la
+//   conexión por muerta. Se revisa con cada heartbeat.
+// * `token`: Token con el que se responde el desafío del store, si el store pide autenticación.
+#[derive(Debug, Clone)]
+pub struct ConnectionConfig {
+    pub window: usize,
+    pub heartbeat_interval: Duration,
+    pub heartbeat_timeout: Duration,
+    pub response_timeout: Duration,
+    pub token: Option<AuthToken>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        ConnectionConfig {
+            window: 8,
+            heartbeat_interval: Duration::from_secs(5),
+            heartbeat_timeout: Duration::from_secs(15),
+            response_timeout: Duration::from_secs(30),
+            token: None,
+        }
+    }
+}
+
+// Estado del ecommerce que usa la conexión con un store.
+//
+// Atributos:
+// * `shared_state`: Estado compartido de este store (`SharedState`) y el `Notify` con el que se avisa
+//   que hay productos nuevos.
+// * `stores_ids`: Identificadores de todos los stores, en el orden en que se les reasignan productos.
+// * `stores_states`: Estado compartido de cada store, donde se encolan los productos reasignados.
+// * `customer_orders`: Registro de los pedidos de los clientes y los envíos en los que se repartieron.
+// * `metrics`: Métricas del ecommerce, donde se registran las respuestas del store y las reconexiones.
+// * `clock`: Reloj con el que se mide la latencia de los pedidos.
+#[derive(Clone)]
+pub struct ConnectionContext {
+    pub shared_state: Arc<(Mutex<SharedState>, Notify)>,
+    pub stores_ids: Vec<String>,
+    pub stores_states: HashMap<String, Arc<(Mutex<SharedState>, Notify)>>,
+    pub customer_orders: Arc<CustomerOrders>,
+    pub metrics: Arc<EcommerceMetrics>,
+    pub clock: Clock,
+}
+
+// Pedido enviado al store que todavía no tiene respuesta.
+//
+// Atributos:
+// * `product`: El pedido enviado.
+// * `dispatched_at`: Momento en el que se envió, según el reloj de la conexión. Con él se mide la latencia.
+// * `sent_at`: Momento en el que se envió, en tiempo real. Con él se controla `response_timeout`.
+struct InFlight {
+    product: tokio::sync::Mutex<Product>,
+    dispatched_at: Duration,
+    sent_at: Instant,
+}
 
 // Maneja la conexión a un store y procesa los productos asignados.
 //
@@ -17,99 +103,792 @@ use tokio::net::TcpStream;
 // Argumentos:
 // * `id`: El identificador del store, representado por una cadena de texto (`String`).
 // * `address`: La dirección IP del store, también como una cadena de texto.
-// * `shared_state`: Un `Arc` conteniendo un `Mutex` que envuelve el estado compartido (`SharedState`)
-//   y una `Condvar` para la sincronización de hilos.
-// * `stores_ids`: Vector de identificadores de tiendas disponibles.
-// * `stores_states`: Mapa que asocia los identificadores de tiendas con sus respectivos estados compartidos.
+// * `context`: Estado del ecommerce que usa la conexión: la cola de este store y la de los demás, los
+//   pedidos de los clientes, las métricas y el reloj.
+// * `config`: Configuración de la conexión: la cantidad de pedidos sin respuesta que puede tener, cada
+//   cuánto se envían heartbeats, cuánto se espera al store antes de dar la conexión por muerta y el
+//   token con el que autenticarse.
 //
 // La función entra en un bucle infinito, manejando la conexión TCP y procesando productos.
-// Dentro del bucle, se maneja la conexión y, si es exitosa, se procesan los productos asignados
-// al store. Si la conexión falla, se realiza un intento de reconexión después de un período de espera.
+// Dentro del bucle, se maneja la conexión y, si el store acepta el saludo con la versión del
+// protocolo y el token (si lo pide), se procesan los productos asignados al store. Se le envían pedidos sin esperar la
+// respuesta de los anteriores mientras haya menos de `config.window` sin responder, y cada respuesta
+// se empareja por su id con el pedido al que responde. Mientras tanto se le envía un heartbeat cada
+// `config.heartbeat_interval`. Si la conexión falla, o si el store no envía nada durante
+// `config.heartbeat_timeout` o no responde un pedido en `config.response_timeout`, la conexión se da
+// por muerta: los pedidos sin respuesta vuelven a la cola del store y se reconecta.
 // En el procesamiento de productos, si un store no puede manejar un producto (por ejemplo, falta de stock),
-// se busca otro store y se reasigna el producto.
+// se busca otro store y se reasigna el producto. Si el store no conoce alguno de los productos, ese
+// producto se da por perdido sin pedirlo en otro store y el resto se le vuelve a pedir al mismo; si no
+// pudo leer el pedido, el pedido se da por perdido. Lo mismo pasa si el store acepta el producto
+// pero luego avisa que el delivery no lo pudo entregar. Si el pedido admite reservas parciales y el
+// store solo tenía una parte, esa parte queda como un envío de este store y el resto se pide en otro.
 pub async fn handle_store_connection(
     id: String,
     address: String,
-    shared_state: Arc<(Mutex<SharedState>, Condvar)>,
-    stores_ids: Vec<String>,
-    stores_states: HashMap<String, Arc<(Mutex<SharedState>, Condvar)>>,
+    context: ConnectionContext,
+    config: ConnectionConfig,
 ) {
+    let ConnectionContext {
+        shared_state,
+        stores_ids,
+        stores_states,
+        customer_orders,
+        metrics,
+        clock,
+    } = &context;
+    let mut first_attempt = true;
     loop {
+        if !first_attempt {
+            metrics.reconnect(&id);
+        }
+        first_attempt = false;
         match TcpStream::connect(&address).await {
             Ok(mut stream) => {
-                println!(
-                    "[E-COMMERCE] \x1b[32m[Store {}] Conexión exitosa al store: {}\x1b[0m",
-                    id, address
+                let handshake = connect_handshake(&mut stream, config.token.as_ref());
+                match time::timeout(config.heartbeat_timeout, handshake).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(e)) => {
+                        warn!(store = id, error:% = e; "El store no aceptó el saludo");
+                        time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                    Err(_) => {
+                        warn!(store = id; "El store no respondió el saludo");
+                        time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                }
+                info!(
+                    store = id, address = address, window = config.window;
+                    "Conexión exitosa al store"
                 );
+                let (read_half, mut write_half) = stream.into_split();
+                //Las respuestas a los pedidos llegan por este canal, los avisos de delivery
+                //los resuelve directamente la tarea que lee del store
+                let (results_tx, mut results_rx) = mpsc::unbounded_channel();
+                //Cada pedido va con un id nuevo, el store lo repite en la respuesta
+                let mut last_request_id: RequestId = NO_REQUEST;
+                //El aviso de un pedido que se entregó mientras la conexión estaba caída se perdió con
+                //ella, así que le pregunto al store en qué quedaron los que estaba entregando
+                let mut queries = HashMap::new();
+                let mut query_requests = Vec::new();
+                for order_id in orders_in_delivery(shared_state) {
+                    last_request_id += 1;
+                    queries.insert(last_request_id, order_id);
+                    let query = StoreRequest::QueryOrder { order_id };
+                    query_requests.push(Envelope::new(last_request_id, query));
+                }
+                let reader = tokio::spawn(read_store_messages(
+                    id.clone(),
+                    read_half,
+                    results_tx,
+                    queries,
+                    context.clone(),
+                    config.heartbeat_timeout,
+                ));
+                if !query_requests.is_empty() {
+                    info!(
+                        store = id, orders = query_requests.len();
+                        "Consulto los pedidos que estaban en delivery"
+                    );
+                }
+                let sent: io::Result<()> = async {
+                    for query in &query_requests {
+                        write_message(&mut write_half, query).await?;
+                    }
+                    Ok(())
+                }
+                .await;
+                if let Err(e) = sent {
+                    error!(store = id, error:% = e; "Error al consultar los pedidos en delivery");
+                    reader.abort();
+                    continue;
+                }
+                let mut in_flight: HashMap<RequestId, InFlight> = HashMap::new();
+                let mut heartbeat = time::interval(config.heartbeat_interval);
+                heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                let window = config.window;
                 loop {
-                    let product = {
-                        let (lock, cvar) = &*shared_state;
-                        let mut state = lock.lock().unwrap();
-                        while state.products_to_deliver.is_empty() {
-                            state = cvar.wait(state).unwrap();
-                        }
-                        state.products_to_deliver.pop()
-                        //Some(state.products_to_deliver.remove(0))
-                    };
-
-                    if let Some(product) = product {
-                        println!("\n[E-COMMERCE] [Store {}] Processing product {:?}", id,product);
-                        task::sleep(Duration::from_secs(5)).await;
-                        let serialized_product = to_string(&*product.lock().await).unwrap();
-
-                        if let Err(e) = stream
-                            .write_all((serialized_product.clone() + "\n").as_bytes())
-                            .await
-                        {
-                            if e.kind() == ErrorKind::BrokenPipe {
-                                let (lock, _cvar) = &*shared_state;
-                                let mut state = lock.lock().unwrap();
-                                state.products_to_deliver.push(product);
+                    tokio::select! {
+                        product = next_product(shared_state), if in_flight.len() < window => {
+                            debug!(store = id, product:? = product; "Procesando pedido");
+                            last_request_id += 1;
+                            let request_id = last_request_id;
+                            let message = StoreRequest::from(&*product.lock().await);
+                            let request = Envelope::new(request_id, message);
+                            if let Err(e) = write_message(&mut write_half, &request).await {
+                                error!(store = id, error:% = e; "Error al enviar datos");
+                                requeue(shared_state, Some(product), in_flight);
+                                break;
                             }
-                            eprintln!("[E-COMMERCE] \x1b[31m[Store {}] Error al enviar datos: {}\x1b[0m", id, e);
-                            break; // Sale de la función si hay un error
-                        } else {
-                            println!(
-                                "[E-COMMERCE] \x1b[32m[Store {}] Producto enviado exitosamente: {}\x1b[0m",
-                                id, serialized_product
+                            info!(
+                                store = id,
+                                request_id = request_id,
+                                in_flight = in_flight.len() + 1,
+                                request:? = request.message;
+                                "Producto enviado exitosamente"
                             );
+                            let order = InFlight {
+                                product,
+                                dispatched_at: clock.now(),
+                                sent_at: Instant::now(),
+                            };
+                            in_flight.insert(request_id, order);
                         }
-                        let response = stream.read_u8().await;
-                        match response {
-                            Ok(r) => {
-                                if r == 0 {
-                                    println!("[E-COMMERCE] \x1b[34m[Store {}] No se encuentra stock en el local pedido. Pido en otro\x1b[0m", id);
-                                    let id_cloned = id.clone();
-                                    product.lock().await.add_store(id_cloned);
-                                    //Busco un nuevo local
-                                    let product_stores = product.lock().await.get_stores();
-                                    let result = stores_ids.iter().find(|&key| !product_stores.contains(key));
-                                    match result {
-                                        Some(store) => {
-                                            let (shared_state_mutex, cvar) = &**stores_states.get(store).unwrap();
-                                            let mut shared_state = shared_state_mutex.lock().unwrap();
-                                            shared_state.products_to_deliver.push(product);
-                                            cvar.notify_one();
-                                        },
-                                        None => println!("[E-COMMERCE] \x1b[31m[Store {}] No hay mas stores disponibles.\x1b[0m", id),
-                                    }
-                                }
+                        _ = heartbeat.tick() => {
+                            let oldest = in_flight.values().map(|order| order.sent_at).min();
+                            let late = |sent: Instant| sent.elapsed() > config.response_timeout;
+                            if oldest.is_some_and(late) {
+                                warn!(
+                                    store = id, in_flight = in_flight.len();
+                                    "El store no responde los pedidos. Doy la conexión por muerta"
+                                );
+                                requeue(shared_state, None, in_flight);
+                                break;
                             }
-                            Err(e) => {
-                                eprintln!("[E-COMMERCE] \x1b[31m[Store {}] Error al leer la respuesta del store: {}\x1b[0m", id, e);
-                                //return;
+                            let heartbeat = Envelope::new(NO_REQUEST, StoreRequest::Heartbeat);
+                            if let Err(e) = write_message(&mut write_half, &heartbeat).await {
+                                error!(store = id, error:% = e; "Error al enviar el heartbeat");
+                                requeue(shared_state, None, in_flight);
+                                break;
                             }
                         }
+                        answer = results_rx.recv() => {
+                            let Some((request_id, result)) = answer else {
+                                warn!(
+                                    store = id, in_flight = in_flight.len();
+                                    "Se perdió la conexión con el store"
+                                );
+                                requeue(shared_state, None, in_flight);
+                                break;
+                            };
+                            let Some(order) = in_flight.remove(&request_id) else {
+                                warn!(
+                                    store = id, request_id = request_id;
+                                    "Respuesta a un pedido que no se está esperando"
+                                );
+                                continue;
+                            };
+                            let rejection = result.as_ref().err().map(RejectReason::code);
+                            let latency = clock.now() - order.dispatched_at;
+                            metrics.order_answered(&id, rejection, latency);
+                            process_result(
+                                &id,
+                                order.product,
+                                result,
+                                stores_ids,
+                                stores_states,
+                                customer_orders,
+                            )
+                            .await;
+                        }
                     }
                 }
+                //La conexión se dio por muerta: la tarea que lee no puede seguir procesando avisos de
+                //una conexión vieja mientras se abre la nueva
+                reader.abort();
             }
             Err(e) => {
-                eprintln!(
-                    "[E-COMMERCE] \x1b[31m[Store {}] Error al intentar conectar al store: {}\x1b[0m \n",
-                    id, e
+                warn!(store = id, error:% = e; "Error al intentar conectar al store");
+                time::sleep(RECONNECT_DELAY).await; // Esperar antes de intentar nuevamente
+            }
+        }
+    }
+}
+
+// Espera a que haya un producto en la cola del store y lo saca.
+//
+// Antes de esperar el aviso siempre se revisa la cola, así que no se pierde ningún producto aunque
+// la espera se cancele.
+async fn next_product(shared_state: &(Mutex<SharedState>, Notify)) -> tokio::sync::Mutex<Product> {
+    let (lock, notify) = shared_state;
+    loop {
+        if let Some(product) = lock.lock().unwrap().products_to_deliver.pop() {
+            return product;
+        }
+        notify.notified().await;
+    }
+}
+
+// Ids de los pedidos que el store está entregando, de menor a mayor.
+fn orders_in_delivery(shared_state: &(Mutex<SharedState>, Notify)) -> Vec<OrderId> {
+    let (lock, _notify) = shared_state;
+    let mut orders: Vec<OrderId> = lock
+        .lock()
+        .unwrap()
+        .products_in_delivery
+        .keys()
+        .copied()
+        .collect();
+    orders.sort();
+    orders
+}
+
+// Devuelve a la cola del store el producto que no se pudo enviar y los que no tuvieron respuesta,
+// para enviarlos de nuevo cuando se reconecte.
+fn requeue(
+    shared_state: &(Mutex<SharedState>, Notify),
+    product: Option<tokio::sync::Mutex<Product>>,
+    in_flight: HashMap<RequestId, InFlight>,
+) {
+    let (lock, _notify) = shared_state;
+    let mut state = lock.lock().unwrap();
+    state.products_to_deliver.extend(product);
+    //Los reencolo en el orden en que se enviaron
+    let mut pending: Vec<_> = in_flight.into_iter().collect();
+    pending.sort_by_key(|(request_id, _)| std::cmp::Reverse(*request_id));
+    state
+        .products_to_deliver
+        .extend(pending.into_iter().map(|(_, order)| order.product));
+}
+
+// Procesa la respuesta del store `id` a un pedido.
+//
+// Si el store lo tomó, el pedido queda en `products_in_delivery` (y si solo tomó una parte, el resto
+// se pide en otro store). Si lo rechazó, se decide según el motivo si se pide en otro store, se le
+// vuelve a pedir al mismo sin los productos que no conoce o se da por perdido.
+async fn process_result(
+    id: &str,
+    product: tokio::sync::Mutex<Product>,
+    result: OrderResult,
+    stores_ids: &[String],
+    stores_states: &HashMap<String, Arc<(Mutex<SharedState>, Notify)>>,
+    customer_orders: &CustomerOrders,
+) {
+    let shared_state = &stores_states[id];
+    match result {
+        Ok((order_id, remaining)) => {
+            info!(store = id, order_id = order_id; "El store tomó el pedido");
+            //Si el store solo tenía una parte, el resto se pide en otro
+            let rest = if remaining.is_empty() {
+                None
+            } else {
+                Some(product.lock().await.split_off(remaining))
+            };
+            {
+                let shipment = product.lock().await;
+                customer_orders.shipped(
+                    shipment.customer_order,
+                    id,
+                    order_id,
+                    shipment.items.clone(),
+                );
+            }
+            {
+                let (lock, _notify) = &**shared_state;
+                let mut state = lock.lock().unwrap();
+                state.products_in_delivery.insert(order_id, product);
+            }
+            if let Some(rest) = rest {
+                info!(
+                    store = id, order_id = order_id, rest:? = rest.items;
+                    "El store tomó solo una parte del pedido. Pido el resto en otro"
+                );
+                reassign_product(
+                    id,
+                    tokio::sync::Mutex::new(rest),
+                    stores_ids,
+                    stores_states,
+                    customer_orders,
+                )
+                .await;
+            }
+        }
+        Err(RejectReason::NotFound { product_id }) => {
+            warn!(
+                store = id, product_id = product_id;
+                "El store no conoce el producto. No lo pido en otro"
+            );
+            let has_rest = {
+                let mut shipment = product.lock().await;
+                let unknown = shipment.remove_product(product_id);
+                customer_orders.lost(shipment.customer_order, &unknown);
+                !shipment.items.is_empty()
+            };
+            //El resto del pedido puede estar en este store, se lo vuelvo a pedir
+            if has_rest {
+                let (lock, notify) = &**shared_state;
+                let mut state = lock.lock().unwrap();
+                state.products_to_deliver.push(product);
+                notify.notify_one();
+            }
+        }
+        Err(RejectReason::Malformed { error }) => {
+            error!(
+                store = id, error = error;
+                "El store no pudo leer el pedido. Lo doy por perdido"
+            );
+            let product = product.into_inner();
+            customer_orders.lost(product.customer_order, &product.items);
+        }
+        Err(reason) => {
+            info!(store = id, reason:? = reason; "El store no tomó el pedido. Pido en otro");
+            reassign_product(id, product, stores_ids, stores_states, customer_orders).await;
+        }
+    }
+}
+
+// Lee los mensajes que envía el store hasta que se cierra la conexión.
+//
+// Argumentos:
+// * `id`: Identificador del store.
+// * `read_half`: Mitad de lectura de la conexión con el store.
+// * `results`: Canal por el que se envía a `handle_store_connection` el resultado de cada pedido, junto
+//   con el id del pedido al que responde.
+// * `queries`: Consultas por los pedidos que estaban en delivery al abrir la conexión, con el id de
+//   pedido del store por el que pregunta cada una.
+// * `context`: Estado del ecommerce que usa la conexión. Del estado de este store se sacan los
+//   productos que está entregando, en los pedidos de los clientes se anota el resultado de cada envío
+//   y en las métricas los avisos de entrega.
+// * `heartbeat_timeout`: Si el store pasa este tiempo sin enviar nada, se deja de leer y la conexión
+//   se da por muerta.
+//
+// Cuando el store avisa que un delivery falló, el producto se reasigna a otro store. La respuesta a
+// una consulta cuenta como el aviso que se perdió: si el pedido se entregó se lo da por entregado, si
+// terminó sin entregarse (o el store no lo conoce) se reasigna, y si todavía se está entregando se
+// espera el aviso.
+async fn read_store_messages(
+    id: String,
+    read_half: OwnedReadHalf,
+    results: UnboundedSender<(RequestId, OrderResult)>,
+    mut queries: HashMap<RequestId, OrderId>,
+    context: ConnectionContext,
+    heartbeat_timeout: Duration,
+) {
+    let mut frames = FramedRead::new(read_half, MessageCodec::<StoreResponse>::new());
+    loop {
+        let decoded = match time::timeout(heartbeat_timeout, frames.next()).await {
+            Ok(Some(Ok(decoded))) => decoded,
+            Ok(None) => break,
+            Ok(Some(Err(e))) => {
+                error!(store = id, error:% = e; "Error al leer la respuesta del store");
+                break;
+            }
+            Err(_) => {
+                warn!(
+                    store = id, timeout_secs = heartbeat_timeout.as_secs_f64();
+                    "El store no envía nada, ni heartbeats. Doy la conexión por muerta"
                 );
-                task::sleep(Duration::from_secs(10)).await; // Esperar antes de intentar nuevamente
+                break;
+            }
+        };
+        let Envelope {
+            id: request_id,
+            message,
+        } = match decoded {
+            Ok(envelope) => envelope,
+            Err(malformed) => {
+                warn!(store = id, error:% = malformed; "Mensaje inválido del store");
+                continue;
+            }
+        };
+        match message {
+            StoreResponse::OrderResult {
+                accepted,
+                order_id,
+                remaining,
+                reason,
+            } => {
+                let result = match order_id {
+                    Some(order_id) if accepted => Ok((order_id, remaining)),
+                    _ => Err(reason.unwrap_or(RejectReason::Unknown)),
+                };
+                if results.send((request_id, result)).is_err() {
+                    break;
+                }
+            }
+            StoreResponse::Rejected { reason } => {
+                if let Some(order_id) = queries.remove(&request_id) {
+                    warn!(
+                        store = id, order_id = order_id, reason:? = reason;
+                        "El store no respondió la consulta. Espero el aviso del pedido"
+                    );
+                } else if request_id == NO_REQUEST {
+                    warn!(store = id, reason:? = reason; "El store rechazó un mensaje sin id");
+                } else if results.send((request_id, Err(reason))).is_err() {
+                    break;
+                }
+            }
+            StoreResponse::OrderStatus { order } => {
+                let Some(order_id) = queries.remove(&request_id) else {
+                    continue;
+                };
+                match order.map(|order| order.state) {
+                    Some(OrderState::Delivered) => delivered(&id, order_id, &context),
+                    Some(
+                        OrderState::Received
+                        | OrderState::Reserved
+                        | OrderState::OutForDelivery
+                        | OrderState::AwaitingRetry,
+                    ) => debug!(store = id, order_id = order_id; "El pedido sigue en delivery"),
+                    state => {
+                        info!(
+                            store = id, order_id = order_id, state:? = state;
+                            "El pedido terminó sin entregarse mientras no había conexión"
+                        );
+                        delivery_failed(&id, order_id, &context).await;
+                    }
+                }
+            }
+            //El ecommerce no consulta el stock ni cambia el store, así que el resto de las respuestas se ignoran
+            StoreResponse::Stock { .. }
+            | StoreResponse::RestockResult { .. }
+            | StoreResponse::DeliveryWorkers { .. }
+            | StoreResponse::Heartbeat
+            | StoreResponse::Unknown => {}
+            StoreResponse::Delivered { order_id } => delivered(&id, order_id, &context),
+            StoreResponse::DeliveryFailed { order_id } => {
+                delivery_failed(&id, order_id, &context).await
+            }
+        }
+    }
+}
+
+// Anota que el store `id` entregó el pedido `order_id`, si todavía se lo estaba esperando.
+fn delivered(id: &str, order_id: OrderId, context: &ConnectionContext) {
+    context.metrics.delivery_reported(id, true);
+    let (lock, _notify) = &*context.shared_state;
+    let product = lock.lock().unwrap().products_in_delivery.remove(&order_id);
+    if let Some(product) = product {
+        let product = product.into_inner();
+        info!(
+            store = id, order_id = order_id, product:? = product;
+            "Se entregó el pedido"
+        );
+        context
+            .customer_orders
+            .delivered(product.customer_order, id, order_id);
+    }
+}
+
+// Reasigna a otro store el pedido `order_id` que el store `id` no pudo entregar, si todavía se lo
+// estaba esperando.
+async fn delivery_failed(id: &str, order_id: OrderId, context: &ConnectionContext) {
+    context.metrics.delivery_reported(id, false);
+    let product = {
+        let (lock, _notify) = &*context.shared_state;
+        let mut state = lock.lock().unwrap();
+        state.products_in_delivery.remove(&order_id)
+    };
+    if let Some(product) = product {
+        info!(
+            store = id, order_id = order_id;
+            "No se pudo entregar el pedido. Pido en otro"
+        );
+        let customer_orders = &context.customer_orders;
+        customer_orders.failed(product.lock().await.customer_order, id, order_id);
+        reassign_product(
+            id,
+            product,
+            &context.stores_ids,
+            &context.stores_states,
+            customer_orders,
+        )
+        .await;
+    }
+}
+
+// Reasigna un producto que el store `id` no pudo entregar a otro store que todavía no lo haya intentado.
+//
+// Si todos los stores ya lo intentaron, el producto se descarta y se anota como perdido en el
+// pedido del cliente.
+async fn reassign_product(
+    id: &str,
+    product: tokio::sync::Mutex<Product>,
+    stores_ids: &[String],
+    stores_states: &HashMap<String, Arc<(Mutex<SharedState>, Notify)>>,
+    customer_orders: &CustomerOrders,
+) {
+    product.lock().await.add_store(id.to_string());
+    //Busco un nuevo local
+    let product_stores = product.lock().await.get_stores();
+    let result = stores_ids.iter().find(|&key| !product_stores.contains(key));
+    match result {
+        Some(store) => {
+            let (shared_state_mutex, notify) = &**stores_states.get(store).unwrap();
+            let mut shared_state = shared_state_mutex.lock().unwrap();
+            shared_state.products_to_deliver.push(product);
+            notify.notify_one();
+        }
+        None => {
+            warn!(store = id; "No hay mas stores disponibles");
+            let product = product.into_inner();
+            customer_orders.lost(product.customer_order, &product.items);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::Priority;
+    use serde_json::json;
+    use store_protocol::{accept_handshake, read_message, Order, OrderOrigin};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    // Encola `count` pedidos de un producto en un store con id "1".
+    fn store_with_orders(
+        count: usize,
+        customer_orders: &CustomerOrders,
+    ) -> HashMap<String, Arc<(Mutex<SharedState>, Notify)>> {
+        let mut state = SharedState::new();
+        for customer_order in 0..count {
+            let items = vec![Item {
+                id: customer_order as i32,
+                amount: 1,
+            }];
+            customer_orders.register(customer_order, &items);
+            state
+                .products_to_deliver
+                .push(tokio::sync::Mutex::new(Product {
+                    items,
+                    stores: Vec::new(),
+                    priority: Priority::Standard,
+                    allow_partial: false,
+                    customer_order,
+                }));
+        }
+        HashMap::from([(
+            "1".to_string(),
+            Arc::new((Mutex::new(state), Notify::new())),
+        )])
+    }
+
+    // Contexto de la conexión con el store "1", el único.
+    fn context(
+        stores_states: HashMap<String, Arc<(Mutex<SharedState>, Notify)>>,
+        customer_orders: Arc<CustomerOrders>,
+    ) -> ConnectionContext {
+        ConnectionContext {
+            shared_state: stores_states["1"].clone(),
+            stores_ids: vec!["1".to_string()],
+            stores_states,
+            customer_orders,
+            metrics: Arc::new(EcommerceMetrics::new()),
+            clock: Clock::virtual_time(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_the_window_before_any_answer_and_requeues_the_unanswered() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        // El store lee los tres pedidos antes de responder, responde dos en orden inverso y se cae.
+        // Los heartbeats que llegan mientras tanto no los responde
+        let store = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            accept_handshake(&mut stream, None).await.unwrap();
+            let mut ids = Vec::new();
+            while ids.len() < 3 {
+                let request: Envelope<serde_json::Value> = read_message(&mut stream).await.unwrap();
+                if request.message["type"] == "cart" {
+                    ids.push(request.id);
+                }
+            }
+            for &id in ids.iter().rev().take(2) {
+                let response = json!({
+                    "type": "order_result",
+                    "accepted": true,
+                    "order_id": 100 + id,
+                    "remaining": [],
+                });
+                write_message(&mut stream, &Envelope::new(id, response))
+                    .await
+                    .unwrap();
+            }
+            //Cierra su lado y lee lo que quede para no cortar la conexión con datos sin leer
+            stream.shutdown().await.unwrap();
+            while read_message::<_, serde_json::Value>(&mut stream)
+                .await
+                .is_ok()
+            {}
+            ids
+        });
+
+        let customer_orders = Arc::new(CustomerOrders::new());
+        let stores_states = store_with_orders(3, &customer_orders);
+        let shared_state = stores_states["1"].clone();
+        let connection = tokio::spawn(handle_store_connection(
+            "1".to_string(),
+            address,
+            context(stores_states, customer_orders),
+            ConnectionConfig {
+                window: 3,
+                ..ConnectionConfig::default()
+            },
+        ));
+
+        assert_eq!(store.await.unwrap(), vec![1, 2, 3]);
+        let (delivering, queued) = loop {
+            {
+                let state = shared_state.0.lock().unwrap();
+                if state.products_in_delivery.len() + state.products_to_deliver.len() == 3 {
+                    let mut delivering: Vec<OrderId> =
+                        state.products_in_delivery.keys().copied().collect();
+                    delivering.sort();
+                    break (delivering, state.products_to_deliver.len());
+                }
+            }
+            tokio::task::yield_now().await;
+        };
+        connection.abort();
+        assert_eq!(delivering, vec![102, 103]);
+        assert_eq!(queued, 1);
+    }
+
+    #[tokio::test]
+    async fn a_store_that_hangs_is_declared_dead_and_its_orders_requeued() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (received_tx, received_rx) = tokio::sync::oneshot::channel();
+        // El store lee el pedido y después no responde nada, ni los heartbeats, sin cerrar la conexión
+        let store = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            accept_handshake(&mut stream, None).await.unwrap();
+            loop {
+                let request: Envelope<serde_json::Value> = read_message(&mut stream).await.unwrap();
+                if request.message["type"] == "cart" {
+                    break;
+                }
+            }
+            received_tx.send(()).unwrap();
+            std::future::pending::<()>().await;
+        });
+
+        let customer_orders = Arc::new(CustomerOrders::new());
+        let stores_states = store_with_orders(1, &customer_orders);
+        let shared_state = stores_states["1"].clone();
+        let connection = tokio::spawn(handle_store_connection(
+            "1".to_string(),
+            address,
+            context(stores_states, customer_orders),
+            ConnectionConfig {
+                window: 1,
+                heartbeat_interval: Duration::from_millis(50),
+                heartbeat_timeout: Duration::from_millis(300),
+                response_timeout: Duration::from_secs(60),
+                ..ConnectionConfig::default()
+            },
+        ));
+
+        received_rx.await.unwrap();
+        let started = Instant::now();
+        while shared_state
+            .0
+            .lock()
+            .unwrap()
+            .products_to_deliver
+            .is_empty()
+        {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "El pedido no volvió a la cola"
+            );
+            time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(250));
+        assert!(shared_state
+            .0
+            .lock()
+            .unwrap()
+            .products_in_delivery
+            .is_empty());
+        connection.abort();
+        store.abort();
+    }
+
+    #[tokio::test]
+    async fn orders_in_delivery_are_queried_after_reconnecting() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        // El store acepta el primer pedido, no responde el segundo y sigue respondiendo los heartbeats,
+        // así que la conexión solo se da por muerta por el pedido sin respuesta. Cuando el ecommerce
+        // cierra su lado, el store avisa la entrega del primero por la conexión vieja y ese aviso se
+        // pierde. Por la conexión nueva responde la consulta del pedido y rechaza el resto.
+        let (queried_tx, queried_rx) = tokio::sync::oneshot::channel();
+        let store = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            accept_handshake(&mut stream, None).await.unwrap();
+            let (mut read_half, mut write_half) = stream.into_split();
+            while let Ok(request) = read_message::<_, StoreRequest>(&mut read_half).await {
+                let response = match request.message {
+                    StoreRequest::Heartbeat => StoreResponse::Heartbeat,
+                    StoreRequest::Cart(_) if request.id == 1 => {
+                        StoreResponse::accepted_order(100, Vec::new())
+                    }
+                    _ => continue,
+                };
+                write_message(&mut write_half, &Envelope::new(request.id, response))
+                    .await
+                    .unwrap();
+            }
+            let delivered = StoreResponse::Delivered { order_id: 100 };
+            let _ = write_message(&mut write_half, &Envelope::new(NO_REQUEST, delivered)).await;
+
+            let (mut stream, _) = listener.accept().await.unwrap();
+            accept_handshake(&mut stream, None).await.unwrap();
+            let mut queried_tx = Some(queried_tx);
+            while let Ok(request) = read_message::<_, StoreRequest>(&mut stream).await {
+                let response = match request.message {
+                    StoreRequest::Heartbeat => StoreResponse::Heartbeat,
+                    StoreRequest::QueryOrder { order_id } => {
+                        if let Some(queried_tx) = queried_tx.take() {
+                            queried_tx.send(order_id).unwrap();
+                        }
+                        let order = Order {
+                            id: order_id,
+                            items: vec![Item { id: 0, amount: 1 }],
+                            origin: OrderOrigin::Ecommerce,
+                            state: OrderState::Delivered,
+                        };
+                        StoreResponse::OrderStatus { order: Some(order) }
+                    }
+                    _ => StoreResponse::rejected_order(RejectReason::InsufficientStock {
+                        product_id: 1,
+                        available: 0,
+                    }),
+                };
+                write_message(&mut stream, &Envelope::new(request.id, response))
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let customer_orders = Arc::new(CustomerOrders::new());
+        let stores_states = store_with_orders(2, &customer_orders);
+        let shared_state = stores_states["1"].clone();
+        let connection = tokio::spawn(handle_store_connection(
+            "1".to_string(),
+            address,
+            context(stores_states, customer_orders),
+            ConnectionConfig {
+                window: 2,
+                heartbeat_interval: Duration::from_millis(50),
+                heartbeat_timeout: Duration::from_secs(5),
+                response_timeout: Duration::from_millis(200),
+                ..ConnectionConfig::default()
+            },
+        ));
+
+        let queried = time::timeout(Duration::from_secs(5), queried_rx).await;
+        assert_eq!(queried.unwrap().unwrap(), 100);
+        let started = Instant::now();
+        loop {
+            {
+                let state = shared_state.0.lock().unwrap();
+                if state.products_in_delivery.is_empty() && state.products_to_deliver.is_empty() {
+                    break;
+                }
             }
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "El pedido 100 nunca se resolvió"
+            );
+            time::sleep(Duration::from_millis(20)).await;
         }
+        connection.abort();
+        store.abort();
     }
 }
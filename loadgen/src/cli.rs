@@ -0,0 +1,160 @@
+use crate::order_mix::OrderMix;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use store_protocol::AuthToken;
+
+// Mensaje de uso del binario.
+pub const USAGE: &str = "Uso: cargo run -- <host:puerto>[,<host:puerto>...] [opciones]
+Opciones:
+  --connections <cantidad>    Conexiones en total, repartidas entre los stores (10)
+  --rate <pedidos/s>          Pedidos por segundo en total, 0 para enviar sin pausa (100)
+  --duration <segundos>       Tiempo durante el que se envían pedidos (10)
+  --in-flight <cantidad>      Pedidos sin responder que puede tener cada conexión (1)
+  --products <cantidad>       Los pedidos usan los productos 0 a cantidad - 1 (10)
+  --max-items <cantidad>      Productos distintos por pedido, entre 1 y este valor (1)
+  --max-amount <cantidad>     Unidades de cada producto, entre 1 y este valor (3)
+  --express <p>               Proporción de pedidos express (0.2)
+  --partial <p>               Proporción de pedidos que admiten reservas parciales (0)
+  --seed <número>             Semilla para repetir la misma secuencia de pedidos
+  --token-file <archivo>      Token con el que autenticarse ante los stores";
+
+// Argumentos de línea de comandos del generador de carga.
+//
+// Atributos:
+// * `stores`: Direcciones de los stores a los que se envían pedidos.
+// * `connections`: Cantidad total de conexiones. Se reparten entre los stores en orden.
+// * `rate`: Pedidos por segundo entre todas las conexiones, o `None` para no esperar entre pedidos.
+// * `duration`: Tiempo durante el que se envían pedidos.
+// * `in_flight`: Pedidos sin responder que puede tener cada conexión.
+// * `mix`: Cómo se arman los pedidos.
+// * `seed`: Semilla de los generadores de pedidos, si se quiere repetir la misma secuencia.
+// * `token`: Token con el que se responde el desafío de los stores que piden autenticación. Se usa
+//   el mismo para todos los stores.
+#[derive(Debug)]
+pub struct LoadArgs {
+    pub stores: Vec<String>,
+    pub connections: usize,
+    pub rate: Option<f64>,
+    pub duration: Duration,
+    pub in_flight: usize,
+    pub mix: OrderMix,
+    pub seed: Option<u64>,
+    pub token: Option<AuthToken>,
+}
+
+// Parsea los argumentos recibidos (sin incluir el nombre del programa).
+//
+// Retorna:
+// Los argumentos parseados o un mensaje de error si falta alguno o hay un valor inválido.
+pub fn parse_args(args: &[String]) -> Result<LoadArgs, String> {
+    let mut stores = None;
+    let mut connections = 10;
+    let mut rate = 100.0;
+    let mut duration = 10.0;
+    let mut in_flight = 1;
+    let mut mix = OrderMix::default();
+    let mut seed = None;
+    let mut token = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--connections" => connections = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--rate" => rate = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--duration" => duration = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--in-flight" => in_flight = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--products" => mix.products = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--max-items" => mix.max_items = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--max-amount" => mix.max_amount = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--express" => mix.express = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--partial" => mix.partial = parse_value(next_value(&mut iter, arg)?, arg)?,
+            "--seed" => seed = Some(parse_value(next_value(&mut iter, arg)?, arg)?),
+            "--token-file" => {
+                let token_file = next_value(&mut iter, arg)?;
+                token =
+                    Some(AuthToken::read(Path::new(token_file)).map_err(|e| {
+                        format!("No se pudo leer el token de {}: {}", token_file, e)
+                    })?)
+            }
+            option if option.starts_with("--") => {
+                return Err(format!("Opción desconocida: {}", option))
+            }
+            value if stores.is_none() => {
+                stores = Some(value.split(',').map(|store| store.to_string()).collect())
+            }
+            value => return Err(format!("Argumento inesperado: {}", value)),
+        }
+    }
+
+    let Some(stores) = stores else {
+        return Err("Se espera la dirección de al menos un store".to_string());
+    };
+    if connections == 0 || in_flight == 0 {
+        return Err("Tiene que haber al menos una conexión y un pedido en vuelo".to_string());
+    }
+    if rate < 0.0 || duration <= 0.0 {
+        return Err(
+            "La tasa no puede ser negativa y la duración tiene que ser positiva".to_string(),
+        );
+    }
+    mix.validate()?;
+
+    Ok(LoadArgs {
+        stores,
+        connections,
+        rate: if rate > 0.0 { Some(rate) } else { None },
+        duration: Duration::from_secs_f64(duration),
+        in_flight,
+        mix,
+        seed,
+        token,
+    })
+}
+
+// Obtiene el valor que acompaña a una opción.
+fn next_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<&'a String, String> {
+    iter.next()
+        .ok_or_else(|| format!("Falta el valor de la opción {}", option))
+}
+
+// Parsea el valor numérico de una opción.
+fn parse_value<T: FromStr>(value: &str, option: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Valor inválido para la opción {}: {}", option, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_stores_and_options() {
+        let parsed = parse_args(&args(&[
+            "127.0.0.1:8080,127.0.0.1:8081",
+            "--connections",
+            "4",
+            "--rate",
+            "0",
+            "--max-items",
+            "3",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.stores, vec!["127.0.0.1:8080", "127.0.0.1:8081"]);
+        assert_eq!(parsed.connections, 4);
+        assert_eq!(parsed.rate, None);
+        assert_eq!(parsed.mix.max_items, 3);
+        assert_eq!(parsed.in_flight, 1);
+
+        assert!(parse_args(&args(&["--rate", "10"])).is_err());
+        assert!(parse_args(&args(&["127.0.0.1:8080", "--express", "2"])).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::order_mix::OrderMix;
+use crate::stats::Stats;
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use std::time::Duration;
+use store_protocol::{
+    connect_handshake, write_message, AuthToken, Decoded, Envelope, MessageCodec, RequestId,
+    StoreRequest, StoreResponse, NO_REQUEST,
+};
+use tokio::net::TcpStream;
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+use tokio_stream::StreamExt;
+use tokio_util::codec::FramedRead;
+
+// Cuánto se esperan las respuestas pendientes después de que se dejan de enviar pedidos.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Cada cuánto se envía un heartbeat, para que el store no cierre la conexión si hay pocos pedidos.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Configuración de una conexión.
+//
+// Atributos:
+// * `address`: Dirección del store.
+// * `first_send`: Cuándo se envía el primer pedido. Las conexiones arrancan escalonadas para no
+//   enviar todas a la vez.
+// * `period`: Tiempo entre pedidos de esta conexión, o `None` para enviar sin pausa.
+// * `in_flight`: Pedidos sin responder que puede tener la conexión.
+// * `deadline`: Hasta cuándo se envían pedidos.
+// * `token`: Token con el que autenticarse, si el store lo pide.
+pub struct ConnectionConfig {
+    pub address: String,
+    pub first_send: Instant,
+    pub period: Option<Duration>,
+    pub in_flight: usize,
+    pub deadline: Instant,
+    pub token: Option<AuthToken>,
+}
+
+// Abre una conexión con un store y le envía pedidos hasta `deadline`.
+//
+// Cada pedido se sortea con `mix` y `rng` y se envía en un frame con un id nuevo. No se envía un
+// pedido nuevo mientras haya `in_flight` sin responder. Cada respuesta trae el id del pedido al que
+// responde, con el que se calcula su latencia aunque lleguen en otro orden. Después del último envío
+// se esperan las respuestas pendientes hasta `DRAIN_TIMEOUT`. Mientras la conexión está abierta se
+// envía un heartbeat cada `HEARTBEAT_INTERVAL`.
+//
+// Retorna:
+// Los resultados de la conexión.
+pub async fn run_connection(config: ConnectionConfig, mix: OrderMix, mut rng: StdRng) -> Stats {
+    let mut stats = Stats::default();
+    let mut stream = match TcpStream::connect(&config.address).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            eprintln!("No se pudo conectar a {}: {}", config.address, e);
+            stats.errors += 1;
+            return stats;
+        }
+    };
+    if let Err(e) = connect_handshake(&mut stream, config.token.as_ref()).await {
+        eprintln!("{} no aceptó el saludo: {}", config.address, e);
+        stats.errors += 1;
+        return stats;
+    }
+    let (read_half, mut write_half) = stream.into_split();
+    let mut frames = FramedRead::new(read_half, MessageCodec::<StoreResponse>::new());
+    let mut pending: HashMap<RequestId, Instant> = HashMap::new();
+    let mut last_request_id = NO_REQUEST;
+    let mut ticker = config.period.map(|period| {
+        let mut ticker = time::interval_at(config.first_send, period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticker
+    });
+    let drain_deadline = config.deadline + DRAIN_TIMEOUT;
+    let mut heartbeat = time::interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let sending = Instant::now() < config.deadline;
+        if !sending && pending.is_empty() {
+            break;
+        }
+        let wake_up = if sending {
+            config.deadline
+        } else {
+            drain_deadline
+        };
+        tokio::select! {
+            _ = next_tick(&mut ticker), if sending && pending.len() < config.in_flight => {
+                last_request_id += 1;
+                let request = Envelope::new(last_request_id, StoreRequest::Cart(mix.generate(&mut rng)));
+                pending.insert(last_request_id, Instant::now());
+                stats.sent += 1;
+                if let Err(e) = write_message(&mut write_half, &request).await {
+                    eprintln!("Error al enviar un pedido a {}: {}", config.address, e);
+                    stats.errors += 1;
+                    break;
+                }
+            }
+            frame = frames.next() => match frame {
+                Some(Ok(decoded)) => record_response(decoded, &mut pending, &mut stats),
+                None => {
+                    if !pending.is_empty() {
+                        eprintln!("{} cerró la conexión con pedidos pendientes", config.address);
+                        stats.errors += 1;
+                    }
+                    break;
+                }
+                Some(Err(e)) => {
+                    eprintln!("Error al leer de {}: {}", config.address, e);
+                    stats.errors += 1;
+                    break;
+                }
+            },
+            _ = heartbeat.tick() => {
+                let heartbeat = Envelope::new(NO_REQUEST, StoreRequest::Heartbeat);
+                if let Err(e) = write_message(&mut write_half, &heartbeat).await {
+                    eprintln!("Error al enviar un heartbeat a {}: {}", config.address, e);
+                    stats.errors += 1;
+                    break;
+                }
+            }
+            _ = time::sleep_until(wake_up) => {
+                if !sending {
+                    break;
+                }
+            }
+        }
+    }
+    stats
+}
+
+// Espera el próximo envío. Sin período el envío es inmediato.
+async fn next_tick(ticker: &mut Option<Interval>) {
+    if let Some(ticker) = ticker {
+        ticker.tick().await;
+    }
+}
+
+// Anota un mensaje recibido del store: si es la respuesta a un pedido, su resultado y su latencia.
+fn record_response(
+    decoded: Decoded<StoreResponse>,
+    pending: &mut HashMap<RequestId, Instant>,
+    stats: &mut Stats,
+) {
+    match decoded.map(|envelope| (envelope.id, envelope.message)) {
+        Ok((
+            id,
+            StoreResponse::OrderResult {
+                accepted,
+                remaining,
+                reason,
+                ..
+            },
+        )) => {
+            if let Some(sent_at) = pending.remove(&id) {
+                stats.latencies.push(sent_at.elapsed());
+            }
+            if !accepted {
+                stats.reject(reason.as_ref().map_or("unknown", |reason| reason.code()));
+            } else if remaining.is_empty() {
+                stats.accepted += 1;
+            } else {
+                stats.partial += 1;
+            }
+        }
+        Ok((id, StoreResponse::Rejected { reason })) => match pending.remove(&id) {
+            Some(sent_at) => {
+                stats.latencies.push(sent_at.elapsed());
+                stats.reject(reason.code());
+            }
+            None => {
+                eprintln!(
+                    "El store rechazó un mensaje que no es un pedido: {}",
+                    reason.code()
+                );
+                stats.errors += 1;
+            }
+        },
+        Ok((_, StoreResponse::Delivered { .. }))
+        | Ok((_, StoreResponse::DeliveryFailed { .. })) => {
+            stats.deliveries += 1;
+        }
+        //El generador de carga solo envía pedidos, el resto de las respuestas se ignoran
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Mensaje inválido del store: {}", e);
+            stats.errors += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use serde_json::{json, Value};
+    use store_protocol::accept_handshake;
+    use tokio::net::TcpListener;
+
+    // Store de prueba: pide autenticación, acepta los pedidos impares, rechaza los pares y responde
+    // los heartbeats.
+    async fn fake_store(listener: TcpListener) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        accept_handshake(&mut stream, Some(&AuthToken::new("secreto")))
+            .await
+            .unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut frames = FramedRead::new(read_half, MessageCodec::<Value>::new());
+        while let Some(Ok(Ok(request))) = frames.next().await {
+            let response = if request.message["type"] == "heartbeat" {
+                json!({"type": "heartbeat"})
+            } else {
+                json!({
+                    "type": "order_result",
+                    "accepted": request.id % 2 == 1,
+                    "order_id": request.id,
+                })
+            };
+            write_message(&mut write_half, &Envelope::new(request.id, response))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_orders_and_records_each_answer() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(fake_store(listener));
+
+        let now = Instant::now();
+        let config = ConnectionConfig {
+            address,
+            first_send: now,
+            period: Some(Duration::from_millis(10)),
+            in_flight: 1,
+            deadline: now + Duration::from_millis(100),
+            token: Some(AuthToken::new("secreto")),
+        };
+        let stats = run_connection(config, OrderMix::default(), StdRng::seed_from_u64(1)).await;
+        assert!(stats.sent >= 5);
+        assert_eq!(stats.answered(), stats.sent);
+        assert_eq!(stats.accepted, stats.sent.div_ceil(2));
+        assert_eq!(stats.latencies.len(), stats.sent);
+        assert_eq!(stats.errors, 0);
+    }
+}
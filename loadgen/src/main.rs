@@ -0,0 +1,88 @@
+use cli::{parse_args, LoadArgs, USAGE};
+use connection::{run_connection, ConnectionConfig};
+use stats::Stats;
+use std::collections::BTreeMap;
+use std::env;
+use tokio::time::Instant;
+
+mod cli;
+mod connection;
+mod order_mix;
+mod stats;
+
+// Generador de carga para los stores.
+//
+// Abre `--connections` conexiones TCP repartidas entre los stores indicados y por cada una envía
+// pedidos con el mismo formato que el ecommerce, a la tasa y con la mezcla de productos y prioridades
+// que se configuren. Al terminar muestra, en total y por store, el throughput, los percentiles de la
+// latencia y cuántos pedidos se aceptaron y rechazaron.
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", USAGE);
+            return;
+        }
+    };
+
+    println!(
+        "Enviando pedidos a {} por {} conexiones durante {:.1} s",
+        args.stores.join(", "),
+        args.connections,
+        args.duration.as_secs_f64()
+    );
+    let started = Instant::now();
+    let by_store = run_load(&args, started).await;
+    let elapsed = started.elapsed();
+
+    let mut total = Stats::default();
+    for (store, stats) in &by_store {
+        println!("Store {}:\n{}", store, stats.summary(elapsed));
+        total.merge(stats.clone());
+    }
+    if by_store.len() > 1 {
+        println!("Total:\n{}", total.summary(elapsed));
+    }
+}
+
+// Lanza todas las conexiones y espera a que terminen.
+//
+// La tasa total se reparte en partes iguales entre las conexiones, y el primer envío de cada una se
+// corre una fracción del período para que no envíen todas juntas.
+//
+// Retorna:
+// Los resultados sumados de las conexiones de cada store.
+async fn run_load(args: &LoadArgs, started: Instant) -> BTreeMap<String, Stats> {
+    let period = args
+        .rate
+        .map(|rate| std::time::Duration::from_secs_f64(args.connections as f64 / rate));
+    let deadline = started + args.duration;
+
+    let mut connections = Vec::new();
+    for index in 0..args.connections {
+        let address = args.stores[index % args.stores.len()].clone();
+        let offset = period.unwrap_or_default() * index as u32 / args.connections as u32;
+        let config = ConnectionConfig {
+            address: address.clone(),
+            first_send: started + offset,
+            period,
+            in_flight: args.in_flight,
+            deadline,
+            token: args.token.clone(),
+        };
+        let rng = sim_core::seeded_rng(args.seed, index as u64);
+        let handle = tokio::spawn(run_connection(config, args.mix.clone(), rng));
+        connections.push((address, handle));
+    }
+
+    let mut by_store: BTreeMap<String, Stats> = BTreeMap::new();
+    for (address, handle) in connections {
+        if let Ok(stats) = handle.await {
+            by_store.entry(address).or_default().merge(stats);
+        }
+    }
+    by_store
+}
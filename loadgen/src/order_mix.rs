@@ -0,0 +1,100 @@
+use rand::seq::index;
+use rand::Rng;
+use store_protocol::{CartRequest, Item, Priority};
+
+// Cómo se arman los pedidos que se envían.
+//
+// Atributos:
+// * `products`: Cantidad de productos distintos, los pedidos usan los ids `0..products`.
+// * `max_items`: Máximo de productos distintos por pedido.
+// * `max_amount`: Máximo de unidades de cada producto.
+// * `express`: Proporción de pedidos express.
+// * `partial`: Proporción de pedidos que admiten reservas parciales.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderMix {
+    pub products: usize,
+    pub max_items: usize,
+    pub max_amount: i32,
+    pub express: f64,
+    pub partial: f64,
+}
+
+impl Default for OrderMix {
+    fn default() -> Self {
+        OrderMix {
+            products: 10,
+            max_items: 1,
+            max_amount: 3,
+            express: 0.2,
+            partial: 0.0,
+        }
+    }
+}
+
+impl OrderMix {
+    // Verifica que los valores estén dentro de rango.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.products == 0 || self.max_items == 0 || self.max_amount <= 0 {
+            return Err(
+                "Los pedidos tienen que tener al menos un producto y una unidad".to_string(),
+            );
+        }
+        if !(0.0..=1.0).contains(&self.express) || !(0.0..=1.0).contains(&self.partial) {
+            return Err("Las proporciones de pedidos tienen que estar entre 0 y 1".to_string());
+        }
+        Ok(())
+    }
+
+    // Sortea un pedido: entre 1 y `max_items` productos distintos con entre 1 y `max_amount`
+    // unidades cada uno. Es el mismo carrito que envía el ecommerce, para que el store lo reciba como
+    // cualquier otro pedido.
+    pub fn generate<R: Rng>(&self, rng: &mut R) -> CartRequest {
+        let count = rng.gen_range(1..=self.max_items.min(self.products));
+        let items = index::sample(rng, self.products, count)
+            .into_iter()
+            .map(|id| Item {
+                id: id as i32,
+                amount: rng.gen_range(1..=self.max_amount),
+            })
+            .collect();
+        let priority = if rng.gen_bool(self.express) {
+            Priority::Express
+        } else {
+            Priority::Standard
+        };
+        CartRequest {
+            items,
+            priority,
+            allow_partial: rng.gen_bool(self.partial),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn generated_orders_respect_the_mix() {
+        let mix = OrderMix {
+            products: 4,
+            max_items: 3,
+            max_amount: 2,
+            express: 1.0,
+            partial: 0.0,
+        };
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..50 {
+            let order = mix.generate(&mut rng);
+            assert!((1..=3).contains(&order.items.len()));
+            assert!(order
+                .items
+                .iter()
+                .all(|item| item.id < 4 && item.amount <= 2));
+            assert_eq!(order.priority, Priority::Express);
+            assert!(!order.allow_partial);
+        }
+    }
+}
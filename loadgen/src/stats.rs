@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+// Resultados de una conexión, o de varias sumadas.
+//
+// Atributos:
+// * `sent`: Pedidos enviados.
+// * `accepted`: Pedidos que el store reservó completos.
+// * `partial`: Pedidos que el store reservó en parte.
+// * `rejected`: Pedidos que el store rechazó.
+// * `rejected_by_reason`: Pedidos rechazados por cada motivo que indicó el store.
+// * `deliveries`: Avisos de entrega (exitosa o no) que llegaron por la conexión.
+// * `latencies`: Tiempo entre el envío de cada pedido y su respuesta.
+// * `errors`: Errores de conexión, de escritura o de lectura.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    pub sent: usize,
+    pub accepted: usize,
+    pub partial: usize,
+    pub rejected: usize,
+    pub rejected_by_reason: BTreeMap<String, usize>,
+    pub deliveries: usize,
+    pub latencies: Vec<Duration>,
+    pub errors: usize,
+}
+
+impl Stats {
+    // Suma los resultados de otra conexión.
+    pub fn merge(&mut self, other: Stats) {
+        self.sent += other.sent;
+        self.accepted += other.accepted;
+        self.partial += other.partial;
+        self.rejected += other.rejected;
+        for (reason, count) in other.rejected_by_reason {
+            *self.rejected_by_reason.entry(reason).or_default() += count;
+        }
+        self.deliveries += other.deliveries;
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+    }
+
+    // Anota un pedido rechazado por `reason`.
+    pub fn reject(&mut self, reason: &str) {
+        self.rejected += 1;
+        *self
+            .rejected_by_reason
+            .entry(reason.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn answered(&self) -> usize {
+        self.accepted + self.partial + self.rejected
+    }
+
+    // Resumen de los resultados en un período de `elapsed`.
+    pub fn summary(&self, elapsed: Duration) -> Summary {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        let answered = self.answered();
+        Summary {
+            sent: self.sent,
+            answered,
+            unanswered: self.sent.saturating_sub(answered),
+            throughput: answered as f64 / elapsed.as_secs_f64(),
+            accepted: self.accepted,
+            partial: self.partial,
+            rejected: self.rejected,
+            rejected_by_reason: self.rejected_by_reason.clone(),
+            acceptance: if answered == 0 {
+                0.0
+            } else {
+                (self.accepted + self.partial) as f64 / answered as f64
+            },
+            deliveries: self.deliveries,
+            errors: self.errors,
+            p50: percentile(&latencies, 0.5),
+            p90: percentile(&latencies, 0.9),
+            p99: percentile(&latencies, 0.99),
+            max: latencies.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+// Resumen de una corrida que se muestra al terminar.
+//
+// Atributos:
+// * `throughput`: Pedidos respondidos por segundo.
+// * `acceptance`: Proporción de los pedidos respondidos que se reservaron, total o parcialmente.
+// * `p50`, `p90`, `p99`, `max`: Percentiles de la latencia.
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub sent: usize,
+    pub answered: usize,
+    pub unanswered: usize,
+    pub throughput: f64,
+    pub accepted: usize,
+    pub partial: usize,
+    pub rejected: usize,
+    pub rejected_by_reason: BTreeMap<String, usize>,
+    pub acceptance: f64,
+    pub deliveries: usize,
+    pub errors: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  Pedidos: {} enviados, {} respondidos, {} sin respuesta",
+            self.sent, self.answered, self.unanswered
+        )?;
+        writeln!(f, "  Throughput: {:.1} pedidos/s", self.throughput)?;
+        writeln!(
+            f,
+            "  Aceptados: {} ({} parciales), rechazados: {}, tasa de aceptación: {:.1}%",
+            self.accepted + self.partial,
+            self.partial,
+            self.rejected,
+            self.acceptance * 100.0
+        )?;
+        if !self.rejected_by_reason.is_empty() {
+            let reasons: Vec<String> = self
+                .rejected_by_reason
+                .iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect();
+            writeln!(f, "  Motivos de rechazo: {}", reasons.join(", "))?;
+        }
+        writeln!(
+            f,
+            "  Latencia: p50 {}, p90 {}, p99 {}, máx {}",
+            millis(self.p50),
+            millis(self.p90),
+            millis(self.p99),
+            millis(self.max)
+        )?;
+        write!(
+            f,
+            "  Avisos de entrega: {}, errores: {}",
+            self.deliveries, self.errors
+        )
+    }
+}
+
+// Percentil `p` (entre 0 y 1) de latencias ordenadas, por el método del rango más cercano.
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.2} ms", duration.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_throughput_acceptance_and_percentiles() {
+        let mut stats = Stats {
+            sent: 60,
+            accepted: 40,
+            latencies: (1..=45).map(Duration::from_millis).collect(),
+            ..Stats::default()
+        };
+        (0..10).for_each(|_| stats.reject("insufficient_stock"));
+        let mut other = Stats {
+            sent: 40,
+            partial: 10,
+            latencies: (46..=90).map(Duration::from_millis).collect(),
+            ..Stats::default()
+        };
+        (0..25).for_each(|_| other.reject("insufficient_stock"));
+        (0..5).for_each(|_| other.reject("overloaded"));
+        stats.merge(other);
+
+        let summary = stats.summary(Duration::from_secs(2));
+        assert_eq!(summary.answered, 90);
+        assert_eq!(summary.unanswered, 10);
+        assert_eq!(summary.throughput, 45.0);
+        assert_eq!(summary.acceptance, 50.0 / 90.0);
+        assert_eq!(summary.p50, Duration::from_millis(45));
+        assert_eq!(summary.p90, Duration::from_millis(81));
+        assert_eq!(summary.p99, Duration::from_millis(90));
+        assert_eq!(summary.max, Duration::from_millis(90));
+        assert_eq!(summary.rejected, 40);
+        assert!(summary
+            .to_string()
+            .contains("Motivos de rechazo: 35 insufficient_stock, 5 overloaded"));
+    }
+}
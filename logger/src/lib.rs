@@ -0,0 +1,232 @@
+use log::kv::{self, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde_json::{Map, Number};
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Logger que comparten el store y el ecommerce, para que los dos escriban los registros con el mismo
+// formato y se puedan filtrar con las mismas herramientas.
+
+// Formato en el que se escriben los logs.
+//
+// Variantes:
+// * `Text`: Una línea legible por registro, con colores si la salida es una terminal.
+// * `Json`: Un objeto JSON por línea con el nivel, el módulo, el mensaje y los campos del registro.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Formato de log inválido: {} (text o json)", s)),
+        }
+    }
+}
+
+// Logger del binario: escribe en stderr cada registro de `log` con sus campos estructurados.
+//
+// Atributos:
+// * `format`: Formato de salida.
+// * `color`: Si se colorea el nivel, solo cuando stderr es una terminal y el formato es texto.
+// * `level`: Nivel mínimo de los registros que se escriben.
+// * `fields`: Campos que se agregan a todos los registros, por ejemplo el id del store.
+pub struct Logger {
+    format: LogFormat,
+    color: bool,
+    level: LevelFilter,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Logger {
+    pub fn new(format: LogFormat, level: LevelFilter, fields: Vec<(&'static str, String)>) -> Self {
+        Logger {
+            format,
+            color: format == LogFormat::Text && io::stderr().is_terminal(),
+            level,
+            fields,
+        }
+    }
+
+    // Instala el logger como el logger global de `log`.
+    pub fn init(self) -> Result<(), SetLoggerError> {
+        log::set_max_level(self.level);
+        log::set_boxed_logger(Box::new(self))
+    }
+
+    // Arma la línea que se escribe para un registro, sin el salto de línea.
+    fn format_record(&self, record: &Record) -> String {
+        let mut fields = Fields(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+        let fields = self
+            .fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), FieldValue::Text(value.clone())))
+            .chain(fields.0);
+        match self.format {
+            LogFormat::Text => {
+                let mut line = format!(
+                    "{} [{}] {}",
+                    self.level_label(record.level()),
+                    short_target(record.target()),
+                    record.args()
+                );
+                for (key, value) in fields {
+                    line.push_str(&format!(" {}={}", key, value));
+                }
+                line
+            }
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("ts".to_string(), timestamp().into());
+                object.insert("level".to_string(), record.level().as_str().into());
+                object.insert("target".to_string(), record.target().into());
+                object.insert("message".to_string(), record.args().to_string().into());
+                for (key, value) in fields {
+                    object.insert(key, value.into_json());
+                }
+                serde_json::Value::Object(object).to_string()
+            }
+        }
+    }
+
+    fn level_label(&self, level: Level) -> String {
+        if !self.color {
+            return format!("{:5}", level);
+        }
+        let color = match level {
+            Level::Error => 31,
+            Level::Warn => 33,
+            Level::Info => 32,
+            Level::Debug => 34,
+            Level::Trace => 36,
+        };
+        format!("\x1b[{}m{:5}\x1b[0m", color, level)
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = self.format_record(record) + "\n";
+            let _ = io::stderr().lock().write_all(line.as_bytes());
+        }
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+// Valor de un campo estructurado. En JSON los números y booleanos se escriben como tales.
+enum FieldValue {
+    Number(Number),
+    Bool(bool),
+    Text(String),
+}
+
+impl FieldValue {
+    fn into_json(self) -> serde_json::Value {
+        match self {
+            FieldValue::Number(number) => serde_json::Value::Number(number),
+            FieldValue::Bool(value) => serde_json::Value::Bool(value),
+            FieldValue::Text(text) => serde_json::Value::String(text),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Number(number) => write!(f, "{}", number),
+            FieldValue::Bool(value) => write!(f, "{}", value),
+            FieldValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+// Junta los campos estructurados de un registro.
+struct Fields(Vec<(String, FieldValue)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(number) = value.to_u64() {
+            FieldValue::Number(number.into())
+        } else if let Some(number) = value.to_i64() {
+            FieldValue::Number(number.into())
+        } else if let Some(number) = value.to_f64().and_then(Number::from_f64) {
+            FieldValue::Number(number)
+        } else if let Some(value) = value.to_bool() {
+            FieldValue::Bool(value)
+        } else {
+            FieldValue::Text(value.to_string())
+        };
+        self.0.push((key.to_string(), value));
+        Ok(())
+    }
+}
+
+// Último tramo del módulo que generó el registro, por ejemplo `store` para `stores::store`.
+fn short_target(target: &str) -> &str {
+    target.rsplit("::").next().unwrap_or(target)
+}
+
+// Segundos desde el epoch con precisión de milisegundos.
+fn timestamp() -> f64 {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (elapsed.as_millis() as f64) / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: LogFormat) -> String {
+        let logger = Logger {
+            format,
+            color: false,
+            level: LevelFilter::Info,
+            fields: vec![("store", "8080".to_string())],
+        };
+        let kvs = [
+            ("order_id", Value::from(7u64)),
+            ("retry", Value::from(true)),
+        ];
+        let args = format_args!("Pedido reservado");
+        let record = Record::builder()
+            .level(Level::Info)
+            .target("stores::store")
+            .args(args)
+            .key_values(&kvs)
+            .build();
+        logger.format_record(&record)
+    }
+
+    #[test]
+    fn formats_records_as_text_and_json() {
+        assert_eq!(
+            render(LogFormat::Text),
+            "INFO  [store] Pedido reservado store=8080 order_id=7 retry=true"
+        );
+        let json: serde_json::Value = serde_json::from_str(&render(LogFormat::Json)).unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "stores::store");
+        assert_eq!(json["message"], "Pedido reservado");
+        assert_eq!(json["store"], "8080");
+        assert_eq!(json["order_id"], 7);
+        assert_eq!(json["retry"], true);
+    }
+}
@@ -0,0 +1,389 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Parser de los archivos de pedidos que leen el store y el ecommerce.
+//
+// Cada archivo tiene un encabezado y después un pedido por línea con el formato
+// `id,cantidad[,id,cantidad...][,prioridad]`: uno o más productos y, opcionalmente, la prioridad
+// (`express` o `standard`, por defecto `standard`). Las líneas vacías se ignoran.
+
+// Producto de un pedido.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderItem {
+    pub id: i32,
+    pub amount: i32,
+}
+
+// Prioridad de un pedido.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    #[default]
+    Standard,
+    Express,
+}
+
+// Pedido leído del archivo.
+//
+// Atributos:
+// * `line`: Número de línea en el archivo, contando el encabezado como la línea 1.
+// * `items`: Productos del pedido.
+// * `priority`: Prioridad del pedido.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderLine {
+    pub line: usize,
+    pub items: Vec<OrderItem>,
+    pub priority: Priority,
+}
+
+// Motivo por el que se rechaza una línea.
+//
+// Variantes:
+// * `ColumnCount`: Falta la cantidad de algún producto.
+// * `InvalidId`: El id no es un número entero no negativo.
+// * `InvalidAmount`: La cantidad no es un número entero positivo.
+// * `InvalidPriority`: La última columna no es ni un número ni una prioridad conocida.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    ColumnCount(usize),
+    InvalidId(String),
+    InvalidAmount(String),
+    InvalidPriority(String),
+}
+
+impl ParseErrorKind {
+    // Nombre corto del motivo, con el que se cuentan los errores en el resumen.
+    pub fn label(&self) -> &'static str {
+        match self {
+            ParseErrorKind::ColumnCount(_) => "columnas incompletas",
+            ParseErrorKind::InvalidId(_) => "id inválido",
+            ParseErrorKind::InvalidAmount(_) => "cantidad inválida",
+            ParseErrorKind::InvalidPriority(_) => "prioridad inválida",
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::ColumnCount(columns) => write!(
+                f,
+                "{} columnas, se espera id,cantidad[,id,cantidad...][,prioridad]",
+                columns
+            ),
+            ParseErrorKind::InvalidId(value) => write!(f, "id inválido '{}'", value),
+            ParseErrorKind::InvalidAmount(value) => {
+                write!(f, "cantidad inválida '{}', tiene que ser mayor a 0", value)
+            }
+            ParseErrorKind::InvalidPriority(value) => write!(
+                f,
+                "prioridad inválida '{}', se espera express o standard",
+                value
+            ),
+        }
+    }
+}
+
+// Error en una línea del archivo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "línea {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Qué hacer con las líneas inválidas.
+//
+// Variantes:
+// * `Skip`: Se ignoran y se sigue con la siguiente.
+// * `Abort`: Se deja de leer el archivo y se devuelve el error.
+// * `SideFile`: Se ignoran y se copian, con el encabezado, al archivo indicado para corregirlas.
+//
+// Desde la línea de comandos se escribe como `skip`, `abort` o `file:<ruta>`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    #[default]
+    Skip,
+    Abort,
+    SideFile(PathBuf),
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "skip" => Ok(ErrorPolicy::Skip),
+            None if s == "abort" => Ok(ErrorPolicy::Abort),
+            Some(("file", path)) if !path.is_empty() => Ok(ErrorPolicy::SideFile(path.into())),
+            _ => Err(format!(
+                "política de errores inválida: {} (se espera skip, abort o file:<ruta>)",
+                s
+            )),
+        }
+    }
+}
+
+// Errores al leer un archivo de pedidos.
+//
+// Variantes:
+// * `Io`: No se pudo leer el archivo o escribir el de líneas rechazadas.
+// * `Parse`: Hay una línea inválida y la política es `Abort`.
+#[derive(Debug)]
+pub enum OrderFileError {
+    Io(io::Error),
+    Parse(ParseError),
+}
+
+impl fmt::Display for OrderFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderFileError::Io(e) => write!(f, "error de entrada/salida: {}", e),
+            OrderFileError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for OrderFileError {}
+
+impl From<io::Error> for OrderFileError {
+    fn from(e: io::Error) -> Self {
+        OrderFileError::Io(e)
+    }
+}
+
+// Los errores de lectura se devuelven tal cual y las líneas inválidas como `InvalidData`.
+impl From<OrderFileError> for io::Error {
+    fn from(e: OrderFileError) -> Self {
+        match e {
+            OrderFileError::Io(e) => e,
+            OrderFileError::Parse(e) => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+// Cuántas líneas del archivo se aceptaron y cuántas se rechazaron por cada motivo.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParseSummary {
+    pub orders: usize,
+    pub rejected: BTreeMap<&'static str, usize>,
+}
+
+impl ParseSummary {
+    pub fn rejected_total(&self) -> usize {
+        self.rejected.values().sum()
+    }
+}
+
+impl fmt::Display for ParseSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pedidos válidos, {} líneas rechazadas",
+            self.orders,
+            self.rejected_total()
+        )?;
+        if !self.rejected.is_empty() {
+            let reasons: Vec<String> = self
+                .rejected
+                .iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect();
+            write!(f, " ({})", reasons.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// Pedidos válidos de un archivo y el resumen de lo que se leyó.
+#[derive(Debug)]
+pub struct OrderFile {
+    pub orders: Vec<OrderLine>,
+    pub summary: ParseSummary,
+}
+
+// Parsea una línea del archivo, sin el salto de línea.
+pub fn parse_line(line: &str, line_number: usize) -> Result<OrderLine, ParseError> {
+    let error = |kind| ParseError {
+        line: line_number,
+        kind,
+    };
+    let mut columns: Vec<&str> = line.split(',').map(|column| column.trim()).collect();
+    let total_columns = columns.len();
+    //Si la cantidad de columnas es impar, la última tiene que ser la prioridad
+    let priority = if total_columns % 2 == 1 {
+        match columns.pop() {
+            Some("express") => Priority::Express,
+            Some("standard") => Priority::Standard,
+            Some(last) if columns.is_empty() || last.parse::<i64>().is_ok() => {
+                return Err(error(ParseErrorKind::ColumnCount(total_columns)))
+            }
+            Some(last) => return Err(error(ParseErrorKind::InvalidPriority(last.to_string()))),
+            None => Priority::Standard,
+        }
+    } else {
+        Priority::Standard
+    };
+
+    let mut items = Vec::new();
+    for item in columns.chunks(2) {
+        let id = match item[0].parse::<i32>() {
+            Ok(id) if is_valid_product_id(id) => id,
+            _ => return Err(error(ParseErrorKind::InvalidId(item[0].to_string()))),
+        };
+        let amount = match item[1].parse::<i32>() {
+            Ok(amount) if amount > 0 => amount,
+            _ => return Err(error(ParseErrorKind::InvalidAmount(item[1].to_string()))),
+        };
+        items.push(OrderItem { id, amount });
+    }
+    if items.is_empty() {
+        return Err(error(ParseErrorKind::ColumnCount(total_columns)));
+    }
+    Ok(OrderLine {
+        line: line_number,
+        items,
+        priority,
+    })
+}
+
+// Indica si `id` es un id de producto válido. Lo usan también los stores para validar su
+// inventario, así los dos aceptan los mismos ids.
+pub fn is_valid_product_id(id: i32) -> bool {
+    id >= 0
+}
+
+// Parsea el contenido de un archivo de pedidos aplicando `policy` a las líneas inválidas.
+//
+// Cada línea inválida se informa en los logs con su número de línea.
+//
+// Retorna:
+// Los pedidos válidos con el resumen de lo leído, o un error si la política es `Abort` y hay una
+// línea inválida, o si no se pudo escribir el archivo de líneas rechazadas.
+pub fn parse_orders(contents: &str, policy: &ErrorPolicy) -> Result<OrderFile, OrderFileError> {
+    let mut lines = contents.lines();
+    let header = lines.next().unwrap_or_default();
+    let mut side_file = match policy {
+        ErrorPolicy::SideFile(path) => {
+            let mut file = BufWriter::new(File::create(path)?);
+            writeln!(file, "{}", header)?;
+            Some(file)
+        }
+        _ => None,
+    };
+
+    let mut orders = Vec::new();
+    let mut summary = ParseSummary::default();
+    for (index, line) in lines.enumerate() {
+        //El encabezado es la línea 1
+        let line_number = index + 2;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line, line_number) {
+            Ok(order) => {
+                summary.orders += 1;
+                orders.push(order);
+            }
+            Err(e) => {
+                warn!(line = e.line, row = line, error:% = e.kind; "Línea inválida en el archivo de pedidos");
+                if *policy == ErrorPolicy::Abort {
+                    return Err(OrderFileError::Parse(e));
+                }
+                *summary.rejected.entry(e.kind.label()).or_default() += 1;
+                if let Some(file) = side_file.as_mut() {
+                    writeln!(file, "{}", line)?;
+                }
+            }
+        }
+    }
+    if let Some(mut file) = side_file {
+        file.flush()?;
+    }
+    Ok(OrderFile { orders, summary })
+}
+
+// Lee y parsea un archivo de pedidos, ver `parse_orders`.
+pub fn read_order_file(path: &Path, policy: &ErrorPolicy) -> Result<OrderFile, OrderFileError> {
+    parse_orders(&fs::read_to_string(path)?, policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENTS: &str = "id,amount\n1,2,3,1,express\n\n4,-1\nx,2\n5\n2,2,urgente\n0,1\n";
+
+    #[test]
+    fn parses_items_and_priority() {
+        assert_eq!(
+            parse_line("1, 2,3,1,express", 2),
+            Ok(OrderLine {
+                line: 2,
+                items: vec![
+                    OrderItem { id: 1, amount: 2 },
+                    OrderItem { id: 3, amount: 1 }
+                ],
+                priority: Priority::Express,
+            })
+        );
+        let kind = |line| parse_line(line, 2).unwrap_err().kind;
+        assert_eq!(kind("4,0"), ParseErrorKind::InvalidAmount("0".to_string()));
+        assert_eq!(kind("-4,1"), ParseErrorKind::InvalidId("-4".to_string()));
+        assert_eq!(kind("4,1,5"), ParseErrorKind::ColumnCount(3));
+        assert_eq!(kind("express"), ParseErrorKind::ColumnCount(1));
+        assert_eq!(
+            kind("4,1,urgente"),
+            ParseErrorKind::InvalidPriority("urgente".to_string())
+        );
+    }
+
+    #[test]
+    fn skips_invalid_lines_and_tallies_them() {
+        let file = parse_orders(CONTENTS, &ErrorPolicy::Skip).unwrap();
+        let lines: Vec<usize> = file.orders.iter().map(|order| order.line).collect();
+        assert_eq!(lines, vec![2, 8]);
+        assert_eq!(file.summary.rejected_total(), 4);
+        assert_eq!(
+            file.summary.to_string(),
+            "2 pedidos válidos, 4 líneas rechazadas (1 cantidad inválida, 1 columnas incompletas, 1 id inválido, 1 prioridad inválida)"
+        );
+    }
+
+    #[test]
+    fn aborts_on_the_first_invalid_line_or_copies_them_to_a_side_file() {
+        let Err(OrderFileError::Parse(error)) = parse_orders(CONTENTS, &ErrorPolicy::Abort) else {
+            panic!("Se esperaba un error de parseo");
+        };
+        assert_eq!(
+            error.to_string(),
+            "línea 4: cantidad inválida '-1', tiene que ser mayor a 0"
+        );
+
+        let path = std::env::temp_dir().join(format!("rejected_{}.csv", std::process::id()));
+        let file = parse_orders(CONTENTS, &ErrorPolicy::SideFile(path.clone())).unwrap();
+        assert_eq!(file.orders.len(), 2);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "id,amount\n4,-1\nx,2\n5\n2,2,urgente\n"
+        );
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            "file:rechazados.csv".parse::<ErrorPolicy>(),
+            Ok(ErrorPolicy::SideFile("rechazados.csv".into()))
+        );
+        assert!("ignore".parse::<ErrorPolicy>().is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::BTreeSet;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+// Piezas de las simulaciones que comparten el store, el ecommerce y el generador de carga.
+
+// Constante con la que se mezcla el número de secuencia con la semilla, para que secuencias
+// consecutivas den generadores bien distintos.
+const STREAM_MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// Crea el generador de números aleatorios de la secuencia `stream`.
+//
+// Cada parte de una simulación que sortea algo usa su propia secuencia, derivada de la semilla y
+// de un número de secuencia. Así lo que sortea una parte no depende de cuántas veces sortearon las
+// demás.
+//
+// Argumentos:
+// * `seed`: Semilla de la simulación. Si no se indica, el generador usa una semilla al azar.
+// * `stream`: Número de secuencia.
+pub fn seeded_rng(seed: Option<u64>, stream: u64) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed ^ stream.wrapping_mul(STREAM_MIX)),
+        None => StdRng::from_entropy(),
+    }
+}
+
+// Cantidad de veces que el próximo en despertarse le cede el turno a las demás tareas antes de
+// adelantar el reloj virtual, para que terminen lo que estaban haciendo y pidan sus propios timers.
+const IDLE_ROUNDS: usize = 32;
+
+// Reloj con el que la simulación espera y mide tiempos.
+//
+// Solo se usa para los tiempos que se simulan (entregas, reintentos de entrega, esperas entre
+// pedidos, el proveedor). Las esperas de la red (reconexiones, heartbeats, saludos) siempre son en
+// tiempo real, porque dependen de otro proceso que no comparte el reloj.
+//
+// Variantes:
+// * `Real`: Las esperas pasan en tiempo real. Guarda el momento en el que arrancó.
+// * `Virtual`: Las esperas no pasan en tiempo real: cuando nadie tiene nada que hacer, el reloj salta
+//   directamente al próximo timer. Lo comparten todas las copias del reloj.
+#[derive(Debug, Clone)]
+pub enum Clock {
+    Real(Instant),
+    Virtual(Arc<VirtualClock>),
+}
+
+// Estado del reloj virtual.
+//
+// Atributos:
+// * `state`: Tiempo virtual transcurrido y timers pendientes.
+// * `advanced`: Avisa a los que esperan que cambió el tiempo o el próximo timer.
+#[derive(Debug, Default)]
+pub struct VirtualClock {
+    state: Mutex<VirtualState>,
+    advanced: Notify,
+}
+
+// Atributos:
+// * `now`: Tiempo virtual transcurrido desde que arrancó el reloj.
+// * `timers`: Timers pendientes, ordenados por el momento en que vencen y, entre los que vencen
+//   juntos, por orden de llegada.
+// * `next_timer`: Número del próximo timer, para desempatar los que vencen juntos.
+#[derive(Debug, Default)]
+struct VirtualState {
+    now: Duration,
+    timers: BTreeSet<(Duration, u64)>,
+    next_timer: u64,
+}
+
+impl Clock {
+    pub fn real() -> Self {
+        Clock::Real(Instant::now())
+    }
+
+    pub fn virtual_time() -> Self {
+        Clock::Virtual(Arc::new(VirtualClock::default()))
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        matches!(self, Clock::Virtual(_))
+    }
+
+    // Tiempo transcurrido desde que arrancó el reloj.
+    pub fn now(&self) -> Duration {
+        match self {
+            Clock::Real(start) => start.elapsed(),
+            Clock::Virtual(clock) => clock.state.lock().unwrap().now,
+        }
+    }
+
+    // Espera `duration` según el reloj. El futuro no depende del reloj, así que se puede lanzar como
+    // una tarea aparte o dentro de un actor.
+    pub fn sleep(&self, duration: Duration) -> impl Future<Output = ()> + Send + 'static {
+        let clock = self.clone();
+        async move {
+            match clock {
+                Clock::Real(_) => tokio::time::sleep(duration).await,
+                Clock::Virtual(clock) => clock.sleep(duration).await,
+            }
+        }
+    }
+}
+
+// Dos relojes son iguales si miden el mismo tiempo: los reales si arrancaron juntos y los virtuales
+// si son copias del mismo.
+impl PartialEq for Clock {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Clock::Real(start), Clock::Real(other)) => start == other,
+            (Clock::Virtual(clock), Clock::Virtual(other)) => Arc::ptr_eq(clock, other),
+            _ => false,
+        }
+    }
+}
+
+impl VirtualClock {
+    // Anota un timer que vence dentro de `duration` y espera a que el reloj llegue hasta él.
+    //
+    // El timer que vence primero es el que adelanta el reloj: le cede el turno a las demás tareas
+    // `IDLE_ROUNDS` veces y, si mientras tanto nadie anotó un timer anterior, salta hasta su
+    // vencimiento. Los demás esperan el aviso de que el reloj avanzó sin ocupar el procesador.
+    async fn sleep(self: Arc<Self>, duration: Duration) {
+        let timer = {
+            let mut state = self.state.lock().unwrap();
+            let timer = (state.now + duration, state.next_timer);
+            state.next_timer += 1;
+            state.timers.insert(timer);
+            timer
+        };
+        let _pending = PendingTimer {
+            clock: &self,
+            timer,
+        };
+        loop {
+            // Se anota para el aviso antes de mirar el estado, así no se pierde uno que llegue en el medio
+            let advanced = self.advanced.notified();
+            tokio::pin!(advanced);
+            advanced.as_mut().enable();
+            let is_next = {
+                let state = self.state.lock().unwrap();
+                if state.now >= timer.0 {
+                    return;
+                }
+                state.timers.first() == Some(&timer)
+            };
+            if !is_next {
+                advanced.await;
+                continue;
+            }
+            for _ in 0..IDLE_ROUNDS {
+                tokio::task::yield_now().await;
+            }
+            let mut state = self.state.lock().unwrap();
+            if state.timers.first() == Some(&timer) {
+                state.now = timer.0;
+            }
+        }
+    }
+}
+
+// Saca el timer de los pendientes cuando termina la espera o cuando se cancela. Si era el próximo en
+// vencer, avisa a los demás para que el siguiente adelante el reloj.
+struct PendingTimer<'a> {
+    clock: &'a VirtualClock,
+    timer: (Duration, u64),
+}
+
+impl Drop for PendingTimer<'_> {
+    fn drop(&mut self) {
+        let was_next = {
+            let mut state = self.clock.state.lock().unwrap();
+            let was_next = state.timers.first() == Some(&self.timer);
+            state.timers.remove(&self.timer);
+            was_next
+        };
+        if was_next {
+            self.clock.advanced.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn same_seed_and_stream_give_the_same_numbers() {
+        let sample = |stream| -> Vec<u32> {
+            let mut rng = seeded_rng(Some(42), stream);
+            (0..5).map(|_| rng.gen()).collect()
+        };
+        assert_eq!(sample(1), sample(1));
+        assert_ne!(sample(1), sample(2));
+    }
+
+    #[tokio::test]
+    async fn virtual_sleeps_only_move_the_clock() {
+        let clock = Clock::virtual_time();
+        let started = Instant::now();
+        clock.sleep(Duration::from_secs(3600)).await;
+        clock.clone().sleep(Duration::from_secs(60)).await;
+        assert_eq!(clock.now(), Duration::from_secs(3660));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn concurrent_virtual_sleeps_wake_up_in_order_without_adding_up() {
+        let clock = Clock::virtual_time();
+        let woken = Arc::new(Mutex::new(Vec::new()));
+        let sleepers: Vec<_> = [30, 10, 20, 10]
+            .into_iter()
+            .map(|secs| {
+                let (clock, woken) = (clock.clone(), woken.clone());
+                tokio::spawn(async move {
+                    clock.sleep(Duration::from_secs(secs)).await;
+                    woken.lock().unwrap().push((secs, clock.now().as_secs()));
+                })
+            })
+            .collect();
+        for sleeper in sleepers {
+            sleeper.await.unwrap();
+        }
+        assert_eq!(
+            *woken.lock().unwrap(),
+            vec![(10, 10), (10, 10), (20, 20), (30, 30)]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_sleep_does_not_hold_the_clock() {
+        let clock = Clock::virtual_time();
+        let cancelled = tokio::spawn(clock.sleep(Duration::from_secs(5)));
+        tokio::task::yield_now().await;
+        cancelled.abort();
+        clock.sleep(Duration::from_secs(60)).await;
+        assert_eq!(clock.now(), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,121 @@
+// Autenticación de las conexiones con un token compartido.
+//
+// El token nunca viaja por la conexión: el store envía un desafío al azar y el cliente responde con
+// el HMAC-SHA256 del desafío usando el token como clave. Como cada conexión tiene un desafío distinto,
+// una respuesta capturada no sirve para autenticar otra conexión.
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Bytes al azar de cada desafío.
+const CHALLENGE_LEN: usize = 32;
+
+// Mensaje con el que se comparan dos tokens en `AuthToken::matches`.
+const MATCH_MESSAGE: &str = "store-token";
+
+// Token compartido entre un store y sus clientes. No se muestra en los logs, y no se puede comparar
+// con `==` para que todas las comparaciones pasen por `matches` o `verify`.
+#[derive(Clone)]
+pub struct AuthToken {
+    secret: Vec<u8>,
+}
+
+impl AuthToken {
+    pub fn new(secret: impl Into<Vec<u8>>) -> AuthToken {
+        AuthToken {
+            secret: secret.into(),
+        }
+    }
+
+    // Lee el token de un archivo, sin los espacios y saltos de línea de los extremos.
+    //
+    // Retorna:
+    // El token, o un error si no se pudo leer el archivo o está vacío.
+    pub fn read(path: &Path) -> io::Result<AuthToken> {
+        let content = fs::read_to_string(path)?;
+        let secret = content.trim();
+        if secret.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("el archivo de token {} está vacío", path.display()),
+            ));
+        }
+        Ok(AuthToken::new(secret))
+    }
+
+    // Respuesta al desafío `challenge`, en hexadecimal.
+    pub fn sign(&self, challenge: &str) -> String {
+        hex::encode(self.mac(challenge).finalize().into_bytes())
+    }
+
+    // Verifica la respuesta a un desafío. La comparación tarda lo mismo sin importar en qué byte
+    // difieran, para no dar pistas sobre la respuesta correcta.
+    pub fn verify(&self, challenge: &str, response: &str) -> bool {
+        match hex::decode(response) {
+            Ok(response) => self.mac(challenge).verify_slice(&response).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    // Indica si `presented` es el token, por ejemplo el que se recibe en un pedido HTTP. Se comparan
+    // los HMAC de un mismo mensaje con cada uno como clave, así la comparación tarda lo mismo sin
+    // importar en qué byte difieran.
+    pub fn matches(&self, presented: &str) -> bool {
+        let presented = AuthToken::new(presented).mac(MATCH_MESSAGE).finalize();
+        self.mac(MATCH_MESSAGE)
+            .verify_slice(&presented.into_bytes())
+            .is_ok()
+    }
+
+    fn mac(&self, challenge: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret)
+            .expect("HMAC acepta claves de cualquier largo");
+        mac.update(challenge.as_bytes());
+        mac
+    }
+}
+
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AuthToken(***)")
+    }
+}
+
+// Genera un desafío nuevo, en hexadecimal.
+pub fn new_challenge() -> String {
+    hex::encode(rand::random::<[u8; CHALLENGE_LEN]>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_same_token_answers_the_challenge() {
+        let token = AuthToken::new("secreto");
+        let challenge = new_challenge();
+        assert_eq!(challenge.len(), CHALLENGE_LEN * 2);
+        assert_ne!(challenge, new_challenge());
+
+        let response = token.sign(&challenge);
+        assert!(token.verify(&challenge, &response));
+        assert!(!token.verify(&new_challenge(), &response));
+        assert!(!AuthToken::new("otro").verify(&challenge, &response));
+        assert!(!token.verify(&challenge, "no es hexadecimal"));
+        assert_eq!(format!("{:?}", token), "AuthToken(***)");
+    }
+
+    #[test]
+    fn matches_only_the_same_token() {
+        let token = AuthToken::new("secreto");
+        assert!(token.matches("secreto"));
+        assert!(!token.matches("secret"));
+        assert!(!token.matches("secreto2"));
+        assert!(!token.matches(""));
+    }
+}
@@ -0,0 +1,484 @@
+// Protocolo entre el ecommerce y los stores.
+//
+// Cada mensaje viaja en un frame: 4 bytes con el largo del contenido (big endian) seguidos del
+// contenido, que es un objeto JSON con el `id` del pedido al que corresponde y los campos del mensaje.
+// Antes de cualquier otro mensaje el cliente envía un `Hello` con su versión del protocolo y el store
+// le responde `Welcome` si la soporta o `UnsupportedVersion` y cierra la conexión. Si el store tiene
+// un token, el `Welcome` trae un desafío que el cliente tiene que responder con el token (ver `auth`)
+// antes de que el store acepte pedidos.
+//
+// Acá está todo lo que comparten los dos lados: el sobre con el id, el saludo, los mensajes (ver
+// `messages`) y el codec con el que se leen y se escriben los frames.
+pub use auth::AuthToken;
+use bytes::{Buf, BufMut, BytesMut};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder};
+
+pub use messages::{
+    CartRequest, Item, Order, OrderId, OrderOrigin, OrderRequest, OrderState, Priority,
+    RejectReason, StockLevel, StoreRequest, StoreResponse,
+};
+
+mod auth;
+mod messages;
+
+// Versión del protocolo que habla este código.
+//
+// * 1: Frames con id y saludo.
+// * 2: Los pedidos rechazados indican el motivo.
+// * 3: Heartbeats para detectar conexiones muertas.
+// * 4: Autenticación con un token compartido.
+pub const PROTOCOL_VERSION: u16 = 4;
+
+// Largo máximo del contenido de un frame. Un frame más largo se considera un error del otro lado y
+// corta la conexión, porque no se puede confiar en el resto de lo que llega.
+pub const MAX_FRAME_LEN: usize = 1024 * 1024;
+
+// Bytes del encabezado de cada frame, donde va el largo del contenido.
+const HEADER_LEN: usize = 4;
+
+// Identificador con el que el cliente numera sus pedidos. La respuesta a un pedido lleva su mismo id.
+pub type RequestId = u64;
+
+// Id de los mensajes que no responden a ningún pedido: el saludo y los avisos que envía el store por
+// su cuenta. Los clientes numeran sus pedidos a partir de 1.
+pub const NO_REQUEST: RequestId = 0;
+
+// Sobre de todos los mensajes. En el JSON el `id` va junto a los campos del mensaje.
+//
+// Atributos:
+// * `id`: Pedido al que corresponde el mensaje, o `NO_REQUEST`.
+// * `message`: El mensaje.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Envelope<T> {
+    pub id: RequestId,
+    #[serde(flatten)]
+    pub message: T,
+}
+
+impl<T> Envelope<T> {
+    pub fn new(id: RequestId, message: T) -> Envelope<T> {
+        Envelope { id, message }
+    }
+}
+
+// Mensajes del saludo con el que empieza cada conexión.
+//
+// Variantes:
+// * `Hello`: El cliente indica la versión del protocolo que habla.
+// * `Welcome`: El store acepta la versión. Si pide autenticación incluye un `challenge`; si no,
+//   empieza a recibir pedidos.
+// * `UnsupportedVersion`: El store no habla esa versión, indica la suya y cierra la conexión.
+// * `Authenticate`: El cliente responde el desafío con el HMAC que calculó con su token.
+// * `Authenticated`: El store aceptó la respuesta y empieza a recibir pedidos.
+// * `Unauthorized`: El store no aceptó la respuesta y cierra la conexión.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Handshake {
+    Hello {
+        version: u16,
+    },
+    Welcome {
+        version: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        challenge: Option<String>,
+    },
+    UnsupportedVersion {
+        supported: u16,
+    },
+    Authenticate {
+        mac: String,
+    },
+    Authenticated,
+    Unauthorized,
+}
+
+// Errores del saludo.
+//
+// Variantes:
+// * `Io`: Se cortó la conexión o llegó algo que no es un saludo.
+// * `Version`: Los dos lados hablan versiones distintas del protocolo.
+// * `Unauthorized`: El cliente no respondió bien el desafío, o el store pide autenticación y el
+//   cliente no tiene token.
+#[derive(Debug)]
+pub enum HandshakeError {
+    Io(io::Error),
+    Version { local: u16, remote: u16 },
+    Unauthorized,
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Io(e) => write!(f, "{}", e),
+            HandshakeError::Version { local, remote } => write!(
+                f,
+                "versión del protocolo {} no soportada, se habla la {}",
+                remote, local
+            ),
+            HandshakeError::Unauthorized => write!(f, "autenticación rechazada"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+impl From<io::Error> for HandshakeError {
+    fn from(e: io::Error) -> Self {
+        HandshakeError::Io(e)
+    }
+}
+
+// Frame cuyo contenido no es un mensaje válido. La conexión sigue, porque el frame se puede saltear.
+//
+// Atributos:
+// * `id`: Id del pedido, si se pudo leer, para poder responderle.
+// * `error`: Por qué no se pudo leer el mensaje.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedMessage {
+    pub id: Option<RequestId>,
+    pub error: String,
+}
+
+impl fmt::Display for MalformedMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "mensaje {} inválido: {}", id, self.error),
+            None => write!(f, "mensaje inválido: {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for MalformedMessage {}
+
+// Resultado de leer un frame: el mensaje o por qué no se pudo leer.
+pub type Decoded<T> = Result<Envelope<T>, MalformedMessage>;
+
+// Codec de los frames del protocolo. Lee mensajes de tipo `T` y escribe cualquier mensaje serializable.
+//
+// Los errores de entrada/salida y los frames demasiado largos cortan la lectura; un frame con un JSON
+// inválido se devuelve como `MalformedMessage` y se sigue con el próximo.
+pub struct MessageCodec<T> {
+    message: PhantomData<fn() -> T>,
+}
+
+impl<T> MessageCodec<T> {
+    pub fn new() -> MessageCodec<T> {
+        MessageCodec {
+            message: PhantomData,
+        }
+    }
+}
+
+impl<T> Default for MessageCodec<T> {
+    fn default() -> Self {
+        MessageCodec::new()
+    }
+}
+
+impl<T: DeserializeOwned> Decoder for MessageCodec<T> {
+    type Item = Decoded<T>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        check_len(len)?;
+        if src.len() < HEADER_LEN + len {
+            src.reserve(HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+        src.advance(HEADER_LEN);
+        let payload = src.split_to(len);
+        Ok(Some(parse_payload(&payload)))
+    }
+}
+
+impl<T, U: Serialize> Encoder<Envelope<U>> for MessageCodec<T> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Envelope<U>, dst: &mut BytesMut) -> io::Result<()> {
+        encode_message(&item, dst)
+    }
+}
+
+// Agrega a `dst` el frame con el mensaje.
+pub fn encode_message<U: Serialize>(message: &Envelope<U>, dst: &mut BytesMut) -> io::Result<()> {
+    let payload = serde_json::to_vec(message)?;
+    check_len(payload.len())?;
+    dst.reserve(HEADER_LEN + payload.len());
+    dst.put_u32(payload.len() as u32);
+    dst.extend_from_slice(&payload);
+    Ok(())
+}
+
+// Escribe un mensaje en `writer` como un único frame.
+pub async fn write_message<W, U>(writer: &mut W, message: &Envelope<U>) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+    U: Serialize,
+{
+    let mut frame = BytesMut::new();
+    encode_message(message, &mut frame)?;
+    writer.write_all(&frame).await
+}
+
+// Lee exactamente un frame de `reader`, sin consumir nada de lo que venga después. Se usa para el
+// saludo, antes de pasarle la conexión a un `FramedRead`.
+//
+// Retorna:
+// El mensaje, o un error si se cortó la conexión o el frame no es un mensaje válido.
+pub async fn read_message<R, T>(reader: &mut R) -> io::Result<Envelope<T>>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let len = reader.read_u32().await? as usize;
+    check_len(len)?;
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload).await?;
+    parse_payload(&payload).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Saludo del lado del cliente: envía su versión, espera a que el store la acepte y, si el store
+// envía un desafío, lo responde con `token`.
+//
+// Retorna:
+// La versión acordada, o un error si el store no la soporta, no aceptó la autenticación o se cortó
+// la conexión.
+pub async fn connect_handshake<S>(
+    stream: &mut S,
+    token: Option<&AuthToken>,
+) -> Result<u16, HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let hello = Handshake::Hello {
+        version: PROTOCOL_VERSION,
+    };
+    write_message(stream, &Envelope::new(NO_REQUEST, hello)).await?;
+    match read_message::<_, Handshake>(stream).await?.message {
+        Handshake::Welcome {
+            version,
+            challenge: None,
+        } => Ok(version),
+        Handshake::Welcome {
+            version,
+            challenge: Some(challenge),
+        } => {
+            let token = token.ok_or(HandshakeError::Unauthorized)?;
+            let answer = Handshake::Authenticate {
+                mac: token.sign(&challenge),
+            };
+            write_message(stream, &Envelope::new(NO_REQUEST, answer)).await?;
+            match read_message::<_, Handshake>(stream).await?.message {
+                Handshake::Authenticated => Ok(version),
+                Handshake::Unauthorized => Err(HandshakeError::Unauthorized),
+                other => Err(unexpected(other)),
+            }
+        }
+        Handshake::UnsupportedVersion { supported } => Err(HandshakeError::Version {
+            local: PROTOCOL_VERSION,
+            remote: supported,
+        }),
+        other => Err(unexpected(other)),
+    }
+}
+
+// Saludo del lado del store: espera la versión del cliente y le responde si la soporta. Si se
+// indica `token`, además le envía un desafío y solo acepta la conexión si el cliente lo responde con
+// el mismo token.
+//
+// Retorna:
+// La versión acordada, o un error si el cliente habla otra versión, no saludó o no se autenticó.
+pub async fn accept_handshake<S>(
+    stream: &mut S,
+    token: Option<&AuthToken>,
+) -> Result<u16, HandshakeError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    match read_message::<_, Handshake>(stream).await?.message {
+        Handshake::Hello { version } if version == PROTOCOL_VERSION => {
+            let challenge = token.map(|_| auth::new_challenge());
+            let welcome = Handshake::Welcome {
+                version,
+                challenge: challenge.clone(),
+            };
+            write_message(stream, &Envelope::new(NO_REQUEST, welcome)).await?;
+            let (Some(token), Some(challenge)) = (token, challenge) else {
+                return Ok(version);
+            };
+            //Cualquier cosa que no sea la respuesta correcta cuenta como un intento fallido
+            let authenticated = match read_message::<_, Handshake>(stream).await {
+                Ok(Envelope {
+                    message: Handshake::Authenticate { mac },
+                    ..
+                }) => token.verify(&challenge, &mac),
+                Ok(_) => false,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => false,
+                Err(e) => return Err(e.into()),
+            };
+            let reply = if authenticated {
+                Handshake::Authenticated
+            } else {
+                Handshake::Unauthorized
+            };
+            write_message(stream, &Envelope::new(NO_REQUEST, reply)).await?;
+            if authenticated {
+                Ok(version)
+            } else {
+                Err(HandshakeError::Unauthorized)
+            }
+        }
+        Handshake::Hello { version } => {
+            let reply = Handshake::UnsupportedVersion {
+                supported: PROTOCOL_VERSION,
+            };
+            write_message(stream, &Envelope::new(NO_REQUEST, reply)).await?;
+            Err(HandshakeError::Version {
+                local: PROTOCOL_VERSION,
+                remote: version,
+            })
+        }
+        other => Err(unexpected(other)),
+    }
+}
+
+fn unexpected(message: Handshake) -> HandshakeError {
+    HandshakeError::Io(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("mensaje inesperado en el saludo: {:?}", message),
+    ))
+}
+
+fn check_len(len: usize) -> io::Result<()> {
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame de {} bytes, el máximo es {}", len, MAX_FRAME_LEN),
+        ));
+    }
+    Ok(())
+}
+
+// Parsea el contenido de un frame. Si no es un mensaje válido intenta leer al menos el id.
+fn parse_payload<T: DeserializeOwned>(payload: &[u8]) -> Decoded<T> {
+    #[derive(Deserialize)]
+    struct IdOnly {
+        id: RequestId,
+    }
+
+    serde_json::from_slice(payload).map_err(|e| MalformedMessage {
+        id: serde_json::from_slice::<IdOnly>(payload)
+            .ok()
+            .map(|only| only.id),
+        error: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum Ping {
+        Ping { value: i32 },
+    }
+
+    #[test]
+    fn decodes_frames_split_across_reads_and_skips_malformed_ones() {
+        let mut codec = MessageCodec::<Ping>::new();
+        let mut wire = BytesMut::new();
+        codec
+            .encode(Envelope::new(7, Ping::Ping { value: 3 }), &mut wire)
+            .unwrap();
+        let first = br#"{"id":7,"type":"ping","value":3}"#;
+        assert_eq!(&wire[..4], (first.len() as u32).to_be_bytes());
+        assert_eq!(&wire[4..], first);
+        let bad = br#"{"id":8,"type":"pong"}"#;
+        wire.put_u32(bad.len() as u32);
+        wire.extend_from_slice(bad);
+
+        let mut src = BytesMut::from(&wire[..10]);
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+        src.extend_from_slice(&wire[10..]);
+        let decoded = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(decoded, Ok(Envelope::new(7, Ping::Ping { value: 3 })));
+        let second = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(second.unwrap_err().id, Some(8));
+        assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+        let mut huge = BytesMut::new();
+        huge.put_u32(MAX_FRAME_LEN as u32 + 1);
+        assert!(codec.decode(&mut huge).is_err());
+    }
+
+    #[tokio::test]
+    async fn handshake_agrees_on_the_version_or_rejects_it() {
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let accepted = tokio::spawn(async move { accept_handshake(&mut server, None).await });
+        assert_eq!(
+            connect_handshake(&mut client, None).await.unwrap(),
+            PROTOCOL_VERSION
+        );
+        assert_eq!(accepted.await.unwrap().unwrap(), PROTOCOL_VERSION);
+
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        let accepted = tokio::spawn(async move { accept_handshake(&mut server, None).await });
+        let hello = Handshake::Hello { version: 99 };
+        write_message(&mut client, &Envelope::new(NO_REQUEST, hello))
+            .await
+            .unwrap();
+        let reply = read_message::<_, Handshake>(&mut client).await.unwrap();
+        assert_eq!(
+            reply.message,
+            Handshake::UnsupportedVersion {
+                supported: PROTOCOL_VERSION
+            }
+        );
+        assert!(matches!(
+            accepted.await.unwrap(),
+            Err(HandshakeError::Version { remote: 99, .. })
+        ));
+    }
+
+    // Saluda con un store que tiene el token `store` usando el token `client`.
+    async fn authenticate(
+        store: &str,
+        client: Option<&str>,
+    ) -> (Result<u16, HandshakeError>, Result<u16, HandshakeError>) {
+        let (mut client_stream, mut server) = tokio::io::duplex(1024);
+        let store = AuthToken::new(store);
+        let accepted =
+            tokio::spawn(async move { accept_handshake(&mut server, Some(&store)).await });
+        let client = client.map(AuthToken::new);
+        let connected = connect_handshake(&mut client_stream, client.as_ref()).await;
+        drop(client_stream);
+        (connected, accepted.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn store_with_a_token_only_accepts_clients_that_answer_the_challenge() {
+        let (connected, accepted) = authenticate("secreto", Some("secreto")).await;
+        assert_eq!(connected.unwrap(), PROTOCOL_VERSION);
+        assert_eq!(accepted.unwrap(), PROTOCOL_VERSION);
+
+        let (connected, accepted) = authenticate("secreto", Some("otro")).await;
+        assert!(matches!(connected, Err(HandshakeError::Unauthorized)));
+        assert!(matches!(accepted, Err(HandshakeError::Unauthorized)));
+
+        // Sin token el cliente no puede responder el desafío y corta la conexión
+        let (connected, accepted) = authenticate("secreto", None).await;
+        assert!(matches!(connected, Err(HandshakeError::Unauthorized)));
+        assert!(accepted.is_err());
+    }
+}
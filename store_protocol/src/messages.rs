@@ -0,0 +1,408 @@
+use serde::{Deserialize, Serialize};
+
+// Identificador que el store le asigna a cada pedido.
+pub type OrderId = u64;
+
+// Producto de un pedido, o del stock del store, con su cantidad.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Item {
+    pub id: i32,
+    pub amount: i32,
+}
+
+// Prioridad de entrega de un pedido del ecommerce.
+//
+// Variantes:
+// * `Express`: Se entrega antes que los pedidos estándar.
+// * `Standard`: Prioridad por defecto.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Express,
+    #[default]
+    Standard,
+}
+
+// Pedido que un cliente le envía al store, dentro de un `Envelope` con el id del pedido.
+//
+// Se distingue por el campo `type` del JSON:
+// * `order`: `{"type": "order", "product_id": <product_id>, "amount": <amount>, "priority": "express" | "standard"}`,
+//   un pedido de un solo producto. La prioridad es opcional y por defecto es `standard`. Con
+//   `"allow_partial": true` el store reserva lo que tenga si no alcanza para todo el pedido.
+// * `cart`: `{"type": "cart", "items": [{"id": <product_id>, "amount": <amount>}, ...], "priority": ...}`,
+//   un pedido con varios productos que se reservan todos o ninguno. Es lo que envían el ecommerce
+//   y el generador de carga.
+// * `query_order`: `{"type": "query_order", "order_id": <order_id>}`, consulta el estado de un pedido.
+// * `query_stock`: `{"type": "query_stock", "ids": [<product_id>, ...]}`, consulta el stock de algunos
+//   productos (o de todos si la lista está vacía) sin modificar nada.
+// * `restock`: `{"type": "restock", "product_id": <product_id>, "amount": <amount>}`, repone stock de un
+//   producto.
+// * `scale_delivery`: `{"type": "scale_delivery", "delivery_workers": <cantidad>}`, cambia la cantidad
+//   de deliverys del store.
+// * `heartbeat`: `{"type": "heartbeat"}`, avisa que el cliente sigue vivo. Va con `NO_REQUEST` y se
+//   responde con otro.
+//
+// El `id` del JSON es el del sobre, por eso los productos sueltos se indican con `product_id`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StoreRequest {
+    Order(OrderRequest),
+    Cart(CartRequest),
+    QueryOrder { order_id: OrderId },
+    QueryStock { ids: Vec<i32> },
+    Restock { product_id: i32, amount: i32 },
+    ScaleDelivery { delivery_workers: usize },
+    Heartbeat,
+}
+
+// Pedido de un solo producto.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrderRequest {
+    pub product_id: i32,
+    pub amount: i32,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub allow_partial: bool,
+}
+
+// Pedido con varios productos.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartRequest {
+    pub items: Vec<Item>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub allow_partial: bool,
+}
+
+impl From<OrderRequest> for CartRequest {
+    fn from(order: OrderRequest) -> CartRequest {
+        CartRequest {
+            items: vec![Item {
+                id: order.product_id,
+                amount: order.amount,
+            }],
+            priority: order.priority,
+            allow_partial: order.allow_partial,
+        }
+    }
+}
+
+// Mensaje que el store envía por la conexión TCP, dentro de un `Envelope` con el id del pedido al que
+// responde. Los avisos de delivery van con `NO_REQUEST`.
+//
+// Variantes:
+// * `OrderResult`: Respuesta a un pedido. Si se aceptó incluye el id que le asignó el store y, si se
+//   reservó solo una parte, los productos que faltaron en `remaining`. Si no, el motivo en `reason`.
+// * `OrderStatus`: Respuesta a una consulta de pedido, `null` si el pedido no existe.
+// * `Stock`: Respuesta a una consulta de stock, con lo disponible, reservado y en delivery de cada producto.
+// * `Delivered`: Aviso de que el pedido `order_id` se entregó.
+// * `DeliveryFailed`: Aviso de que el pedido `order_id` no se pudo entregar.
+// * `RestockResult`: Respuesta a una reposición, con el stock resultante o `null` si la cantidad no era válida.
+// * `DeliveryWorkers`: Respuesta a un cambio del pool de deliverys, con la cantidad resultante.
+// * `Rejected`: Respuesta a un mensaje que no es un pedido y no se pudo atender, o que no se pudo leer.
+// * `Heartbeat`: Respuesta a un heartbeat del cliente, para que sepa que el store sigue vivo.
+// * `Unknown`: Un mensaje que esta versión no conoce. El store nunca lo envía; sirve para que los
+//   clientes ignoren los mensajes nuevos de un store más nuevo.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StoreResponse {
+    OrderResult {
+        accepted: bool,
+        order_id: Option<OrderId>,
+        #[serde(default)]
+        remaining: Vec<Item>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<RejectReason>,
+    },
+    OrderStatus {
+        order: Option<Order>,
+    },
+    Stock {
+        products: Vec<StockLevel>,
+    },
+    Delivered {
+        order_id: OrderId,
+    },
+    DeliveryFailed {
+        order_id: OrderId,
+    },
+    RestockResult {
+        product_id: i32,
+        stock: Option<i32>,
+    },
+    DeliveryWorkers {
+        count: usize,
+    },
+    Rejected {
+        reason: RejectReason,
+    },
+    Heartbeat,
+    #[serde(other)]
+    Unknown,
+}
+
+impl StoreResponse {
+    // Respuesta a un pedido que el store tomó, entero o solo una parte.
+    pub fn accepted_order(order_id: OrderId, remaining: Vec<Item>) -> StoreResponse {
+        StoreResponse::OrderResult {
+            accepted: true,
+            order_id: Some(order_id),
+            remaining,
+            reason: None,
+        }
+    }
+
+    // Respuesta a un pedido que el store no tomó.
+    pub fn rejected_order(reason: RejectReason) -> StoreResponse {
+        StoreResponse::OrderResult {
+            accepted: false,
+            order_id: None,
+            remaining: Vec::new(),
+            reason: Some(reason),
+        }
+    }
+}
+
+// Motivo por el que el store no atendió un pedido, en el campo `code` del JSON.
+//
+// Variantes:
+// * `NotFound`: El store no conoce el producto `product_id`. Ningún otro store lo va a tener.
+// * `InsufficientStock`: No hay suficiente del producto `product_id`, solo quedan `available` unidades.
+// * `Malformed`: El mensaje no se pudo leer o el pedido no es válido, con el motivo en `error`. Otro
+//   store tampoco lo va a tomar.
+// * `Overloaded`: El store no puede atender pedidos ahora: el actor `Store` no respondió o se está
+//   cerrando.
+// * `Unknown`: Un motivo que esta versión no conoce. El store nunca lo envía.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "code", rename_all = "snake_case")]
+pub enum RejectReason {
+    NotFound {
+        product_id: i32,
+    },
+    InsufficientStock {
+        product_id: i32,
+        available: i32,
+    },
+    Malformed {
+        error: String,
+    },
+    Overloaded,
+    #[serde(other)]
+    Unknown,
+}
+
+impl RejectReason {
+    // Nombre del motivo, como aparece en el JSON y en las métricas.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectReason::NotFound { .. } => "not_found",
+            RejectReason::InsufficientStock { .. } => "insufficient_stock",
+            RejectReason::Malformed { .. } => "malformed",
+            RejectReason::Overloaded => "overloaded",
+            RejectReason::Unknown => "unknown",
+        }
+    }
+}
+
+// Origen de un pedido.
+//
+// Variantes:
+// * `Local`: Pedido hecho en el local, leído del archivo de pedidos.
+// * `Ecommerce`: Pedido recibido del ecommerce por la conexión TCP.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderOrigin {
+    Local,
+    Ecommerce,
+}
+
+// Estados por los que pasa un pedido.
+//
+// Variantes:
+// * `Received`: El store recibió el pedido y todavía no verificó el stock.
+// * `Rejected`: No había stock suficiente de algún producto o alguno no existe.
+// * `Reserved`: Se descontó el stock de todos los productos. Los pedidos del ecommerce esperan un delivery.
+// * `OutForDelivery`: Un delivery tomó el pedido y lo está entregando.
+// * `Delivered`: El pedido se entregó (o se retiró en el local).
+// * `AwaitingRetry`: Falló un intento de entrega y el pedido espera para volver a intentarlo.
+// * `DeliveryFailed`: Se agotaron los intentos de entrega del pedido.
+// * `Restocked`: Los productos de un pedido que no se entregó volvieron al stock.
+// * `Cancelled`: El store se cerró antes de que un delivery tomara el pedido y los productos volvieron al stock.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderState {
+    Received,
+    Rejected,
+    Reserved,
+    OutForDelivery,
+    AwaitingRetry,
+    Delivered,
+    DeliveryFailed,
+    Restocked,
+    Cancelled,
+}
+
+impl OrderState {
+    // Indica si un pedido puede pasar de este estado a `next`.
+    //
+    // Los pedidos locales pasan de `Reserved` a `Delivered` directamente porque se retiran en el local.
+    pub fn can_transition_to(self, next: OrderState) -> bool {
+        use OrderState::*;
+        matches!(
+            (self, next),
+            (Received, Rejected)
+                | (Received, Reserved)
+                | (Reserved, OutForDelivery)
+                | (Reserved, Delivered)
+                | (OutForDelivery, Delivered)
+                | (OutForDelivery, DeliveryFailed)
+                | (OutForDelivery, AwaitingRetry)
+                | (AwaitingRetry, OutForDelivery)
+                | (AwaitingRetry, Cancelled)
+                | (DeliveryFailed, Restocked)
+                | (Reserved, Cancelled)
+        )
+    }
+
+    // Indica si el pedido ya no puede cambiar de estado.
+    pub fn is_terminal(self) -> bool {
+        use OrderState::*;
+        matches!(self, Rejected | Delivered | Restocked | Cancelled)
+    }
+}
+
+// Pedido registrado en el store.
+//
+// Atributos:
+// * `id`: Identificador del pedido.
+// * `items`: Productos pedidos, cada uno con su cantidad.
+// * `origin`: Si el pedido es local o del ecommerce.
+// * `state`: Estado actual del pedido.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Order {
+    pub id: OrderId,
+    pub items: Vec<Item>,
+    pub origin: OrderOrigin,
+    pub state: OrderState,
+}
+
+// Stock de un producto del store.
+//
+// Atributos:
+// * `id`: Identificador del producto.
+// * `available`: Unidades disponibles para nuevos pedidos.
+// * `reserved`: Unidades reservadas por pedidos del ecommerce que esperan un delivery (o un reintento).
+// * `in_delivery`: Unidades que están entregando los deliverys.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StockLevel {
+    pub id: i32,
+    pub available: i32,
+    pub reserved: i32,
+    pub in_delivery: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Envelope;
+
+    #[test]
+    fn parses_single_product_and_cart_orders() {
+        let order: Envelope<StoreRequest> =
+            serde_json::from_str(r#"{"id":1,"type":"order","product_id":3,"amount":2}"#).unwrap();
+        assert_eq!(order.id, 1);
+        let StoreRequest::Order(order) = order.message else {
+            panic!("Se esperaba un pedido de un producto");
+        };
+        let cart = CartRequest::from(order);
+        assert_eq!(cart.items, vec![Item { id: 3, amount: 2 }]);
+
+        let cart: Envelope<StoreRequest> = serde_json::from_str(
+            r#"{"id":2,"type":"cart","items":[{"id":3,"amount":2},{"id":5,"amount":1}],"priority":"express"}"#,
+        )
+        .unwrap();
+        let StoreRequest::Cart(cart) = cart.message else {
+            panic!("Se esperaba un carrito");
+        };
+        assert_eq!(cart.items.len(), 2);
+        assert_eq!(cart.priority, Priority::Express);
+        assert!(!cart.allow_partial);
+
+        let heartbeat: Envelope<StoreRequest> =
+            serde_json::from_str(r#"{"id":0,"type":"heartbeat"}"#).unwrap();
+        assert_eq!(heartbeat.message, StoreRequest::Heartbeat);
+    }
+
+    #[test]
+    fn serializes_carts_with_all_their_fields() {
+        let request = StoreRequest::Cart(CartRequest {
+            items: vec![Item { id: 1, amount: 2 }],
+            priority: Priority::Express,
+            allow_partial: false,
+        });
+        assert_eq!(
+            serde_json::to_string(&Envelope::new(3, request)).unwrap(),
+            r#"{"id":3,"type":"cart","items":[{"id":1,"amount":2}],"priority":"express","allow_partial":false}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&Envelope::new(0, StoreRequest::Heartbeat)).unwrap(),
+            r#"{"id":0,"type":"heartbeat"}"#
+        );
+    }
+
+    #[test]
+    fn responses_read_back_as_they_were_written() {
+        let responses = [
+            StoreResponse::accepted_order(5, vec![Item { id: 1, amount: 2 }]),
+            StoreResponse::rejected_order(RejectReason::InsufficientStock {
+                product_id: 3,
+                available: 1,
+            }),
+            StoreResponse::RestockResult {
+                product_id: 3,
+                stock: Some(7),
+            },
+            StoreResponse::OrderStatus {
+                order: Some(Order {
+                    id: 4,
+                    items: vec![Item { id: 1, amount: 1 }],
+                    origin: OrderOrigin::Ecommerce,
+                    state: OrderState::OutForDelivery,
+                }),
+            },
+            StoreResponse::DeliveryFailed { order_id: 4 },
+            StoreResponse::Heartbeat,
+        ];
+        for response in responses {
+            let json = serde_json::to_string(&Envelope::new(9, response.clone())).unwrap();
+            let read: Envelope<StoreResponse> = serde_json::from_str(&json).unwrap();
+            assert_eq!(read.message, response, "{}", json);
+        }
+        assert_eq!(
+            serde_json::to_string(&StoreResponse::rejected_order(RejectReason::Overloaded))
+                .unwrap(),
+            r#"{"type":"order_result","accepted":false,"order_id":null,"remaining":[],"reason":{"code":"overloaded"}}"#
+        );
+    }
+
+    #[test]
+    fn unknown_messages_and_reasons_are_not_errors() {
+        let accepted: StoreResponse =
+            serde_json::from_str(r#"{"type":"order_result","accepted":true,"order_id":4}"#)
+                .unwrap();
+        assert_eq!(accepted, StoreResponse::accepted_order(4, Vec::new()));
+        let unknown: StoreResponse =
+            serde_json::from_str(r#"{"type":"rejected","reason":{"code":"on_fire"}}"#).unwrap();
+        assert_eq!(
+            unknown,
+            StoreResponse::Rejected {
+                reason: RejectReason::Unknown
+            }
+        );
+        let unknown: StoreResponse =
+            serde_json::from_str(r#"{"type":"promotion","discount":10}"#).unwrap();
+        assert_eq!(unknown, StoreResponse::Unknown);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::messages::{AdjustStock, GetBlockedOrders, GetDeliveryWorkers, QueryStock, Restock};
+use crate::store::Store;
+use actix::dev::ToEnvelope;
+use actix::{Addr, Handler, MailboxError, Message};
+use hyper::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use store_protocol::AuthToken;
+
+// Lo que necesita el servidor de administración para responder las consultas.
+//
+// Atributos:
+// * `store_addr`: Store al que se le hacen las consultas y las modificaciones.
+// * `connections`: Cantidad de conexiones abiertas con el ecommerce, la actualizan los `StoreServer`.
+// * `token`: Token del store, que cada pedido tiene que traer en `Authorization: Bearer <token>`.
+#[derive(Clone)]
+pub struct AdminState {
+    pub store_addr: Addr<Store>,
+    pub connections: Arc<AtomicUsize>,
+    pub token: Arc<AuthToken>,
+}
+
+// Cuerpo de los pedidos que modifican el stock de un producto.
+#[derive(Deserialize)]
+struct StockChange {
+    id: i32,
+    amount: i32,
+}
+
+// Levanta el servidor HTTP de administración del store en `127.0.0.1:<port>`.
+//
+// El servidor corre en el mismo sistema de actix que el store y le hace las consultas con mensajes,
+// así que nunca ve el estado del store a medio modificar. Todos los pedidos tienen que traer el token
+// del store en el header `Authorization: Bearer <token>`, si no se responden con un 401. Expone:
+// * `GET /inventory`: Stock disponible, reservado y en delivery de cada producto.
+// * `GET /orders/blocked`: Pedidos que esperan un delivery o un reintento.
+// * `GET /workers`: Qué está haciendo cada delivery.
+// * `GET /connections`: Cantidad de conexiones abiertas con el ecommerce.
+// * `POST /restock`: Suma unidades a un producto, con un cuerpo `{"id": <id>, "amount": <cantidad>}`.
+// * `POST /inventory/adjust`: Fija las unidades disponibles de un producto, con el mismo cuerpo.
+//
+// Retorna:
+// El futuro que atiende los pedidos hasta que se lo cancele, o un error si no se pudo abrir el puerto.
+pub fn serve_admin(
+    port: &str,
+    state: AdminState,
+) -> io::Result<impl Future<Output = hyper::Result<()>>> {
+    let listener = std::net::TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    listener.set_nonblocking(true)?;
+    let make_service = make_service_fn(move |_conn| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle_request(req, state.clone()))) }
+    });
+    let server = Server::from_tcp(listener).map_err(io::Error::other)?;
+    info!(port = port; "Servidor de administración escuchando");
+    Ok(server.serve(make_service))
+}
+
+// Atiende un pedido HTTP según su método y su ruta.
+async fn handle_request(
+    req: Request<Body>,
+    state: AdminState,
+) -> Result<Response<Body>, Infallible> {
+    if !is_authorized(&req, &state.token) {
+        let mut response = error_response(
+            StatusCode::UNAUTHORIZED,
+            "Falta el token del store o no es válido".to_string(),
+        );
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        return Ok(response);
+    }
+    let store_addr = &state.store_addr;
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/inventory") => ask(store_addr, QueryStock { ids: Vec::new() }).await,
+        (&Method::GET, "/orders/blocked") => ask(store_addr, GetBlockedOrders).await,
+        (&Method::GET, "/workers") => ask(store_addr, GetDeliveryWorkers).await,
+        (&Method::GET, "/connections") => json_response(
+            StatusCode::OK,
+            &json!({ "connections": state.connections.load(Ordering::SeqCst) }),
+        ),
+        (&Method::POST, "/restock") => {
+            change_stock(req, store_addr, |change| Restock {
+                id: change.id,
+                amount: change.amount,
+            })
+            .await
+        }
+        (&Method::POST, "/inventory/adjust") => {
+            change_stock(req, store_addr, |change| AdjustStock {
+                id: change.id,
+                amount: change.amount,
+            })
+            .await
+        }
+        _ => error_response(StatusCode::NOT_FOUND, "No existe el recurso".to_string()),
+    };
+    Ok(response)
+}
+
+// Indica si el pedido trae el token del store en el header `Authorization`.
+fn is_authorized(req: &Request<Body>, token: &AuthToken) -> bool {
+    req.headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|presented| token.matches(presented.trim()))
+}
+
+// Le envía una consulta al store y responde con el resultado como JSON.
+async fn ask<M>(store_addr: &Addr<Store>, msg: M) -> Response<Body>
+where
+    M: Message + Send + 'static,
+    M::Result: Serialize + Send,
+    Store: Handler<M>,
+    <Store as actix::Actor>::Context: ToEnvelope<Store, M>,
+{
+    match store_addr.send(msg).await {
+        Ok(result) => json_response(StatusCode::OK, &result),
+        Err(e) => store_unavailable(e),
+    }
+}
+
+// Lee del cuerpo del pedido el producto y la cantidad, le envía al store el mensaje que arma `msg`
+// y responde con el stock resultante.
+async fn change_stock<M>(
+    req: Request<Body>,
+    store_addr: &Addr<Store>,
+    msg: impl FnOnce(&StockChange) -> M,
+) -> Response<Body>
+where
+    M: Message<Result = Option<i32>> + Send + 'static,
+    Store: Handler<M>,
+    <Store as actix::Actor>::Context: ToEnvelope<Store, M>,
+{
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    let change: StockChange = match serde_json::from_slice(&body) {
+        Ok(change) => change,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e.to_string()),
+    };
+    match store_addr.send(msg(&change)).await {
+        Ok(Some(stock)) => {
+            json_response(StatusCode::OK, &json!({ "id": change.id, "stock": stock }))
+        }
+        Ok(None) => error_response(
+            StatusCode::BAD_REQUEST,
+            format!("Cantidad inválida: {}", change.amount),
+        ),
+        Err(e) => store_unavailable(e),
+    }
+}
+
+fn store_unavailable(e: MailboxError) -> Response<Body> {
+    error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        format!("El store no responde: {}", e),
+    )
+}
+
+fn error_response(status: StatusCode, message: String) -> Response<Body> {
+    json_response(status, &json!({ "error": message }))
+}
+
+fn json_response(status: StatusCode, value: &impl Serialize) -> Response<Body> {
+    let body = serde_json::to_string(value).unwrap_or_default();
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use crate::journal::{Journal, StoreSnapshot};
+    use crate::metrics::StoreMetrics;
+    use crate::product::Product;
+    use actix::Actor;
+    use serde_json::Value;
+
+    const TOKEN: &str = "secreto";
+
+    fn state() -> AdminState {
+        let config = StoreConfig {
+            delivery_workers: 2,
+            ..StoreConfig::default()
+        };
+        let products = [(1, Product { id: 1, amount: 4 })].into_iter().collect();
+        let store = Store::new(
+            config,
+            StoreSnapshot::new(products),
+            Arc::new(Journal::disabled()),
+            Arc::new(StoreMetrics::new()),
+        );
+        AdminState {
+            store_addr: store.start(),
+            connections: Arc::new(AtomicUsize::new(3)),
+            token: Arc::new(AuthToken::new(TOKEN)),
+        }
+    }
+
+    async fn request(
+        state: &AdminState,
+        method: Method,
+        path: &str,
+        body: &str,
+    ) -> (StatusCode, Value) {
+        request_with_token(state, method, path, body, Some(TOKEN)).await
+    }
+
+    async fn request_with_token(
+        state: &AdminState,
+        method: Method,
+        path: &str,
+        body: &str,
+        token: Option<&str>,
+    ) -> (StatusCode, Value) {
+        let mut builder = Request::builder().method(method).uri(path);
+        if let Some(token) = token {
+            builder = builder.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let req = builder.body(Body::from(body.to_string())).unwrap();
+        let response = handle_request(req, state.clone()).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn adjusts_and_restocks_inventory() {
+        let state = state();
+        let (status, body) = request(
+            &state,
+            Method::POST,
+            "/inventory/adjust",
+            r#"{"id":1,"amount":1}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({ "id": 1, "stock": 1 }));
+        let (_, body) = request(&state, Method::POST, "/restock", r#"{"id":1,"amount":5}"#).await;
+        assert_eq!(body["stock"], 6);
+
+        let (status, _) =
+            request(&state, Method::POST, "/restock", r#"{"id":1,"amount":-5}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _) = request(&state, Method::POST, "/restock", "no es json").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (_, body) = request(&state, Method::GET, "/inventory", "").await;
+        assert_eq!(
+            body,
+            json!([{ "id": 1, "available": 6, "reserved": 0, "in_delivery": 0 }])
+        );
+    }
+
+    #[actix_rt::test]
+    async fn reports_workers_and_connections() {
+        let state = state();
+        let (_, body) = request(&state, Method::GET, "/workers", "").await;
+        assert_eq!(
+            body,
+            json!([{ "state": "idle", "id": 0 }, { "state": "idle", "id": 1 }])
+        );
+        let (_, body) = request(&state, Method::GET, "/connections", "").await;
+        assert_eq!(body, json!({ "connections": 3 }));
+        let (_, body) = request(&state, Method::GET, "/orders/blocked", "").await;
+        assert_eq!(body, json!([]));
+        let (status, _) = request(&state, Method::GET, "/nada", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn rejects_requests_without_the_store_token() {
+        let state = state();
+        let change = r#"{"id":1,"amount":5}"#;
+        for token in [None, Some("otro")] {
+            let (status, _) =
+                request_with_token(&state, Method::POST, "/restock", change, token).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            let (status, _) =
+                request_with_token(&state, Method::GET, "/inventory", "", token).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        let (_, body) = request(&state, Method::GET, "/inventory", "").await;
+        assert_eq!(body[0]["available"], 4);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Intentos de autenticación (fallidos o en curso) que se permiten por dirección sin esperar turno.
+pub const MAX_AUTH_FAILURES: usize = 5;
+
+// Ventana en la que se cuentan los intentos fallidos.
+pub const AUTH_FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+// Tiempo entre un intento y el siguiente de una dirección que ya usó los que tenía sin esperar.
+pub const AUTH_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// Lo máximo que una conexión espera su turno para autenticarse. Si le tocaría más tarde se cierra.
+pub const MAX_AUTH_WAIT: Duration = Duration::from_secs(5);
+
+// Limita los intentos de autenticación por dirección IP, para que no se pueda adivinar el token
+// probando muchas veces.
+//
+// Mientras una dirección tenga menos de `max_failures` intentos fallidos recientes más intentos en
+// curso, sus conexiones se autentican enseguida. Después cada conexión nueva tiene que esperar su
+// turno, uno cada `retry_interval`, así que por más conexiones que abra a la vez no puede probar más
+// rápido que eso. No se bloquea la dirección: como el store solo escucha en `127.0.0.1` todos los
+// clientes la comparten, y un cliente que conoce el token igual entra después de una ráfaga de
+// intentos fallidos, solo que esperando su turno.
+//
+// Atributos:
+// * `max_failures`: Intentos fallidos o en curso a partir de los cuales hay que esperar turno.
+// * `window`: Tiempo durante el cual se recuerda cada intento fallido.
+// * `retry_interval`: Tiempo entre los turnos de una dirección.
+// * `max_wait`: Lo máximo que puede esperar su turno una conexión.
+// * `addresses`: Intentos de cada dirección.
+pub struct AuthLimiter {
+    max_failures: usize,
+    window: Duration,
+    retry_interval: Duration,
+    max_wait: Duration,
+    addresses: Mutex<HashMap<IpAddr, AddressAttempts>>,
+}
+
+// Intentos de autenticación de una dirección.
+//
+// Atributos:
+// * `failures`: Momentos de los intentos fallidos recientes.
+// * `pending`: Intentos en curso, que todavía no terminaron el saludo.
+// * `next_turn`: Próximo turno libre, si la dirección tuvo que esperar turno.
+#[derive(Default)]
+struct AddressAttempts {
+    failures: VecDeque<Instant>,
+    pending: usize,
+    next_turn: Option<Instant>,
+}
+
+// Intento de autenticación reservado con `AuthLimiter::reserve`. Deja de estar en curso cuando se
+// descarta, y solo cuenta como fallido si se llamó a `failed`: una conexión que se corta o no termina
+// el saludo a tiempo no probó ningún token.
+//
+// Atributos:
+// * `limiter`: Limitador que reservó el intento.
+// * `ip`: Dirección del cliente.
+// * `wait`: Tiempo que tiene que esperar el intento su turno antes de empezar el saludo.
+// * `failed`: Si el cliente respondió mal el desafío.
+pub struct AuthAttempt {
+    limiter: Arc<AuthLimiter>,
+    ip: IpAddr,
+    wait: Duration,
+    failed: bool,
+}
+
+impl AuthLimiter {
+    pub fn new(
+        max_failures: usize,
+        window: Duration,
+        retry_interval: Duration,
+        max_wait: Duration,
+    ) -> AuthLimiter {
+        AuthLimiter {
+            max_failures,
+            window,
+            retry_interval,
+            max_wait,
+            addresses: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Reserva un intento de autenticación para la dirección, antes de enviarle el desafío. Revisar
+    // cuántos intentos tiene y anotar este se hace con un solo lock, así varias conexiones simultáneas
+    // de la misma dirección no consiguen más intentos que una sola.
+    //
+    // Retorna:
+    // El intento reservado, o `None` si su turno llegaría después de `max_wait`.
+    pub fn reserve(self: &Arc<Self>, ip: IpAddr) -> Option<AuthAttempt> {
+        let wait = self.reserve_at(ip, Instant::now())?;
+        Some(AuthAttempt {
+            limiter: self.clone(),
+            ip,
+            wait,
+            failed: false,
+        })
+    }
+
+    fn reserve_at(&self, ip: IpAddr, now: Instant) -> Option<Duration> {
+        let mut addresses = self.lock();
+        self.forget_idle(&mut addresses, now);
+        let attempts = addresses.entry(ip).or_default();
+        let wait = if attempts.failures.len() + attempts.pending < self.max_failures {
+            Duration::ZERO
+        } else {
+            let turn = attempts.next_turn.map_or(now, |next| next.max(now));
+            let wait = turn - now;
+            if wait > self.max_wait {
+                return None;
+            }
+            attempts.next_turn = Some(turn + self.retry_interval);
+            wait
+        };
+        attempts.pending += 1;
+        Some(wait)
+    }
+
+    // Termina un intento en curso de la dirección, anotándolo como fallido si el cliente respondió mal.
+    fn finish_at(&self, ip: IpAddr, failed: bool, now: Instant) {
+        let mut addresses = self.lock();
+        if let Some(attempts) = addresses.get_mut(&ip) {
+            attempts.pending = attempts.pending.saturating_sub(1);
+            if failed {
+                attempts.failures.push_back(now);
+            }
+        }
+    }
+
+    // Olvida los intentos fallidos viejos de todas las direcciones, y las direcciones que se quedan
+    // sin intentos, para que no se acumulen las que no vuelven a conectarse.
+    fn forget_idle(&self, addresses: &mut HashMap<IpAddr, AddressAttempts>, now: Instant) {
+        addresses.retain(|_, attempts| {
+            while let Some(first) = attempts.failures.front() {
+                if now.duration_since(*first) < self.window {
+                    break;
+                }
+                attempts.failures.pop_front();
+            }
+            let waiting = attempts.next_turn.is_some_and(|next| next > now);
+            !attempts.failures.is_empty() || attempts.pending > 0 || waiting
+        });
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, AddressAttempts>> {
+        self.addresses.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for AuthLimiter {
+    fn default() -> AuthLimiter {
+        AuthLimiter::new(
+            MAX_AUTH_FAILURES,
+            AUTH_FAILURE_WINDOW,
+            AUTH_RETRY_INTERVAL,
+            MAX_AUTH_WAIT,
+        )
+    }
+}
+
+impl AuthAttempt {
+    // Tiempo que tiene que esperar el intento su turno antes de empezar el saludo.
+    pub fn wait(&self) -> Duration {
+        self.wait
+    }
+
+    // Anota el intento como fallido, porque el cliente respondió mal el desafío.
+    pub fn failed(mut self) {
+        self.failed = true;
+    }
+}
+
+impl Drop for AuthAttempt {
+    fn drop(&mut self) {
+        self.limiter.finish_at(self.ip, self.failed, Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    fn limiter() -> AuthLimiter {
+        AuthLimiter::new(3, Duration::from_secs(60), SECOND, 2 * SECOND)
+    }
+
+    #[test]
+    fn concurrent_attempts_wait_their_turn() {
+        let limiter = limiter();
+        let attacker: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let start = Instant::now();
+
+        // Los intentos en curso ya cuentan, aunque todavía no se sepa si fallaron
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve_at(attacker, start), Some(Duration::ZERO));
+        }
+        assert_eq!(limiter.reserve_at(attacker, start), Some(Duration::ZERO));
+        assert_eq!(limiter.reserve_at(attacker, start), Some(SECOND));
+        assert_eq!(limiter.reserve_at(attacker, start), Some(2 * SECOND));
+        assert_eq!(limiter.reserve_at(attacker, start), None);
+        assert_eq!(limiter.reserve_at(other, start), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn a_legitimate_client_gets_in_right_after_a_burst_of_failures() {
+        let limiter = limiter();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        // Alguien prueba tokens equivocados y otros se quedan colgados sin terminar el saludo
+        for _ in 0..3 {
+            limiter.reserve_at(local, start).unwrap();
+            limiter.finish_at(local, true, start);
+        }
+        for _ in 0..2 {
+            limiter.reserve_at(local, start + SECOND).unwrap();
+            limiter.finish_at(local, false, start + 3 * SECOND);
+        }
+        assert_eq!(limiter.lock()[&local].failures.len(), 3);
+
+        // El ecommerce llega justo después y entra esperando su turno
+        let wait = limiter.reserve_at(local, start + 3 * SECOND).unwrap();
+        assert!(wait <= SECOND);
+        limiter.finish_at(local, false, start + 3 * SECOND);
+    }
+
+    #[test]
+    fn forgets_addresses_that_do_not_come_back() {
+        let limiter = limiter();
+        let start = Instant::now();
+        for last in 1..=50u8 {
+            let ip = IpAddr::from([10, 0, 0, last]);
+            limiter.reserve_at(ip, start).unwrap();
+            limiter.finish_at(ip, true, start);
+        }
+        assert_eq!(limiter.lock().len(), 50);
+
+        let other: IpAddr = "10.0.1.1".parse().unwrap();
+        limiter
+            .reserve_at(other, start + Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(limiter.lock().len(), 1);
+    }
+}
@@ -0,0 +1,358 @@
+use crate::config::{ConfigError, DeliveryTime, StoreConfig};
+use crate::supplier::SupplierConfig;
+use log::LevelFilter;
+use logger::LogFormat;
+use order_file::ErrorPolicy;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Unidades que repone el proveedor por producto si no se indica `--reorder-amount`.
+const DEFAULT_REORDER_AMOUNT: i32 = 10;
+
+// Mensaje de uso del binario.
+pub const USAGE: &str = "Uso: cargo run <puerto> <orders_file.csv> [opciones]
+Opciones:
+  --inventory <archivo.csv|json>      Stock inicial
+  --on-error <skip|abort|file:<ruta>> Qué hacer con las líneas inválidas del archivo de pedidos
+  --journal <archivo>                 Journal para recuperar el estado ante una caída
+  --supplier-interval <segundos>      Activa el proveedor
+  --reorder-point <cantidad>          Solo repone productos con menos unidades que esto
+  --reorder-amount <cantidad>         Unidades que repone el proveedor por producto
+  --config <archivo.json>             Configuración de la simulación
+  --delivery-workers <cantidad>       Cantidad de deliveries
+  --delivery-probability <p>          Probabilidad de que una entrega salga bien
+  --delivery-time <distribución>      fixed:<s>, uniform:<min>:<max> o exponential:<media>
+  --delivery-attempts <cantidad>      Intentos de entrega antes de devolver el pedido al stock
+  --retry-backoff <segundos>          Espera antes del primer reintento, se duplica en cada uno
+  --retry-other-worker                Cada reintento lo hace un delivery distinto
+  --aging <segundos>                  Espera tras la cual un pedido estándar pasa a express
+  --heartbeat-timeout <segundos>      Tiempo sin recibir nada tras el cual se cierra una conexión
+  --auth-token-file <archivo>         Token que deben presentar los clientes para conectarse
+  --admin-port <puerto>               Activa el servidor HTTP de administración (requiere token)
+  --metrics-port <puerto>             Expone las métricas en formato Prometheus
+  --log-format <text|json>            Formato de los logs (text por defecto)
+  --log-level <nivel>                 error, warn, info (por defecto), debug o trace
+  --seed <número>                     Semilla para repetir la misma simulación
+  --virtual-time                      Usa un reloj virtual: las esperas no pasan en tiempo real";
+
+// Argumentos de linea de comandos del store.
+//
+// Atributos:
+// * `port`: Puerto en el que escucha conexiones del ecommerce.
+// * `orders_file`: Archivo con los pedidos locales.
+// * `order_errors`: Qué hacer con las líneas inválidas del archivo de pedidos.
+// * `inventory_file`: Archivo opcional con el stock inicial.
+// * `journal_file`: Archivo opcional donde se registran las modificaciones del stock.
+// * `supplier`: Configuración del proveedor, si se lo activó con `--supplier-interval`.
+// * `config_file`: Archivo opcional con la configuración de la simulación.
+// * `delivery_workers`, `delivery_probability`, `delivery_time`, `delivery_attempts`, `retry_backoff`,
+//   `retry_other_worker`, `standard_aging`, `heartbeat_timeout`, `seed`, `virtual_time`: Valores que
+//   pisan los del archivo de configuración.
+// * `auth_token_file`: Archivo opcional con el token que deben presentar los clientes.
+// * `admin_port`: Puerto del servidor HTTP de administración, si se lo activó con `--admin-port`.
+//   Solo se acepta junto con `auth_token_file`.
+// * `metrics_port`: Puerto en el que se exponen las métricas, si se lo activó con `--metrics-port`.
+// * `log_format`, `log_level`: Formato y nivel mínimo de los logs.
+#[derive(Debug)]
+pub struct StoreArgs {
+    pub port: String,
+    pub orders_file: PathBuf,
+    pub order_errors: ErrorPolicy,
+    pub inventory_file: Option<PathBuf>,
+    pub journal_file: Option<PathBuf>,
+    pub supplier: Option<SupplierConfig>,
+    pub config_file: Option<PathBuf>,
+    pub delivery_workers: Option<usize>,
+    pub delivery_probability: Option<f64>,
+    pub delivery_time: Option<DeliveryTime>,
+    pub delivery_attempts: Option<u32>,
+    pub retry_backoff: Option<f64>,
+    pub retry_other_worker: bool,
+    pub standard_aging: Option<f64>,
+    pub heartbeat_timeout: Option<f64>,
+    pub seed: Option<u64>,
+    pub virtual_time: bool,
+    pub auth_token_file: Option<PathBuf>,
+    pub admin_port: Option<String>,
+    pub metrics_port: Option<String>,
+    pub log_format: LogFormat,
+    pub log_level: LevelFilter,
+}
+
+impl StoreArgs {
+    // Arma la configuración del store: parte del archivo de configuración (o de los valores
+    // por defecto si no se indicó) y le aplica las opciones de línea de comandos.
+    pub fn store_config(&self) -> Result<StoreConfig, ConfigError> {
+        let mut config = match &self.config_file {
+            Some(config_file) => StoreConfig::load(config_file)?,
+            None => StoreConfig::default(),
+        };
+        if let Some(workers) = self.delivery_workers {
+            config.delivery_workers = workers;
+        }
+        if let Some(probability) = self.delivery_probability {
+            config.delivery_success_probability = probability;
+        }
+        if let Some(delivery_time) = self.delivery_time {
+            config.delivery_time = delivery_time;
+        }
+        if let Some(attempts) = self.delivery_attempts {
+            config.delivery_retry.max_attempts = attempts;
+        }
+        if let Some(backoff) = self.retry_backoff {
+            config.delivery_retry.initial_backoff_secs = backoff;
+            config.delivery_retry.max_backoff_secs =
+                config.delivery_retry.max_backoff_secs.max(backoff);
+        }
+        if self.retry_other_worker {
+            config.delivery_retry.different_worker = true;
+        }
+        if let Some(aging) = self.standard_aging {
+            config.standard_aging_secs = aging;
+        }
+        if let Some(timeout) = self.heartbeat_timeout {
+            config.heartbeat_timeout_secs = timeout;
+        }
+        if let Some(seed) = self.seed {
+            config.simulation.seed = Some(seed);
+        }
+        if self.virtual_time {
+            config.simulation.virtual_time = true;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+// Parsea los argumentos recibidos (sin incluir el nombre del programa).
+//
+// Los dos primeros argumentos posicionales son el puerto y el archivo de pedidos,
+// el resto son opciones de la forma `--nombre <valor>`.
+//
+// Retorna:
+// Los argumentos parseados o un mensaje de error si falta alguno o hay una opción desconocida.
+pub fn parse_args(args: &[String]) -> Result<StoreArgs, String> {
+    let mut positional = Vec::new();
+    let mut order_errors = ErrorPolicy::Skip;
+    let mut inventory_file = None;
+    let mut journal_file = None;
+    let mut supplier_interval = None;
+    let mut reorder_point = None;
+    let mut reorder_amount = None;
+    let mut config_file = None;
+    let mut delivery_workers = None;
+    let mut delivery_probability = None;
+    let mut delivery_time = None;
+    let mut delivery_attempts = None;
+    let mut retry_backoff = None;
+    let mut retry_other_worker = false;
+    let mut standard_aging = None;
+    let mut heartbeat_timeout = None;
+    let mut seed = None;
+    let mut virtual_time = false;
+    let mut auth_token_file = None;
+    let mut admin_port = None;
+    let mut metrics_port = None;
+    let mut log_format = LogFormat::Text;
+    let mut log_level = LevelFilter::Info;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--on-error" => order_errors = next_value(&mut iter, arg)?.parse()?,
+            "--inventory" => inventory_file = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--journal" => journal_file = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--supplier-interval" => {
+                supplier_interval = Some(parse_value::<u64>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--reorder-point" => {
+                reorder_point = Some(parse_value::<i32>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--reorder-amount" => {
+                reorder_amount = Some(parse_value::<i32>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--config" => config_file = Some(PathBuf::from(next_value(&mut iter, arg)?)),
+            "--delivery-workers" => {
+                delivery_workers = Some(parse_value::<usize>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--delivery-probability" => {
+                delivery_probability = Some(parse_value::<f64>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--delivery-time" => delivery_time = Some(next_value(&mut iter, arg)?.parse()?),
+            "--delivery-attempts" => {
+                delivery_attempts = Some(parse_value::<u32>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--retry-backoff" => {
+                retry_backoff = Some(parse_value::<f64>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--retry-other-worker" => retry_other_worker = true,
+            "--aging" => {
+                standard_aging = Some(parse_value::<f64>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--heartbeat-timeout" => {
+                heartbeat_timeout = Some(parse_value::<f64>(next_value(&mut iter, arg)?, arg)?)
+            }
+            "--seed" => seed = Some(parse_value::<u64>(next_value(&mut iter, arg)?, arg)?),
+            "--virtual-time" => virtual_time = true,
+            "--auth-token-file" => {
+                auth_token_file = Some(PathBuf::from(next_value(&mut iter, arg)?))
+            }
+            "--admin-port" => {
+                admin_port = Some(parse_value::<u16>(next_value(&mut iter, arg)?, arg)?.to_string())
+            }
+            "--metrics-port" => {
+                metrics_port =
+                    Some(parse_value::<u16>(next_value(&mut iter, arg)?, arg)?.to_string())
+            }
+            "--log-format" => log_format = next_value(&mut iter, arg)?.parse()?,
+            "--log-level" => log_level = parse_value(next_value(&mut iter, arg)?, arg)?,
+            option if option.starts_with("--") => {
+                return Err(format!("Opción desconocida: {}", option))
+            }
+            value => positional.push(value.to_string()),
+        }
+    }
+
+    if positional.len() != 2 {
+        return Err("Se esperan el puerto y el archivo de pedidos".to_string());
+    }
+
+    if admin_port.is_some() && auth_token_file.is_none() {
+        return Err("--admin-port requiere --auth-token-file".to_string());
+    }
+
+    let supplier = match supplier_interval {
+        Some(0) => return Err("El intervalo del proveedor debe ser mayor a 0".to_string()),
+        Some(seconds) => {
+            let reorder_amount = reorder_amount.unwrap_or(DEFAULT_REORDER_AMOUNT);
+            if reorder_amount <= 0 {
+                return Err("La cantidad a reponer debe ser mayor a 0".to_string());
+            }
+            Some(SupplierConfig {
+                interval: Duration::from_secs(seconds),
+                reorder_point,
+                reorder_amount,
+            })
+        }
+        None if reorder_point.is_some() || reorder_amount.is_some() => {
+            return Err(
+                "--reorder-point y --reorder-amount requieren --supplier-interval".to_string(),
+            )
+        }
+        None => None,
+    };
+
+    Ok(StoreArgs {
+        port: positional[0].clone(),
+        orders_file: PathBuf::from(format!("./{}", positional[1])),
+        order_errors,
+        inventory_file,
+        journal_file,
+        supplier,
+        config_file,
+        delivery_workers,
+        delivery_probability,
+        delivery_time,
+        delivery_attempts,
+        retry_backoff,
+        retry_other_worker,
+        standard_aging,
+        heartbeat_timeout,
+        seed,
+        virtual_time,
+        auth_token_file,
+        admin_port,
+        metrics_port,
+        log_format,
+        log_level,
+    })
+}
+
+// Obtiene el valor que acompaña a una opción.
+fn next_value<'a>(
+    iter: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<&'a String, String> {
+    iter.next()
+        .ok_or_else(|| format!("Falta el valor de la opción {}", option))
+}
+
+// Parsea el valor numérico de una opción.
+fn parse_value<T: FromStr>(value: &str, option: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Valor inválido para la opción {}: {}", option, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_supplier_options() {
+        let parsed = parse_args(&args(&[
+            "8080",
+            "orders.csv",
+            "--supplier-interval",
+            "30",
+            "--reorder-point",
+            "3",
+        ]))
+        .unwrap();
+        assert_eq!(
+            parsed.supplier,
+            Some(SupplierConfig {
+                interval: Duration::from_secs(30),
+                reorder_point: Some(3),
+                reorder_amount: DEFAULT_REORDER_AMOUNT,
+            })
+        );
+    }
+
+    #[test]
+    fn cli_options_override_the_default_config() {
+        let parsed = parse_args(&args(&[
+            "8080",
+            "orders.csv",
+            "--delivery-workers",
+            "2",
+            "--delivery-time",
+            "fixed:1",
+        ]))
+        .unwrap();
+        let config = parsed.store_config().unwrap();
+        assert_eq!(config.delivery_workers, 2);
+        assert_eq!(config.delivery_time, DeliveryTime::Fixed { secs: 1.0 });
+        assert_eq!(
+            config.delivery_success_probability,
+            StoreConfig::default().delivery_success_probability
+        );
+    }
+
+    #[test]
+    fn the_admin_server_requires_a_token() {
+        let result = parse_args(&args(&["8080", "orders.csv", "--admin-port", "9000"]));
+        assert!(result.is_err());
+        let parsed = parse_args(&args(&[
+            "8080",
+            "orders.csv",
+            "--admin-port",
+            "9000",
+            "--auth-token-file",
+            "token.txt",
+        ]))
+        .unwrap();
+        assert_eq!(parsed.admin_port.as_deref(), Some("9000"));
+    }
+
+    #[test]
+    fn reorder_options_require_a_supplier() {
+        let result = parse_args(&args(&["8080", "orders.csv", "--reorder-amount", "5"]));
+        assert!(result.is_err());
+    }
+}
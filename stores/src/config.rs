@@ -0,0 +1,344 @@
+use crate::simulation::Simulation;
+use rand::Rng;
+use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Errores posibles al cargar la configuración del store.
+//
+// Variantes:
+// * `Io`: No se pudo abrir o leer el archivo.
+// * `Json`: El archivo JSON tiene un formato inválido.
+// * `Invalid`: Algún valor de la configuración está fuera de rango.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "no se pudo leer el archivo: {}", e),
+            ConfigError::Json(e) => write!(f, "JSON inválido: {}", e),
+            ConfigError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(e: serde_json::Error) -> Self {
+        ConfigError::Json(e)
+    }
+}
+
+// Distribución del tiempo que tarda un delivery en entregar un pedido, en segundos.
+//
+// Variantes:
+// * `Fixed`: Siempre tarda `secs`.
+// * `Uniform`: Tarda un tiempo uniforme en el rango [`min_secs`, `max_secs`).
+// * `Exponential`: Tarda un tiempo con distribución exponencial de media `mean_secs`.
+//
+// Desde la línea de comandos se escribe como `fixed:<secs>`, `uniform:<min>:<max>` o `exponential:<media>`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum DeliveryTime {
+    Fixed { secs: f64 },
+    Uniform { min_secs: f64, max_secs: f64 },
+    Exponential { mean_secs: f64 },
+}
+
+impl DeliveryTime {
+    // Sortea cuánto va a tardar una entrega.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        let secs = match *self {
+            DeliveryTime::Fixed { secs } => secs,
+            DeliveryTime::Uniform { min_secs, max_secs } if min_secs < max_secs => {
+                rng.gen_range(min_secs..max_secs)
+            }
+            DeliveryTime::Uniform { min_secs, .. } => min_secs,
+            DeliveryTime::Exponential { mean_secs } => {
+                let u: f64 = rng.gen();
+                -mean_secs * (1.0 - u).ln()
+            }
+        };
+        Duration::from_secs_f64(secs)
+    }
+
+    // Los tiempos tienen que ser finitos: con `inf` o `NaN` no se puede armar la espera del delivery.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let valid = match *self {
+            DeliveryTime::Fixed { secs } => secs.is_finite() && secs >= 0.0,
+            DeliveryTime::Uniform { min_secs, max_secs } => {
+                min_secs.is_finite()
+                    && max_secs.is_finite()
+                    && min_secs >= 0.0
+                    && min_secs <= max_secs
+            }
+            DeliveryTime::Exponential { mean_secs } => mean_secs.is_finite() && mean_secs > 0.0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(format!(
+                "tiempo de delivery inválido: {:?}",
+                self
+            )))
+        }
+    }
+}
+
+impl FromStr for DeliveryTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let number = |value: &str| {
+            value
+                .parse::<f64>()
+                .map_err(|_| format!("tiempo de delivery inválido: {}", s))
+        };
+        match parts.as_slice() {
+            ["fixed", secs] => Ok(DeliveryTime::Fixed {
+                secs: number(secs)?,
+            }),
+            ["uniform", min, max] => Ok(DeliveryTime::Uniform {
+                min_secs: number(min)?,
+                max_secs: number(max)?,
+            }),
+            ["exponential", mean] => Ok(DeliveryTime::Exponential {
+                mean_secs: number(mean)?,
+            }),
+            _ => Err(format!(
+                "tiempo de delivery inválido: {} (se espera fixed:<s>, uniform:<min>:<max> o exponential:<media>)",
+                s
+            )),
+        }
+    }
+}
+
+// Política de reintentos de las entregas que fallan.
+//
+// Atributos:
+// * `max_attempts`: Cantidad máxima de intentos de entrega de un pedido, contando el primero.
+// * `initial_backoff_secs`: Espera antes del primer reintento.
+// * `backoff_multiplier`: Por cuánto se multiplica la espera en cada reintento.
+// * `max_backoff_secs`: Espera máxima entre reintentos.
+// * `different_worker`: Si cada reintento lo tiene que hacer un delivery distinto al del intento anterior.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff_secs: f64,
+    pub backoff_multiplier: f64,
+    pub max_backoff_secs: f64,
+    pub different_worker: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff_secs: 1.0,
+            backoff_multiplier: 2.0,
+            max_backoff_secs: 30.0,
+            different_worker: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // Cuánto esperar antes de reintentar un pedido que ya falló `failed_attempts` veces.
+    pub fn backoff(&self, failed_attempts: u32) -> Duration {
+        let exponent = failed_attempts.saturating_sub(1) as i32;
+        let secs = self.initial_backoff_secs * self.backoff_multiplier.powi(exponent);
+        Duration::from_secs_f64(secs.min(self.max_backoff_secs))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_attempts == 0 {
+            return Err(ConfigError::Invalid(
+                "tiene que haber al menos un intento de entrega".to_string(),
+            ));
+        }
+        let finite = self.initial_backoff_secs.is_finite()
+            && self.backoff_multiplier.is_finite()
+            && self.max_backoff_secs.is_finite();
+        if !finite
+            || self.initial_backoff_secs < 0.0
+            || self.backoff_multiplier < 1.0
+            || self.max_backoff_secs < self.initial_backoff_secs
+        {
+            return Err(ConfigError::Invalid(format!(
+                "política de reintentos inválida: {:?}",
+                self
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Configuración de la simulación de un store.
+//
+// Atributos:
+// * `delivery_workers`: Cantidad de deliveries que entregan pedidos en paralelo.
+// * `delivery_success_probability`: Probabilidad de que una entrega salga bien.
+// * `delivery_time`: Distribución del tiempo que tarda cada entrega.
+// * `delivery_retry`: Qué hacer cuando una entrega falla.
+// * `standard_aging_secs`: Espera a partir de la cual un pedido estándar pasa a express.
+// * `heartbeat_timeout_secs`: Tiempo sin recibir nada de un cliente tras el cual se da su conexión
+//   por muerta y se cierra.
+// * `simulation`: Semilla y reloj de la simulación.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    pub delivery_workers: usize,
+    pub delivery_success_probability: f64,
+    pub delivery_time: DeliveryTime,
+    pub delivery_retry: RetryPolicy,
+    pub standard_aging_secs: f64,
+    pub heartbeat_timeout_secs: f64,
+    pub simulation: Simulation,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig {
+            delivery_workers: 5,
+            delivery_success_probability: 0.8,
+            delivery_time: DeliveryTime::Uniform {
+                min_secs: 5.0,
+                max_secs: 10.0,
+            },
+            delivery_retry: RetryPolicy::default(),
+            standard_aging_secs: 30.0,
+            heartbeat_timeout_secs: 30.0,
+            simulation: Simulation::default(),
+        }
+    }
+}
+
+impl StoreConfig {
+    // Carga la configuración de un archivo JSON. Los campos que no estén en el archivo
+    // toman su valor por defecto.
+    pub fn load(path: &Path) -> Result<StoreConfig, ConfigError> {
+        let config: StoreConfig = serde_json::from_reader(File::open(path)?)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Verifica que los valores de la configuración estén dentro de rango.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.delivery_workers == 0 {
+            return Err(ConfigError::Invalid(
+                "tiene que haber al menos un delivery".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.delivery_success_probability) {
+            return Err(ConfigError::Invalid(format!(
+                "la probabilidad de entrega debe estar entre 0 y 1: {}",
+                self.delivery_success_probability
+            )));
+        }
+        if !self.standard_aging_secs.is_finite() || self.standard_aging_secs < 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "el tiempo de aging no puede ser negativo: {}",
+                self.standard_aging_secs
+            )));
+        }
+        if !self.heartbeat_timeout_secs.is_finite() || self.heartbeat_timeout_secs <= 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "el tiempo sin heartbeats tiene que ser positivo: {}",
+                self.heartbeat_timeout_secs
+            )));
+        }
+        self.delivery_time.validate()?;
+        self.delivery_retry.validate()
+    }
+
+    // Tiempo sin recibir nada de un cliente tras el cual se cierra su conexión.
+    pub fn heartbeat_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.heartbeat_timeout_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_fields_take_default_values() {
+        let config: StoreConfig = serde_json::from_str(
+            r#"{"delivery_workers": 2, "delivery_time": {"distribution": "fixed", "secs": 0.5}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.delivery_workers, 2);
+        assert_eq!(config.delivery_success_probability, 0.8);
+        assert_eq!(config.delivery_time, DeliveryTime::Fixed { secs: 0.5 });
+    }
+
+    #[test]
+    fn parses_delivery_time_from_cli() {
+        assert_eq!(
+            "uniform:1:3".parse::<DeliveryTime>().unwrap(),
+            DeliveryTime::Uniform {
+                min_secs: 1.0,
+                max_secs: 3.0
+            }
+        );
+        assert!("normal:3".parse::<DeliveryTime>().is_err());
+        let invalid = StoreConfig {
+            delivery_success_probability: 1.5,
+            ..StoreConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+        let invalid = StoreConfig {
+            heartbeat_timeout_secs: 0.0,
+            ..StoreConfig::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn rejects_times_that_are_not_finite() {
+        for delivery_time in ["fixed:inf", "uniform:1:inf", "exponential:NaN"] {
+            let config = StoreConfig {
+                delivery_time: delivery_time.parse().unwrap(),
+                ..StoreConfig::default()
+            };
+            assert!(config.validate().is_err(), "{}", delivery_time);
+        }
+        let config = StoreConfig {
+            standard_aging_secs: f64::INFINITY,
+            ..StoreConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            initial_backoff_secs: 1.0,
+            backoff_multiplier: 2.0,
+            max_backoff_secs: 5.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(4), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,93 @@
+use crate::config::DeliveryTime;
+use crate::messages::{Deliver, DeliveryFinished, StopDelivery};
+use crate::product::describe_items;
+use actix::{
+    Actor, ActorContext, ActorFutureExt, AsyncContext, Context, Handler, Recipient, WrapFuture,
+};
+use log::info;
+use rand::distributions::{Bernoulli, Distribution};
+use rand::rngs::StdRng;
+use sim_core::Clock;
+
+// Actor que simula un delivery del store.
+//
+// Recibe de a un pedido por vez con `Deliver` (todos sus productos van en un mismo envío), espera el
+// tiempo de entrega sorteado sin bloquear el thread y le avisa al store con `DeliveryFinished` si lo
+// pudo entregar o no.
+//
+// Atributos:
+// * `id`: Número de delivery, se usa para identificarlo en el store y en los logs.
+// * `store`: A quién avisarle el resultado de cada entrega.
+// * `bernoulli_dist`: Decide si una entrega sale bien.
+// * `delivery_time`: Distribución del tiempo que tarda cada entrega.
+// * `rng`: Generador con el que se sortean el tiempo y el resultado de las entregas.
+// * `clock`: Reloj de la simulación con el que se espera el tiempo de entrega.
+pub struct DeliveryWorker {
+    id: usize,
+    store: Recipient<DeliveryFinished>,
+    bernoulli_dist: Bernoulli,
+    delivery_time: DeliveryTime,
+    rng: StdRng,
+    clock: Clock,
+}
+
+impl DeliveryWorker {
+    pub fn new(
+        id: usize,
+        store: Recipient<DeliveryFinished>,
+        bernoulli_dist: Bernoulli,
+        delivery_time: DeliveryTime,
+        rng: StdRng,
+        clock: Clock,
+    ) -> DeliveryWorker {
+        DeliveryWorker {
+            id,
+            store,
+            bernoulli_dist,
+            delivery_time,
+            rng,
+            clock,
+        }
+    }
+}
+
+impl Actor for DeliveryWorker {
+    type Context = Context<Self>;
+}
+
+impl Handler<Deliver> for DeliveryWorker {
+    type Result = ();
+
+    fn handle(&mut self, msg: Deliver, ctx: &mut Self::Context) -> Self::Result {
+        let order = msg.order;
+        info!(worker = self.id, order_id = order.order_id; "Comenzamos el delivery del pedido");
+        let delivery_time = self.delivery_time.sample(&mut self.rng);
+        let delivery = self.clock.sleep(delivery_time).into_actor(self);
+        ctx.spawn(delivery.map(move |_, act, _ctx| {
+            // Decidir si se resuelve el envio  o no
+            let delivered = act.bernoulli_dist.sample(&mut act.rng);
+            if delivered {
+                info!(
+                    worker = act.id, order_id = order.order_id, items:% = describe_items(&order.items);
+                    "Se pudo entregar correctamente el pedido"
+                );
+            } else {
+                info!(worker = act.id, order_id = order.order_id; "No se pudo entregar el pedido");
+            }
+            act.store.do_send(DeliveryFinished {
+                worker: act.id,
+                order,
+                delivered,
+            });
+        }));
+    }
+}
+
+impl Handler<StopDelivery> for DeliveryWorker {
+    type Result = ();
+
+    fn handle(&mut self, _msg: StopDelivery, ctx: &mut Self::Context) -> Self::Result {
+        info!(worker = self.id; "Termino");
+        ctx.stop();
+    }
+}
@@ -0,0 +1,160 @@
+use crate::order::{BlockedOrder, Priority};
+use log::info;
+use sim_core::Clock;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Pedido esperando en la cola junto con el momento (según el reloj de la simulación) en que entró.
+struct QueuedOrder {
+    order: BlockedOrder,
+    enqueued_at: Duration,
+}
+
+// Cola de pedidos a la espera de un delivery.
+//
+// Los pedidos express salen antes que los estándar y dentro de cada clase se respeta el orden
+// de llegada. Para que los pedidos estándar no esperen para siempre, los que llevan más de
+// `aging` en la cola se promueven a express (ubicándose según el momento en que entraron).
+//
+// Atributos:
+// * `express`: Pedidos express y estándar promovidos, ordenados por llegada.
+// * `standard`: Pedidos estándar, ordenados por llegada.
+// * `aging`: Tiempo de espera a partir del cual un pedido estándar se promueve.
+// * `clock`: Reloj de la simulación con el que se mide la espera.
+pub struct DeliveryQueue {
+    express: VecDeque<QueuedOrder>,
+    standard: VecDeque<QueuedOrder>,
+    aging: Duration,
+    clock: Clock,
+}
+
+impl DeliveryQueue {
+    pub fn new(aging: Duration, clock: Clock) -> DeliveryQueue {
+        DeliveryQueue {
+            express: VecDeque::new(),
+            standard: VecDeque::new(),
+            aging,
+            clock,
+        }
+    }
+
+    // Agrega un pedido al final de la cola de su prioridad.
+    pub fn push(&mut self, order: BlockedOrder) {
+        let queued = QueuedOrder {
+            enqueued_at: self.clock.now(),
+            order,
+        };
+        match queued.order.priority {
+            Priority::Express => self.express.push_back(queued),
+            Priority::Standard => self.standard.push_back(queued),
+        }
+    }
+
+    // Saca el primer pedido, en orden de prioridad, que cumpla `can_take`.
+    //
+    // Argumentos:
+    // * `can_take`: Indica si hay algún delivery que pueda tomar el pedido.
+    pub fn pop_first(
+        &mut self,
+        mut can_take: impl FnMut(&BlockedOrder) -> bool,
+    ) -> Option<BlockedOrder> {
+        self.promote_aged();
+        for queue in [&mut self.express, &mut self.standard] {
+            if let Some(index) = queue.iter().position(|queued| can_take(&queued.order)) {
+                return queue.remove(index).map(|queued| queued.order);
+            }
+        }
+        None
+    }
+
+    // Saca todos los pedidos de la cola.
+    pub fn drain(&mut self) -> Vec<BlockedOrder> {
+        self.express
+            .drain(..)
+            .chain(self.standard.drain(..))
+            .map(|queued| queued.order)
+            .collect()
+    }
+
+    // Recorre los pedidos de la cola sin sacarlos, primero los express.
+    pub fn iter(&self) -> impl Iterator<Item = &BlockedOrder> {
+        self.express
+            .iter()
+            .chain(self.standard.iter())
+            .map(|queued| &queued.order)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.express.len() + self.standard.len()
+    }
+
+    // Pasa a la cola express los pedidos estándar que esperaron más que `aging`.
+    fn promote_aged(&mut self) {
+        let now = self.clock.now();
+        while let Some(oldest) = self.standard.front() {
+            if now - oldest.enqueued_at < self.aging {
+                return;
+            }
+            let promoted = self.standard.pop_front().unwrap();
+            info!(
+                order_id = promoted.order.order_id,
+                waited_secs = (now - promoted.enqueued_at).as_secs_f64();
+                "El pedido esperó demasiado y pasa a express"
+            );
+            let index = self
+                .express
+                .partition_point(|queued| queued.enqueued_at <= promoted.enqueued_at);
+            self.express.insert(index, promoted);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::product::Product;
+
+    fn order(order_id: u64, priority: Priority) -> BlockedOrder {
+        BlockedOrder::new(order_id, vec![Product { id: 1, amount: 1 }], None, priority)
+    }
+
+    fn pop(queue: &mut DeliveryQueue) -> Option<u64> {
+        queue.pop_first(|_| true).map(|order| order.order_id)
+    }
+
+    #[test]
+    fn express_orders_go_first_and_each_class_is_fifo() {
+        let mut queue = DeliveryQueue::new(Duration::from_secs(60), Clock::real());
+        queue.push(order(1, Priority::Standard));
+        queue.push(order(2, Priority::Express));
+        queue.push(order(3, Priority::Standard));
+        queue.push(order(4, Priority::Express));
+        assert_eq!(queue.len(), 4);
+        assert_eq!(pop(&mut queue), Some(2));
+        assert_eq!(pop(&mut queue), Some(4));
+        assert_eq!(pop(&mut queue), Some(1));
+        assert_eq!(pop(&mut queue), Some(3));
+        assert_eq!(pop(&mut queue), None);
+    }
+
+    #[test]
+    fn aged_standard_orders_are_promoted() {
+        let mut queue = DeliveryQueue::new(Duration::ZERO, Clock::real());
+        queue.push(order(1, Priority::Standard));
+        queue.push(order(2, Priority::Express));
+        // Con aging cero el pedido estándar se promueve y, como llegó antes, sale primero
+        assert_eq!(pop(&mut queue), Some(1));
+        assert_eq!(pop(&mut queue), Some(2));
+    }
+
+    #[test]
+    fn skips_orders_no_worker_can_take() {
+        let mut queue = DeliveryQueue::new(Duration::from_secs(60), Clock::real());
+        queue.push(order(1, Priority::Express));
+        queue.push(order(2, Priority::Express));
+        let taken = queue.pop_first(|order| order.order_id != 1).unwrap();
+        assert_eq!(taken.order_id, 2);
+        assert_eq!(queue.len(), 1);
+    }
+}
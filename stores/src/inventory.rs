@@ -0,0 +1,198 @@
+use crate::product::Product;
+use csv::ReaderBuilder;
+use order_file::is_valid_product_id;
+use rand::Rng;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+// Errores posibles al cargar el inventario inicial de un store.
+//
+// Variantes:
+// * `Io`: No se pudo abrir o leer el archivo.
+// * `Csv`: El archivo CSV tiene un formato inválido.
+// * `Json`: El archivo JSON tiene un formato inválido.
+// * `UnsupportedFormat`: La extensión del archivo no es `csv` ni `json`.
+// * `DuplicateId`: Hay más de una entrada para el mismo producto.
+// * `InvalidId`: Hay un producto con un id que no es válido (negativo), igual que en los pedidos.
+// * `NegativeAmount`: Hay un producto con una cantidad negativa.
+#[derive(Debug)]
+pub enum InventoryError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    UnsupportedFormat(String),
+    DuplicateId(i32),
+    InvalidId(i32),
+    NegativeAmount { id: i32, amount: i32 },
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InventoryError::Io(e) => write!(f, "no se pudo leer el archivo: {}", e),
+            InventoryError::Csv(e) => write!(f, "CSV inválido: {}", e),
+            InventoryError::Json(e) => write!(f, "JSON inválido: {}", e),
+            InventoryError::UnsupportedFormat(ext) => {
+                write!(f, "formato no soportado '{}', se espera csv o json", ext)
+            }
+            InventoryError::DuplicateId(id) => write!(f, "el producto {} está repetido", id),
+            InventoryError::InvalidId(id) => write!(f, "id de producto inválido {}", id),
+            InventoryError::NegativeAmount { id, amount } => {
+                write!(
+                    f,
+                    "el producto {} tiene una cantidad negativa ({})",
+                    id, amount
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+impl From<io::Error> for InventoryError {
+    fn from(e: io::Error) -> Self {
+        InventoryError::Io(e)
+    }
+}
+
+impl From<csv::Error> for InventoryError {
+    fn from(e: csv::Error) -> Self {
+        InventoryError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for InventoryError {
+    fn from(e: serde_json::Error) -> Self {
+        InventoryError::Json(e)
+    }
+}
+
+// Carga el inventario inicial de un store desde un archivo.
+//
+// El formato se decide por la extensión del archivo:
+// * `.csv`: con encabezado `id,amount` y una fila por producto.
+// * `.json`: un arreglo de objetos `{"id": <id>, "amount": <amount>}`.
+//
+// Argumentos:
+// * `path`: Ruta del archivo de inventario.
+//
+// Retorna:
+// Un `HashMap` con el stock inicial indexado por id de producto, o un `InventoryError`
+// si el archivo no se puede leer, tiene ids repetidos o inválidos o cantidades negativas.
+pub fn load_inventory(path: &Path) -> Result<HashMap<i32, Product>, InventoryError> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let file = File::open(path)?;
+    match extension.as_str() {
+        "csv" => parse_csv(file),
+        "json" => parse_json(file),
+        _ => Err(InventoryError::UnsupportedFormat(extension)),
+    }
+}
+
+// Lee un inventario en formato CSV (`id,amount`).
+pub fn parse_csv<R: Read>(reader: R) -> Result<HashMap<i32, Product>, InventoryError> {
+    let mut rdr = ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let mut products = Vec::new();
+    for result in rdr.deserialize() {
+        let product: Product = result?;
+        products.push(product);
+    }
+    build_inventory(products)
+}
+
+// Lee un inventario en formato JSON (arreglo de productos).
+pub fn parse_json<R: Read>(reader: R) -> Result<HashMap<i32, Product>, InventoryError> {
+    let products: Vec<Product> = serde_json::from_reader(reader)?;
+    build_inventory(products)
+}
+
+// Valida la lista de productos leída y arma el stock.
+fn build_inventory(products: Vec<Product>) -> Result<HashMap<i32, Product>, InventoryError> {
+    let mut inventory = HashMap::new();
+    for product in products {
+        if !is_valid_product_id(product.id) {
+            return Err(InventoryError::InvalidId(product.id));
+        }
+        if product.amount < 0 {
+            return Err(InventoryError::NegativeAmount {
+                id: product.id,
+                amount: product.amount,
+            });
+        }
+        if inventory.contains_key(&product.id) {
+            return Err(InventoryError::DuplicateId(product.id));
+        }
+        inventory.insert(product.id, product);
+    }
+    Ok(inventory)
+}
+
+// Genera un inventario aleatorio con los productos 0..10 y entre 5 y 15 unidades de cada uno.
+// Se usa cuando no se indica un archivo de inventario.
+pub fn random_inventory<R: Rng>(rng: &mut R) -> HashMap<i32, Product> {
+    let mut inventory = HashMap::new();
+    for product_id in 0..10 {
+        let random_amount: i32 = rng.gen_range(5..15);
+        inventory.insert(
+            product_id,
+            Product {
+                id: product_id,
+                amount: random_amount,
+            },
+        );
+    }
+    inventory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_csv_inventory() {
+        let inventory = parse_csv("id,amount\n0,10\n3, 7\n".as_bytes()).unwrap();
+        assert_eq!(inventory.len(), 2);
+        assert_eq!(inventory[&0].amount, 10);
+        assert_eq!(inventory[&3].amount, 7);
+    }
+
+    #[test]
+    fn reads_json_inventory() {
+        let inventory =
+            parse_json(r#"[{"id":1,"amount":4},{"id":2,"amount":0}]"#.as_bytes()).unwrap();
+        assert_eq!(inventory.len(), 2);
+        assert_eq!(inventory[&1].amount, 4);
+    }
+
+    #[test]
+    fn rejects_duplicated_ids() {
+        let result = parse_csv("id,amount\n1,10\n1,3\n".as_bytes());
+        assert!(matches!(result, Err(InventoryError::DuplicateId(1))));
+    }
+
+    #[test]
+    fn rejects_the_same_ids_as_the_order_file() {
+        let result = parse_csv("id,amount\n-1,10\n".as_bytes());
+        assert!(matches!(result, Err(InventoryError::InvalidId(-1))));
+        assert!(order_file::parse_line("-1,10", 1).is_err());
+    }
+
+    #[test]
+    fn rejects_negative_amounts() {
+        let result = parse_json(r#"[{"id":5,"amount":-2}]"#.as_bytes());
+        assert!(matches!(
+            result,
+            Err(InventoryError::NegativeAmount { id: 5, amount: -2 })
+        ));
+    }
+}
@@ -0,0 +1,343 @@
+use crate::order::{BlockedOrder, OrderId, Priority};
+use crate::product::Product;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Evento registrado en el journal del store.
+//
+// Cada línea del journal es uno de estos eventos serializado en JSON.
+//
+// Variantes:
+// * `Stock`: Nueva cantidad en stock de un producto luego de una modificación.
+// * `NextOrder`: Próximo id de pedido a asignar. Se escribe al compactar el journal.
+// * `Blocked`: Pedido bloqueado a la espera de ser entregado. Se escribe al compactar el journal.
+// * `Sold`: Pedido local cuyos productos se descontaron del stock, quedando en `stock` las unidades
+//   que le quedan a cada uno.
+// * `Reserved`: Pedido del ecommerce cuyos productos se descontaron del stock (quedando en `stock`)
+//   y se bloqueó para delivery. Se registra en una sola línea para que todo el pedido sea atómico.
+// * `Delivered`: Pedido bloqueado que se entregó correctamente.
+// * `DeliveryFailed`: Pedido bloqueado que no se pudo entregar y cuyos productos volvieron al stock,
+//   quedando en `stock` las unidades de cada uno. Igual que `Reserved`, va en una sola línea.
+// * `Cancelled`: Pedido bloqueado que se canceló al cerrar el store, antes de que lo tomara un
+//   delivery. Sus productos volvieron al stock, quedando en `stock` las unidades de cada uno.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Stock {
+        id: i32,
+        amount: i32,
+    },
+    NextOrder {
+        order: OrderId,
+    },
+    Blocked {
+        order: OrderId,
+        items: Vec<Product>,
+        #[serde(default)]
+        priority: Priority,
+    },
+    Sold {
+        order: OrderId,
+        items: Vec<Product>,
+        stock: Vec<Product>,
+    },
+    Reserved {
+        order: OrderId,
+        items: Vec<Product>,
+        stock: Vec<Product>,
+        #[serde(default)]
+        priority: Priority,
+    },
+    Delivered {
+        order: OrderId,
+    },
+    DeliveryFailed {
+        order: OrderId,
+        #[serde(default)]
+        stock: Vec<Product>,
+    },
+    Cancelled {
+        order: OrderId,
+        #[serde(default)]
+        stock: Vec<Product>,
+    },
+}
+
+// Foto del estado del store, ya sea la inicial o la reconstruida a partir del journal.
+//
+// Atributos:
+// * `products`: Stock de cada producto.
+// * `orders_blocked`: Pedidos bloqueados que nunca llegaron a entregarse.
+// * `next_order_id`: Id que se le asignará al próximo pedido.
+pub struct StoreSnapshot {
+    pub products: HashMap<i32, Product>,
+    pub orders_blocked: Vec<BlockedOrder>,
+    pub next_order_id: OrderId,
+}
+
+impl StoreSnapshot {
+    // Crea la foto de un store que arranca con el stock `products` y sin pedidos.
+    pub fn new(products: HashMap<i32, Product>) -> StoreSnapshot {
+        StoreSnapshot {
+            products,
+            orders_blocked: Vec::new(),
+            next_order_id: 1,
+        }
+    }
+}
+
+// Journal de solo escritura al final (append-only) con las modificaciones del stock.
+//
+// Si el store se levanta sin journal se usa `Journal::disabled()` y los eventos se descartan.
+pub struct Journal {
+    file: Mutex<Option<File>>,
+}
+
+impl Journal {
+    // Crea un journal que no registra nada.
+    pub fn disabled() -> Journal {
+        Journal {
+            file: Mutex::new(None),
+        }
+    }
+
+    // Crea (o compacta) el journal en `path` a partir de una foto del estado del store.
+    //
+    // Se escribe primero la foto en un archivo temporal y luego se renombra,
+    // de forma que si el proceso se cae a mitad de camino el journal anterior sigue intacto.
+    //
+    // Argumentos:
+    // * `path`: Ruta del journal.
+    // * `snapshot`: Estado actual del store.
+    pub fn create(path: &Path, snapshot: &StoreSnapshot) -> io::Result<Journal> {
+        let tmp_path = tmp_path(path);
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            write_entry(
+                &mut tmp,
+                &JournalEntry::NextOrder {
+                    order: snapshot.next_order_id,
+                },
+            )?;
+            let mut ids: Vec<&i32> = snapshot.products.keys().collect();
+            ids.sort();
+            for id in ids {
+                write_entry(
+                    &mut tmp,
+                    &JournalEntry::Stock {
+                        id: *id,
+                        amount: snapshot.products[id].amount,
+                    },
+                )?;
+            }
+            for blocked in &snapshot.orders_blocked {
+                write_entry(
+                    &mut tmp,
+                    &JournalEntry::Blocked {
+                        order: blocked.order_id,
+                        items: blocked.items.clone(),
+                        priority: blocked.priority,
+                    },
+                )?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)?;
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Journal {
+            file: Mutex::new(Some(file)),
+        })
+    }
+
+    // Agrega un evento al final del journal.
+    //
+    // Un error de escritura no detiene al store, solo se informa por pantalla.
+    pub fn record(&self, entry: JournalEntry) {
+        let mut file_guard = self.file.lock().unwrap();
+        if let Some(file) = file_guard.as_mut() {
+            if let Err(e) = write_entry(file, &entry) {
+                error!(entry:? = entry, error:% = e; "Error al registrar en el journal");
+            }
+        }
+    }
+}
+
+// Reconstruye el estado del store leyendo el journal de `path`.
+//
+// Retorna:
+// `Ok(None)` si el journal no existe o está vacío, o el estado recuperado en caso contrario.
+// Una última línea incompleta (el proceso se cayó mientras la escribía) se descarta.
+pub fn replay(path: &Path) -> io::Result<Option<StoreSnapshot>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut snapshot = StoreSnapshot::new(HashMap::new());
+    let mut entries = 0;
+    let mut lines = BufReader::new(file).lines().peekable();
+    while let Some(line) = lines.next() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = match serde_json::from_str::<JournalEntry>(&line) {
+            Ok(entry) => entry,
+            Err(_) if lines.peek().is_none() => {
+                warn!("Se descarta la última línea incompleta del journal");
+                break;
+            }
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        entries += 1;
+        apply(&mut snapshot, entry);
+    }
+
+    if entries == 0 {
+        return Ok(None);
+    }
+    Ok(Some(snapshot))
+}
+
+// Aplica un evento del journal sobre el estado recuperado.
+fn apply(snapshot: &mut StoreSnapshot, entry: JournalEntry) {
+    match entry {
+        JournalEntry::Stock { id, amount } => {
+            snapshot.products.insert(id, Product { id, amount });
+        }
+        JournalEntry::NextOrder { order } => {
+            snapshot.next_order_id = snapshot.next_order_id.max(order);
+        }
+        JournalEntry::Blocked {
+            order,
+            items,
+            priority,
+        } => {
+            block(snapshot, order, items, priority);
+        }
+        JournalEntry::Sold { order, stock, .. } => {
+            set_stock(snapshot, stock);
+            snapshot.next_order_id = snapshot.next_order_id.max(order + 1);
+        }
+        JournalEntry::Reserved {
+            order,
+            items,
+            stock,
+            priority,
+        } => {
+            set_stock(snapshot, stock);
+            block(snapshot, order, items, priority);
+        }
+        JournalEntry::Delivered { order } => unblock(snapshot, order),
+        JournalEntry::DeliveryFailed { order, stock }
+        | JournalEntry::Cancelled { order, stock } => {
+            set_stock(snapshot, stock);
+            unblock(snapshot, order);
+        }
+    }
+}
+
+fn block(snapshot: &mut StoreSnapshot, order: OrderId, items: Vec<Product>, priority: Priority) {
+    snapshot
+        .orders_blocked
+        .push(BlockedOrder::new(order, items, None, priority));
+    snapshot.next_order_id = snapshot.next_order_id.max(order + 1);
+}
+
+fn unblock(snapshot: &mut StoreSnapshot, order: OrderId) {
+    snapshot
+        .orders_blocked
+        .retain(|blocked| blocked.order_id != order);
+}
+
+fn set_stock(snapshot: &mut StoreSnapshot, stock: Vec<Product>) {
+    for product in stock {
+        snapshot.products.insert(product.id, product);
+    }
+}
+
+fn write_entry(file: &mut File, entry: &JournalEntry) -> io::Result<()> {
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    file.flush()?;
+    //Recién cuando el evento llegó al disco se lo puede dar por registrado
+    file.sync_data()
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn replays_stock_and_pending_orders() {
+        let path = env::temp_dir().join(format!("journal_test_{}.log", std::process::id()));
+        let mut products = HashMap::new();
+        products.insert(1, Product { id: 1, amount: 10 });
+        products.insert(2, Product { id: 2, amount: 5 });
+
+        let journal = Journal::create(&path, &StoreSnapshot::new(products)).unwrap();
+        journal.record(JournalEntry::Reserved {
+            order: 1,
+            items: vec![Product { id: 1, amount: 3 }, Product { id: 2, amount: 1 }],
+            stock: vec![Product { id: 1, amount: 7 }, Product { id: 2, amount: 4 }],
+            priority: Priority::Express,
+        });
+        journal.record(JournalEntry::Reserved {
+            order: 2,
+            items: vec![Product { id: 2, amount: 1 }],
+            stock: vec![Product { id: 2, amount: 3 }],
+            priority: Priority::Standard,
+        });
+        journal.record(JournalEntry::Sold {
+            order: 3,
+            items: vec![Product { id: 2, amount: 1 }],
+            stock: vec![Product { id: 2, amount: 2 }],
+        });
+        journal.record(JournalEntry::Delivered { order: 2 });
+        journal.record(JournalEntry::Reserved {
+            order: 4,
+            items: vec![Product { id: 2, amount: 2 }],
+            stock: vec![Product { id: 2, amount: 0 }],
+            priority: Priority::Standard,
+        });
+        // El stock que vuelve va en la misma línea que el fallo de la entrega
+        journal.record(JournalEntry::DeliveryFailed {
+            order: 4,
+            stock: vec![Product { id: 2, amount: 2 }],
+        });
+        drop(journal);
+
+        let snapshot = replay(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(snapshot.products[&1].amount, 7);
+        assert_eq!(snapshot.products[&2].amount, 2);
+        assert_eq!(snapshot.orders_blocked.len(), 1);
+        assert_eq!(snapshot.orders_blocked[0].order_id, 1);
+        assert_eq!(snapshot.orders_blocked[0].items.len(), 2);
+        assert_eq!(snapshot.orders_blocked[0].items[0].amount, 3);
+        assert_eq!(snapshot.orders_blocked[0].priority, Priority::Express);
+        assert_eq!(snapshot.next_order_id, 5);
+    }
+
+    #[test]
+    fn missing_journal_recovers_nothing() {
+        let path = env::temp_dir().join("journal_que_no_existe.log");
+        assert!(replay(&path).unwrap().is_none());
+    }
+}
@@ -1,53 +1,220 @@
 use actix::prelude::*;
+use admin::{serve_admin, AdminState};
+use auth::{AuthAttempt, AuthLimiter};
+use cli::{parse_args, USAGE};
+use inventory::{load_inventory, random_inventory};
+use journal::{replay, Journal, StoreSnapshot};
+use log::{debug, error, info, warn};
+use logger::Logger;
+use messages::Shutdown;
+use metrics::{serve_metrics, StoreMetrics};
+use order_file::{read_order_file, OrderLine};
 use orders_processor::{process_line, process_store_orders};
-use std::path::Path;
+use product::Product;
+use simulation::{INVENTORY_STREAM, ORDERS_STREAM};
+use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 use std::{env, io};
 use store::Store;
-use tokio::io::{split, AsyncBufReadExt, BufReader};
+use store_protocol::{accept_handshake, AuthToken, HandshakeError, MessageCodec, StoreRequest};
+use supplier::Supplier;
+use tokio::io::split;
 use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
+use tokio::time;
+use tokio_util::codec::FramedRead;
 
 use crate::store_server::StoreServer;
 
+mod admin;
+mod auth;
+mod cli;
+mod config;
+mod delivery;
+mod delivery_queue;
+mod inventory;
+mod journal;
 mod messages;
+mod metrics;
+mod order;
 mod orders_processor;
 mod product;
+mod protocol;
+mod simulation;
 mod store;
 mod store_server;
+mod supplier;
 
 // Implementa la lógica principal del servidor
 #[actix_rt::main]
 async fn main() -> io::Result<()> {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
 
     // Verifica si se proporcionó el puerto y el archivo como argumento
-    let (port, file) = match args.len() {
-        3 => (args[1].clone(),args[2].clone()), // Si hay tres argumentos, el segundo es el puerto y el tercero es el file
-        _ => {
-            eprintln!("Uso: cargo run <puerto> <orders_file.csv>");
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("{}", USAGE);
             return Ok(());
         }
     };
 
+    // Todos los registros llevan el puerto del store para distinguirlo de los demás
+    let logger = Logger::new(
+        args.log_format,
+        args.log_level,
+        vec![("store", args.port.clone())],
+    );
+    if let Err(e) = logger.init() {
+        eprintln!("No se pudo iniciar el logger: {}", e);
+    }
 
-    // Creo un listener
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+    let mut config = match args.store_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(error:% = e; "Configuración inválida");
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()));
+        }
+    };
+    info!(config:? = config; "Configuración");
+
+    // El reloj de la simulación arranca antes de lanzar cualquier actor
+    config.simulation.start_clock();
+    let clock = config.simulation.clock.clone();
+
+    // Si hay un journal de una ejecucion anterior recupero el estado desde ahi
+    let recovered = match &args.journal_file {
+        Some(journal_file) => replay(journal_file)?,
+        None => None,
+    };
+
+    let initial_state = match recovered {
+        Some(state) => {
+            info!(
+                products = state.products.len(), pending_orders = state.orders_blocked.len();
+                "Estado recuperado del journal"
+            );
+            state
+        }
+        // Cargo el stock inicial del archivo de inventario o, si no se indico, uno aleatorio
+        None => match &args.inventory_file {
+            Some(inventory_file) => match load_inventory(inventory_file) {
+                Ok(stock) => StoreSnapshot::new(stock),
+                Err(e) => {
+                    error!(
+                        file:% = inventory_file.display(), error:% = e;
+                        "Error al cargar el inventario"
+                    );
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()));
+                }
+            },
+            None => StoreSnapshot::new(random_inventory(
+                &mut config.simulation.rng(INVENTORY_STREAM),
+            )),
+        },
+    };
 
-    // Defino el path de los pedidos
-    let file_path = Path::new(&format!("./{}", file)).to_owned();
+    // Compacto el journal con el estado inicial y a partir de aca se registran las modificaciones
+    let journal = match &args.journal_file {
+        Some(journal_file) => Journal::create(journal_file, &initial_state)?,
+        None => Journal::disabled(),
+    };
+
+    // Leo y valido los pedidos locales, las lineas invalidas se tratan segun la politica elegida
+    let order_file = match read_order_file(&args.orders_file, &args.order_errors) {
+        Ok(order_file) => order_file,
+        Err(e) => {
+            error!(
+                file:% = args.orders_file.display(), error:% = e;
+                "Error al leer el archivo de pedidos"
+            );
+            return Err(e.into());
+        }
+    };
+    info!(
+        orders = order_file.summary.orders, rejected = order_file.summary.rejected_total();
+        "Archivo de pedidos leído"
+    );
+
+    // Si se configuro un token, los clientes se tienen que autenticar antes de enviar pedidos
+    let token = match &args.auth_token_file {
+        Some(token_file) => match AuthToken::read(token_file) {
+            Ok(token) => Some(Arc::new(token)),
+            Err(e) => {
+                error!(file:% = token_file.display(), error:% = e; "Error al leer el token");
+                return Err(e);
+            }
+        },
+        None => {
+            warn!("Sin --auth-token-file: cualquiera que llegue al puerto puede enviar pedidos");
+            None
+        }
+    };
+    let auth_limiter = Arc::new(AuthLimiter::default());
+
+    // Creo un listener
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", args.port)).await?;
 
-    let store = Store::new();
+    let metrics = Arc::new(StoreMetrics::new());
+    let orders_rng = config.simulation.rng(ORDERS_STREAM);
+    //Los heartbeats miden lo que tarda la red, así que siempre se controlan en tiempo real
+    let heartbeat_timeout = config.heartbeat_timeout();
+    let store = Store::new(config, initial_state, Arc::new(journal), metrics.clone());
     let store_addr = store.start();
 
+    // Si se configuro, lanzo el proveedor que repone el stock periodicamente
+    if let Some(supplier_config) = args.supplier {
+        Supplier::new(supplier_config, store_addr.clone(), clock.clone()).start();
+    }
+
+    // Si se configuro, lanzo el servidor HTTP de administracion en el mismo sistema de actix
+    // Pide el token del store en cada pedido, por eso parse_args no acepta --admin-port sin
+    // --auth-token-file
+    let connections = Arc::new(AtomicUsize::new(0));
+    let admin_handle = match (&args.admin_port, &token) {
+        (Some(admin_port), Some(token)) => {
+            let state = AdminState {
+                store_addr: store_addr.clone(),
+                connections: connections.clone(),
+                token: token.clone(),
+            };
+            let server = serve_admin(admin_port, state)?;
+            Some(actix_rt::spawn(async move {
+                if let Err(e) = server.await {
+                    error!(error:% = e; "Error en el servidor de administración");
+                }
+            }))
+        }
+        _ => None,
+    };
+
+    // Si se configuro, expongo las metricas para Prometheus
+    let metrics_handle = match &args.metrics_port {
+        Some(metrics_port) => {
+            let server = serve_metrics(metrics_port, metrics, store_addr.clone())?;
+            Some(actix_rt::spawn(async move {
+                if let Err(e) = server.await {
+                    error!(error:% = e; "Error en el servidor de métricas");
+                }
+            }))
+        }
+        None => None,
+    };
+
     // Creo un canal para comunicar lo que voy leyendo con
-    let (tx, mut rx) = mpsc::channel::<csv::StringRecord>(16);
+    let (tx, mut rx) = mpsc::channel::<OrderLine>(16);
 
-    // Lanzo una task que se encarga de leer el archivo
-    let processor_handle = tokio::spawn(async move {
-        process_store_orders(file_path, tx).await.unwrap();
-    });
+    // Lanzo una task que se encarga de ir enviando los pedidos del archivo
+    let processor_handle = tokio::spawn(process_store_orders(
+        order_file.orders,
+        tx,
+        orders_rng,
+        clock,
+    ));
 
     // Lanzo una task que se encarga de procesar la linea.
     let addr_store = store_addr.clone();
@@ -65,24 +232,138 @@ async fn main() -> io::Result<()> {
         }
     });
 
-    println!("Espero una conexión");
-    while let Ok((stream, _addr)) = listener.accept().await {
-        println!("\x1b[31mConexión nueva entrante\x1b[0m");
+    info!(port = args.port.as_str(); "Espero una conexión");
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, addr) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    error!(error:% = e; "Error al aceptar una conexión");
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+        //Con token, el intento se reserva antes de mandar el desafio, asi varias conexiones a la vez
+        //no pueden probar mas veces. Si la direccion ya uso sus intentos, espera su turno
+        let attempt = match &token {
+            Some(_) => match auth_limiter.reserve(addr.ip()) {
+                Some(attempt) => Some(attempt),
+                None => {
+                    warn!(
+                        peer:% = addr;
+                        "Demasiados intentos de autenticación seguidos, rechazo la conexión"
+                    );
+                    continue;
+                }
+            },
+            None => None,
+        };
+        info!(peer:% = addr; "Conexión nueva entrante");
         let addr_store = store_addr.clone();
-        StoreServer::create(|ctx| {
-            let (r, w) = split(stream);
-            let write = Arc::new(Mutex::new(w));
-            StoreServer::add_stream(
-                tokio_stream::wrappers::LinesStream::new(BufReader::new(r).lines()),
-                ctx,
+        let connections = connections.clone();
+        let token = token.clone();
+        //El saludo se hace en otra task para no frenar las conexiones que llegan mientras tanto
+        actix_rt::spawn(async move {
+            let mut stream = stream;
+            if let Some(wait) = attempt.as_ref().map(AuthAttempt::wait) {
+                if !wait.is_zero() {
+                    debug!(
+                        peer:% = addr, wait_secs = wait.as_secs_f64();
+                        "Espero el turno para autenticar"
+                    );
+                    time::sleep(wait).await;
+                }
+            }
+            //Un cliente que no termina el saludo no puede quedarse con la conexion abierta
+            let handshake = time::timeout(
+                heartbeat_timeout,
+                accept_handshake(&mut stream, token.as_deref()),
             );
-            StoreServer::new(write, addr_store)
+            let handshake = handshake.await;
+            //El intento termina con el saludo y solo cuenta como fallido si el cliente respondio mal
+            //el desafio
+            if let Some(attempt) = attempt {
+                if matches!(handshake, Ok(Err(HandshakeError::Unauthorized))) {
+                    attempt.failed();
+                }
+            }
+            match handshake {
+                Ok(Ok(version)) => {
+                    info!(peer:% = addr, version = version; "Saludo aceptado")
+                }
+                Ok(Err(HandshakeError::Unauthorized)) => {
+                    warn!(peer:% = addr; "Autenticación fallida, cierro la conexión");
+                    return;
+                }
+                Ok(Err(e)) => {
+                    warn!(peer:% = addr, error:% = e; "Saludo inválido, cierro la conexión");
+                    return;
+                }
+                Err(_) => {
+                    warn!(
+                        peer:% = addr;
+                        "El cliente no terminó el saludo a tiempo, cierro la conexión"
+                    );
+                    return;
+                }
+            }
+            StoreServer::create(|ctx| {
+                let (r, w) = split(stream);
+                let write = Arc::new(Mutex::new(w));
+                let frames = FramedRead::new(r, MessageCodec::<StoreRequest>::new());
+                StoreServer::add_stream(frames, ctx);
+                StoreServer::new(write, addr_store, connections, heartbeat_timeout)
+            });
         });
     }
 
-    // Esperar a que el procesador de órdenes termine
-    processor_handle.await.unwrap();
-    result.await.unwrap();
+    // Dejo de leer el archivo de pedidos y de procesar sus lineas
+    info!("Cerrando el store");
+    drop(listener);
+    processor_handle.abort();
+    result.abort();
+    for handle in [admin_handle, metrics_handle].into_iter().flatten() {
+        handle.abort();
+    }
+
+    // Espero a que los deliverys terminen y muestro como quedo el stock
+    match store_addr.send(Shutdown).await {
+        Ok(inventory) => print_inventory(&inventory),
+        Err(e) => error!(error:% = e; "Error al cerrar el store"),
+    }
+    info!(
+        orders = order_file.summary.orders, rejected = order_file.summary.rejected_total();
+        "Resumen del archivo de pedidos: {}", order_file.summary
+    );
 
     Ok(())
 }
+
+// Espera a que llegue SIGINT (Ctrl-C) o SIGTERM.
+async fn shutdown_signal() {
+    let mut sigterm = match signal(SignalKind::terminate()) {
+        Ok(sigterm) => sigterm,
+        Err(e) => {
+            warn!(error:% = e; "No se pudo escuchar SIGTERM");
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("Recibi SIGINT"),
+        _ = sigterm.recv() => info!("Recibi SIGTERM"),
+    }
+}
+
+// Muestra el inventario final del store ordenado por id de producto.
+fn print_inventory(inventory: &HashMap<i32, Product>) {
+    let mut products: Vec<&Product> = inventory.values().collect();
+    products.sort_by_key(|product| product.id);
+    info!(products = products.len(); "Inventario final");
+    for product in products {
+        info!(product_id = product.id, amount = product.amount; "Stock final del producto");
+    }
+}
@@ -1,55 +1,282 @@
-use actix::Message;
+use actix::{Message, MessageResponse, Recipient};
+use serde::Serialize;
+use std::collections::HashMap;
 
-use crate::store::Store;
+use crate::order::{BlockedOrder, Order, OrderId, OrderState, Priority};
+use crate::product::Product;
+pub use store_protocol::StockLevel;
 
-// Mensaje para indicar la apertura de un archivo.
+// Mensaje para representar la recepción de un pedido.
+//
+// Este mensaje se utiliza en el contexto de Actix para representar un pedido
+// recibido. Contiene los productos pedidos, cada uno con la `cantidad` solicitada.
+//
+// Atributos:
+// * `items`: Productos del pedido. Se toman todos o ninguno.
+//
+// Retorna un `bool` como resultado, indicando si la operación de recepción fue exitosa.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ReceiveOrder {
+    pub items: Vec<Product>,
+}
+
+// Resultado de intentar reservar un pedido en el store.
+//
+// Variantes:
+// * `Reserved`: Había stock suficiente de todos los productos, se descontó y el pedido `order_id`
+//   quedó en la cola de delivery.
+// * `PartiallyReserved`: El pedido admitía reservas parciales y solo había stock de una parte. Esa parte
+//   quedó reservada como el pedido `order_id` y `remaining` indica lo que faltó.
+// * `NotFound`: El store no tiene el producto `id`.
+// * `InsufficientStock`: El producto `id` existe pero no alcanza la cantidad, `available` indica cuánto hay.
+// * `InvalidOrder`: El pedido no tiene productos o alguna cantidad no es positiva.
+// * `ShuttingDown`: El store se está cerrando y no toma pedidos para delivery.
+#[derive(MessageResponse, Debug, PartialEq)]
+pub enum ReserveOutcome {
+    Reserved {
+        order_id: OrderId,
+    },
+    PartiallyReserved {
+        order_id: OrderId,
+        remaining: Vec<Product>,
+    },
+    NotFound {
+        id: i32,
+    },
+    InsufficientStock {
+        id: i32,
+        available: i32,
+    },
+    InvalidOrder,
+    ShuttingDown,
+}
+
+// Mensaje para reservar un pedido hecho por el ecommerce.
+//
+// En un único llamado al handler se verifica el stock de todos los productos, se descuenta y se deja
+// el pedido en la cola de delivery, de forma que ningún otro pedido pueda intercalarse entre medio.
+// Si falta alguno de los productos no se reserva ninguno.
+//
+// Atributos:
+// * `items`: Productos a reservar, cada uno con su cantidad. Se entregan en un mismo envío.
+// * `notify`: A quién avisarle el resultado del delivery del pedido.
+// * `priority`: Prioridad del pedido en la cola de delivery.
+// * `allow_partial`: Si no hay stock de todo, reservar lo que haya en lugar de rechazar el pedido.
+//
+// Retorna un `ReserveOutcome` indicando si se pudo reservar o por qué no.
+#[derive(Message)]
+#[rtype(result = "ReserveOutcome")]
+pub struct ReserveOrder {
+    pub items: Vec<Product>,
+    pub notify: Recipient<DeliveryReport>,
+    pub priority: Priority,
+    pub allow_partial: bool,
+}
+
+// Mensaje con el resultado del delivery de un pedido del ecommerce.
+//
+// Lo envía el store al `StoreServer` de la conexión por la que llegó el pedido, que se encarga de
+// reenviarlo al ecommerce. Si esa conexión ya se cerró, lo envía a la última conexión anotada con
+// `SubscribeReports` o lo guarda hasta que se anote una.
 //
-// Este mensaje se utiliza para notificar a un actor `Store` que debe abrir un archivo.
-// Contiene la dirección del actor `Store` que manejará la apertura del archivo.
+// Atributos:
+// * `order_id`: Pedido entregado o no.
+// * `delivered`: Si el pedido se pudo entregar.
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct DeliveryReport {
+    pub order_id: OrderId,
+    pub delivered: bool,
+}
+
+// Mensaje con el que una conexión se anota en el store para recibir los avisos de delivery de los
+// pedidos cuya conexión original ya se cerró (por ejemplo porque el ecommerce se reconectó) o que se
+// recuperaron del journal. Al anotarse recibe los avisos que estaban guardados.
 //
 // Atributos:
-// * `Addr<Store>`: Dirección del actor `Store` responsable de manejar la apertura del archivo.
+// * `recipient`: A quién enviarle los avisos.
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct OpenFile(pub actix::Addr<Store>);
+pub struct SubscribeReports {
+    pub recipient: Recipient<DeliveryReport>,
+}
 
-// Mensaje para indicar la lectura de un pedido.
+// Mensaje con un aviso de delivery que un `StoreServer` no pudo escribir en su conexión, por ejemplo
+// porque el ecommerce ya la cerró aunque el store todavía no se dio cuenta. El store se lo envía a
+// otra conexión anotada o lo guarda hasta que se anote una.
 //
-// Este mensaje se utiliza en el contexto de Actix para señalizar la acción
-// de leer un pedido. No contiene datos adicionales.
+// Atributos:
+// * `report`: El aviso que no se pudo enviar.
+// * `from`: Conexión que no lo pudo enviar, a la que no se le vuelve a enviar.
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct ReadOrder();
+pub struct UnsentReport {
+    pub report: DeliveryReport,
+    pub from: Recipient<DeliveryReport>,
+}
 
-// Mensaje para representar la recepción de un pedido.
+// Mensaje para consultar un pedido del registro del store.
 //
-// Este mensaje se utiliza en el contexto de Actix para representar un pedido
-// recibido. Contiene el `id` del producto y la `cantidad` solicitada.
+// Atributos:
+// * `id`: Identificador del pedido.
+//
+// Retorna el pedido con su estado actual, o `None` si no existe.
+#[derive(Message)]
+#[rtype(result = "Option<Order>")]
+pub struct GetOrder {
+    pub id: OrderId,
+}
+
+// Mensaje para consultar el stock de algunos productos sin modificar nada.
 //
 // Atributos:
-// * `id`: Identificador del producto, representado por un entero de 32 bits.
-// * `amount`: Cantidad del producto solicitada, representada por un entero de 32 bits.
+// * `ids`: Productos a consultar. Si está vacío se consultan todos los productos del store.
 //
-// Retorna un `bool` como resultado, indicando si la operación de recepción fue exitosa.
+// Retorna el stock de los productos consultados que existen en el store, ordenados por id.
 #[derive(Message)]
-#[rtype(result = "bool")]
-pub struct ReceiveOrder {
+#[rtype(result = "Vec<StockLevel>")]
+pub struct QueryStock {
+    pub ids: Vec<i32>,
+}
+
+// Mensaje para reponer stock de un producto.
+//
+// Si el producto no existe en el store se crea con la cantidad indicada.
+//
+// Atributos:
+// * `id`: Identificador del producto a reponer.
+// * `amount`: Cantidad a agregar al stock, debe ser positiva.
+//
+// Retorna el stock del producto luego de la reposición, o `None` si la cantidad no es válida.
+#[derive(Message)]
+#[rtype(result = "Option<i32>")]
+pub struct Restock {
     pub id: i32,
     pub amount: i32,
 }
 
-// Mensaje para indicar el bloqueo de un producto.
+// Mensaje para corregir el stock disponible de un producto, por ejemplo después de un recuento.
 //
-// Este mensaje se utiliza para notificar a un actor `Store` que debe bloquear
-// un producto específico, en respuesta a un pedido recibido.
-// Contiene el `id` del producto y la `cantidad` a bloquear.
+// A diferencia de `Restock` no suma unidades sino que fija la cantidad disponible. No afecta a
+// las unidades reservadas ni a las que están en delivery.
 //
 // Atributos:
-// * `id`: Identificador del producto a bloquear.
-// * `amount`: Cantidad del producto a bloquear.
+// * `id`: Identificador del producto a corregir. Si no existe en el store se crea.
+// * `amount`: Cantidad disponible del producto, no puede ser negativa.
+//
+// Retorna el stock del producto luego de la corrección, o `None` si la cantidad no es válida.
 #[derive(Message)]
-#[rtype(result = "()")]
-pub struct BlockProduct {
+#[rtype(result = "Option<i32>")]
+pub struct AdjustStock {
     pub id: i32,
     pub amount: i32,
 }
+
+// Mensaje para consultar los pedidos del ecommerce que tienen productos bloqueados y todavía no
+// tomó ningún delivery.
+//
+// Retorna los pedidos que esperan un delivery, en el orden en que se van a tomar, seguidos de
+// los que esperan para reintentar la entrega.
+#[derive(Message)]
+#[rtype(result = "Vec<BlockedOrderStatus>")]
+pub struct GetBlockedOrders;
+
+// Estado de un pedido bloqueado.
+//
+// Atributos:
+// * `order_id`: Id del pedido.
+// * `items`: Productos bloqueados para el pedido.
+// * `priority`: Prioridad del pedido.
+// * `attempts`: Intentos de entrega que ya fallaron.
+// * `state`: `Reserved` si espera un delivery o `AwaitingRetry` si espera para reintentar.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BlockedOrderStatus {
+    pub order_id: OrderId,
+    pub items: Vec<Product>,
+    pub priority: Priority,
+    pub attempts: u32,
+    pub state: OrderState,
+}
+
+// Mensaje para consultar qué está haciendo cada delivery del pool.
+//
+// Retorna el estado de cada delivery ordenado por id.
+#[derive(Message)]
+#[rtype(result = "Vec<WorkerStatus>")]
+pub struct GetDeliveryWorkers;
+
+// Estado de un delivery del pool.
+//
+// Variantes:
+// * `Idle`: Está libre esperando un pedido.
+// * `Delivering`: Está entregando el pedido `order_id`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum WorkerStatus {
+    Idle { id: usize },
+    Delivering { id: usize, order_id: OrderId },
+}
+
+// Mensaje para consultar qué productos tienen poco stock.
+//
+// Atributos:
+// * `reorder_point`: Se devuelven los productos con menos unidades que este valor.
+//
+// Retorna los productos (con su stock actual) que están por debajo del punto de reposición.
+#[derive(Message)]
+#[rtype(result = "Vec<Product>")]
+pub struct GetLowStock {
+    pub reorder_point: i32,
+}
+
+// Mensaje para cerrar el store de forma ordenada.
+//
+// Los pedidos que ningún delivery llegó a tomar vuelven al stock y el pool de actores `DeliveryWorker`
+// se achica a cero: los libres se detienen enseguida y los que están entregando terminan esa entrega.
+// Se responde cuando se detuvieron todos.
+//
+// Retorna el inventario final del store.
+#[derive(Message)]
+#[rtype(result = "HashMap<i32, Product>")]
+pub struct Shutdown;
+
+// Mensaje para cambiar la cantidad de deliveries del store mientras está funcionando.
+//
+// Si se achica el pool, los deliveries que están entregando un pedido terminan esa entrega antes de irse.
+//
+// Atributos:
+// * `count`: Cantidad de deliveries que se quiere tener.
+//
+// Retorna la nueva cantidad de deliveries.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct SetDeliveryWorkers {
+    pub count: usize,
+}
+
+// Mensaje del store a un delivery para que entregue un pedido.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Deliver {
+    pub order: BlockedOrder,
+}
+
+// Mensaje del store a un delivery libre para que termine.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct StopDelivery;
+
+// Mensaje de un delivery al store cuando termina una entrega.
+//
+// Atributos:
+// * `worker`: Delivery que hizo la entrega, queda libre para otro pedido.
+// * `order`: Pedido entregado o no.
+// * `delivered`: Si el pedido se pudo entregar.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct DeliveryFinished {
+    pub worker: usize,
+    pub order: BlockedOrder,
+    pub delivered: bool,
+}
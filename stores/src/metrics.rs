@@ -0,0 +1,201 @@
+use crate::messages::QueryStock;
+use crate::order::OrderOrigin;
+use crate::store::Store;
+use actix::Addr;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::info;
+use prometheus::{Encoder, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+
+// Métricas del store en formato Prometheus.
+//
+// Los contadores los actualiza el actor `Store` a medida que procesa pedidos y entregas. El stock de
+// cada producto se consulta al store recién cuando se piden las métricas.
+//
+// Atributos:
+// * `registry`: Registro con todas las métricas del store.
+// * `orders_received`: Pedidos recibidos, por origen (`local` o `ecommerce`).
+// * `orders_accepted`: Pedidos reservados total o parcialmente, por origen.
+// * `orders_rejected`: Pedidos rechazados, por origen.
+// * `product_stock`: Unidades de cada producto, por estado (`available`, `reserved` o `in_delivery`).
+// * `deliveries`: Intentos de entrega, por resultado (`delivered` o `failed`).
+pub struct StoreMetrics {
+    registry: Registry,
+    orders_received: IntCounterVec,
+    orders_accepted: IntCounterVec,
+    orders_rejected: IntCounterVec,
+    product_stock: IntGaugeVec,
+    deliveries: IntCounterVec,
+}
+
+impl StoreMetrics {
+    pub fn new() -> StoreMetrics {
+        let registry = Registry::new();
+        let orders_received = counter(
+            &registry,
+            "store_orders_received_total",
+            "Pedidos recibidos",
+            &["origin"],
+        );
+        let orders_accepted = counter(
+            &registry,
+            "store_orders_accepted_total",
+            "Pedidos reservados total o parcialmente",
+            &["origin"],
+        );
+        let orders_rejected = counter(
+            &registry,
+            "store_orders_rejected_total",
+            "Pedidos rechazados",
+            &["origin"],
+        );
+        let product_stock = IntGaugeVec::new(
+            Opts::new("store_product_stock", "Unidades de cada producto"),
+            &["product", "state"],
+        )
+        .unwrap();
+        registry.register(Box::new(product_stock.clone())).unwrap();
+        let deliveries = counter(
+            &registry,
+            "store_deliveries_total",
+            "Intentos de entrega",
+            &["result"],
+        );
+        StoreMetrics {
+            registry,
+            orders_received,
+            orders_accepted,
+            orders_rejected,
+            product_stock,
+            deliveries,
+        }
+    }
+
+    // Registra un pedido recibido y si se pudo reservar.
+    pub fn order_processed(&self, origin: OrderOrigin, accepted: bool) {
+        let origin = origin_label(origin);
+        self.orders_received.with_label_values(&[origin]).inc();
+        if accepted {
+            self.orders_accepted.with_label_values(&[origin]).inc();
+        } else {
+            self.orders_rejected.with_label_values(&[origin]).inc();
+        }
+    }
+
+    // Registra el resultado de un intento de entrega.
+    pub fn delivery_finished(&self, delivered: bool) {
+        let result = if delivered { "delivered" } else { "failed" };
+        self.deliveries.with_label_values(&[result]).inc();
+    }
+
+    // Le pide el stock al store, lo carga en `product_stock` y devuelve todas las métricas en el
+    // formato de texto de Prometheus.
+    async fn render(&self, store_addr: &Addr<Store>) -> String {
+        if let Ok(stock) = store_addr.send(QueryStock { ids: Vec::new() }).await {
+            for level in stock {
+                let id = level.id.to_string();
+                for (state, amount) in [
+                    ("available", level.available),
+                    ("reserved", level.reserved),
+                    ("in_delivery", level.in_delivery),
+                ] {
+                    self.product_stock
+                        .with_label_values(&[&id, state])
+                        .set(amount as i64);
+                }
+            }
+        }
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+fn counter(registry: &Registry, name: &str, help: &str, labels: &[&str]) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+    registry.register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+fn origin_label(origin: OrderOrigin) -> &'static str {
+    match origin {
+        OrderOrigin::Local => "local",
+        OrderOrigin::Ecommerce => "ecommerce",
+    }
+}
+
+// Levanta el servidor HTTP que expone las métricas en `http://127.0.0.1:<port>/metrics`.
+//
+// Retorna:
+// El futuro que atiende los pedidos hasta que se lo cancele, o un error si no se pudo abrir el puerto.
+pub fn serve_metrics(
+    port: &str,
+    metrics: Arc<StoreMetrics>,
+    store_addr: Addr<Store>,
+) -> io::Result<impl Future<Output = hyper::Result<()>>> {
+    let listener = std::net::TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    listener.set_nonblocking(true)?;
+    let make_service = make_service_fn(move |_conn| {
+        let metrics = metrics.clone();
+        let store_addr = store_addr.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let metrics = metrics.clone();
+                let store_addr = store_addr.clone();
+                async move {
+                    let response = if req.method() == Method::GET && req.uri().path() == "/metrics"
+                    {
+                        Response::builder()
+                            .header("Content-Type", TextEncoder::new().format_type())
+                            .body(Body::from(metrics.render(&store_addr).await))
+                    } else {
+                        Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty())
+                    };
+                    Ok::<_, Infallible>(response.unwrap())
+                }
+            }))
+        }
+    });
+    let server = Server::from_tcp(listener).map_err(io::Error::other)?;
+    info!(port = port; "Métricas expuestas en /metrics");
+    Ok(server.serve(make_service))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use crate::journal::{Journal, StoreSnapshot};
+    use crate::product::Product;
+    use actix::Actor;
+
+    #[actix_rt::test]
+    async fn renders_counters_and_product_stock() {
+        let metrics = Arc::new(StoreMetrics::new());
+        let products = [(3, Product { id: 3, amount: 7 })].into_iter().collect();
+        let store_addr = Store::new(
+            StoreConfig::default(),
+            StoreSnapshot::new(products),
+            Arc::new(Journal::disabled()),
+            metrics.clone(),
+        )
+        .start();
+        metrics.order_processed(OrderOrigin::Ecommerce, true);
+        metrics.order_processed(OrderOrigin::Local, false);
+        metrics.delivery_finished(false);
+
+        let text = metrics.render(&store_addr).await;
+        assert!(text.contains("store_orders_received_total{origin=\"ecommerce\"} 1"));
+        assert!(text.contains("store_orders_rejected_total{origin=\"local\"} 1"));
+        assert!(text.contains("store_deliveries_total{result=\"failed\"} 1"));
+        assert!(text.contains("store_product_stock{product=\"3\",state=\"available\"} 7"));
+    }
+}
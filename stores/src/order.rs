@@ -0,0 +1,246 @@
+use crate::journal::{Journal, JournalEntry};
+use crate::messages::DeliveryReport;
+use crate::product::Product;
+use actix::Recipient;
+use log::error;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+pub use store_protocol::{Order, OrderId, OrderOrigin, OrderState, Priority};
+
+// Pedidos terminados que se siguen pudiendo consultar. Los más viejos se olvidan.
+pub const MAX_FINISHED_ORDERS: usize = 1024;
+
+// Cantidad de ids que se reservan en el journal de una sola vez.
+const ID_BLOCK: OrderId = 1024;
+
+// Pedido reservado que espera en `orders_blocked` a que lo tome un delivery.
+//
+// Atributos:
+// * `order_id`: Pedido al que corresponde la entrega.
+// * `items`: Productos a entregar, todos juntos en un mismo envío.
+// * `notify`: A quién avisarle el resultado del delivery. Los pedidos recuperados del journal
+//   no lo tienen porque la conexión por la que llegaron ya no existe; sus avisos los recibe la
+//   conexión que esté anotada en el store (ver `Store::report`).
+// * `attempts`: Intentos de entrega que ya fallaron.
+// * `last_worker`: Delivery que hizo el último intento, si hubo alguno.
+// * `priority`: Prioridad con la que el pedido espera en la cola de delivery.
+#[derive(Clone)]
+pub struct BlockedOrder {
+    pub order_id: OrderId,
+    pub items: Vec<Product>,
+    pub notify: Option<Recipient<DeliveryReport>>,
+    pub priority: Priority,
+    pub attempts: u32,
+    pub last_worker: Option<usize>,
+}
+
+impl BlockedOrder {
+    // Crea un pedido bloqueado que todavía no tuvo ningún intento de entrega.
+    pub fn new(
+        order_id: OrderId,
+        items: Vec<Product>,
+        notify: Option<Recipient<DeliveryReport>>,
+        priority: Priority,
+    ) -> BlockedOrder {
+        BlockedOrder {
+            order_id,
+            items,
+            notify,
+            priority,
+            attempts: 0,
+            last_worker: None,
+        }
+    }
+}
+
+// Registro en memoria de los pedidos del store, indexado por id.
+//
+// Los pedidos en curso se guardan hasta que terminan; de los terminados solo se guardan los últimos
+// `max_finished`, para que la memoria no crezca sin límite.
+//
+// Los ids se reservan de a bloques en el journal (con `NextOrder`) antes de usarlos, así ningún id se
+// repite después de una caída aunque el pedido no haya dejado otro rastro en el journal (por ejemplo
+// si se rechazó).
+//
+// Atributos:
+// * `next_id`: Id del próximo pedido.
+// * `reserved_until`: Primer id que todavía no se reservó en el journal.
+// * `orders`: Pedidos en curso y últimos terminados.
+// * `finished`: Ids de los pedidos terminados, del más viejo al más nuevo.
+// * `max_finished`: Cantidad de pedidos terminados que se guardan.
+// * `journal`: Journal donde se reservan los ids.
+pub struct OrderRegistry {
+    next_id: OrderId,
+    reserved_until: OrderId,
+    orders: HashMap<OrderId, Order>,
+    finished: VecDeque<OrderId>,
+    max_finished: usize,
+    journal: Arc<Journal>,
+}
+
+impl OrderRegistry {
+    // Crea un registro vacío cuyo primer pedido tendrá el id `next_id`.
+    pub fn new(next_id: OrderId, journal: Arc<Journal>) -> OrderRegistry {
+        OrderRegistry::with_capacity(next_id, journal, MAX_FINISHED_ORDERS)
+    }
+
+    // Igual que `new`, guardando como mucho `max_finished` pedidos terminados.
+    pub fn with_capacity(
+        next_id: OrderId,
+        journal: Arc<Journal>,
+        max_finished: usize,
+    ) -> OrderRegistry {
+        OrderRegistry {
+            next_id,
+            reserved_until: next_id,
+            orders: HashMap::new(),
+            finished: VecDeque::new(),
+            max_finished,
+            journal,
+        }
+    }
+
+    // Registra un pedido nuevo en estado `Received` y devuelve su id.
+    pub fn create(&mut self, items: Vec<Product>, origin: OrderOrigin) -> OrderId {
+        let id = self.next_id;
+        if id >= self.reserved_until {
+            self.reserved_until = id + ID_BLOCK;
+            self.journal.record(JournalEntry::NextOrder {
+                order: self.reserved_until,
+            });
+        }
+        self.next_id += 1;
+        self.orders.insert(
+            id,
+            Order {
+                id,
+                items,
+                origin,
+                state: OrderState::Received,
+            },
+        );
+        id
+    }
+
+    // Registra un pedido recuperado del journal que ya estaba reservado.
+    pub fn restore(&mut self, blocked: &BlockedOrder) {
+        self.next_id = self.next_id.max(blocked.order_id + 1);
+        self.orders.insert(
+            blocked.order_id,
+            Order {
+                id: blocked.order_id,
+                items: blocked.items.clone(),
+                origin: OrderOrigin::Ecommerce,
+                state: OrderState::Reserved,
+            },
+        );
+    }
+
+    // Pasa el pedido `id` al estado `next`.
+    //
+    // Retorna:
+    // `false` si el pedido no existe o la transición no es válida, en cuyo caso no se modifica nada.
+    pub fn transition(&mut self, id: OrderId, next: OrderState) -> bool {
+        match self.orders.get_mut(&id) {
+            Some(order) if order.state.can_transition_to(next) => {
+                order.state = next;
+                if next.is_terminal() {
+                    self.finish(id);
+                }
+                true
+            }
+            Some(order) => {
+                error!(
+                    order_id = id, from:? = order.state, to:? = next;
+                    "Transición inválida del pedido"
+                );
+                false
+            }
+            None => {
+                error!(order_id = id; "No existe el pedido");
+                false
+            }
+        }
+    }
+
+    // Busca un pedido por id. Los pedidos terminados hace mucho ya no se encuentran.
+    pub fn get(&self, id: OrderId) -> Option<&Order> {
+        self.orders.get(&id)
+    }
+
+    // Anota que el pedido terminó y, si hay demasiados terminados, olvida el más viejo.
+    fn finish(&mut self, id: OrderId) {
+        self.finished.push_back(id);
+        while self.finished.len() > self.max_finished {
+            if let Some(oldest) = self.finished.pop_front() {
+                self.orders.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_the_delivery_lifecycle() {
+        let mut registry = OrderRegistry::new(1, Arc::new(Journal::disabled()));
+        let items = vec![Product { id: 3, amount: 2 }, Product { id: 5, amount: 1 }];
+        let id = registry.create(items, OrderOrigin::Ecommerce);
+        assert_eq!(id, 1);
+        assert!(registry.transition(id, OrderState::Reserved));
+        assert!(registry.transition(id, OrderState::OutForDelivery));
+        assert!(registry.transition(id, OrderState::AwaitingRetry));
+        assert!(registry.transition(id, OrderState::OutForDelivery));
+        assert!(registry.transition(id, OrderState::DeliveryFailed));
+        assert!(registry.transition(id, OrderState::Restocked));
+        let order = registry.get(id).unwrap();
+        assert_eq!(order.state, OrderState::Restocked);
+        assert_eq!(order.items.len(), 2);
+    }
+
+    #[test]
+    fn rejects_invalid_transitions() {
+        let mut registry = OrderRegistry::new(1, Arc::new(Journal::disabled()));
+        let id = registry.create(vec![Product { id: 3, amount: 2 }], OrderOrigin::Local);
+        assert!(registry.transition(id, OrderState::Rejected));
+        assert!(!registry.transition(id, OrderState::Delivered));
+        assert_eq!(registry.get(id).unwrap().state, OrderState::Rejected);
+        assert!(!registry.transition(42, OrderState::Reserved));
+    }
+
+    #[test]
+    fn forgets_the_oldest_finished_orders() {
+        let mut registry = OrderRegistry::with_capacity(1, Arc::new(Journal::disabled()), 2);
+        let pending = registry.create(vec![Product { id: 1, amount: 1 }], OrderOrigin::Ecommerce);
+        registry.transition(pending, OrderState::Reserved);
+        let finished: Vec<OrderId> = (0..3)
+            .map(|_| {
+                let id = registry.create(vec![Product { id: 1, amount: 1 }], OrderOrigin::Local);
+                registry.transition(id, OrderState::Rejected);
+                id
+            })
+            .collect();
+        assert!(registry.get(pending).is_some());
+        assert!(registry.get(finished[0]).is_none());
+        assert!(registry.get(finished[1]).is_some());
+        assert!(registry.get(finished[2]).is_some());
+    }
+
+    #[test]
+    fn ids_are_not_reused_after_a_restart() {
+        let path = std::env::temp_dir().join(format!("order_ids_test_{}.log", std::process::id()));
+        let snapshot = crate::journal::StoreSnapshot::new(HashMap::new());
+        let journal = Arc::new(Journal::create(&path, &snapshot).unwrap());
+        let mut registry = OrderRegistry::new(snapshot.next_order_id, journal);
+        // Un pedido rechazado no deja otro rastro en el journal que la reserva de su id
+        let id = registry.create(vec![Product { id: 1, amount: 1 }], OrderOrigin::Local);
+        registry.transition(id, OrderState::Rejected);
+        drop(registry);
+
+        let recovered = crate::journal::replay(&path).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(recovered.next_order_id > id);
+    }
+}
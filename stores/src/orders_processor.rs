@@ -1,35 +1,44 @@
 // store_orders_processor.rs
-use csv::ReaderBuilder;
+use log::debug;
+use order_file::OrderLine;
+use rand::rngs::StdRng;
 use rand::Rng;
-use std::io;
-use std::path::PathBuf;
+use sim_core::Clock;
 use std::time::Duration;
 use tokio::sync::mpsc;
 
 use crate::messages::ReceiveOrder;
+use crate::product::Product;
 
-pub async fn process_line(line: csv::StringRecord) -> ReceiveOrder {
-    // Simula el procesamiento de la línea
-    println!("[LINE PROCESS] Procesando línea: {:?}", line);
-    // TODO: lógica para procesar línea del CSV
-    ReceiveOrder {
-        id: line.get(0).unwrap().parse::<i32>().unwrap(),
-        amount: line.get(1).unwrap().parse::<i32>().unwrap(),
-    }
+// Arma el mensaje para el store con los productos de un pedido del archivo.
+// Los pedidos locales se venden en el momento, así que la prioridad no se usa.
+pub async fn process_line(order: OrderLine) -> ReceiveOrder {
+    debug!(line = order.line, items = order.items.len(); "Procesando línea");
+    let items = order
+        .items
+        .iter()
+        .map(|item| Product {
+            id: item.id,
+            amount: item.amount,
+        })
+        .collect();
+    ReceiveOrder { items }
 }
 
+// Envía por `tx` los pedidos ya validados del archivo, esperando entre 1 y 4 segundos antes de cada
+// uno. Las esperas se sortean con `rng` para poder repetir la simulación con la misma semilla y
+// pasan según `clock`, el reloj de la simulación.
 pub async fn process_store_orders(
-    file_path: PathBuf,
-    tx: mpsc::Sender<csv::StringRecord>,
-) -> io::Result<()> {
-    let mut rdr = ReaderBuilder::new().from_path(file_path)?;
-
-    for result in rdr.records() {
-        let record = result?;
-        let sleep_time = rand::thread_rng().gen_range(1..5);
-        tokio::time::sleep(Duration::from_secs(sleep_time)).await;
-        tx.send(record).await.unwrap();
+    orders: Vec<OrderLine>,
+    tx: mpsc::Sender<OrderLine>,
+    mut rng: StdRng,
+    clock: Clock,
+) {
+    for order in orders {
+        let sleep_time = rng.gen_range(1..5);
+        clock.sleep(Duration::from_secs(sleep_time)).await;
+        if tx.send(order).await.is_err() {
+            break;
+        }
     }
-
-    Ok(())
 }
@@ -1,7 +1,11 @@
-use serde::{Deserialize, Serialize};
+// Los productos del stock y de los pedidos viajan tal cual en los mensajes del protocolo.
+pub use store_protocol::Item as Product;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Product {
-    pub id: i32,
-    pub amount: i32,
+// Describe los productos de un pedido para mostrarlos por pantalla, por ejemplo "2 del producto 1, 1 del producto 4".
+pub fn describe_items(items: &[Product]) -> String {
+    items
+        .iter()
+        .map(|item| format!("{} del producto {}", item.amount, item.id))
+        .collect::<Vec<String>>()
+        .join(", ")
 }
@@ -0,0 +1,59 @@
+use crate::messages::ReserveOutcome;
+use store_protocol::{RejectReason, StoreResponse};
+
+// Los mensajes que se envían por la conexión TCP están en el crate `store_protocol`, que comparten
+// el store, el ecommerce y el generador de carga. Acá solo se arma la respuesta a un pedido a partir
+// de lo que hizo el actor `Store` con él.
+impl From<ReserveOutcome> for StoreResponse {
+    fn from(outcome: ReserveOutcome) -> StoreResponse {
+        let reason = match outcome {
+            ReserveOutcome::Reserved { order_id } => {
+                return StoreResponse::accepted_order(order_id, Vec::new())
+            }
+            ReserveOutcome::PartiallyReserved {
+                order_id,
+                remaining,
+            } => return StoreResponse::accepted_order(order_id, remaining),
+            ReserveOutcome::NotFound { id } => RejectReason::NotFound { product_id: id },
+            ReserveOutcome::InsufficientStock { id, available } => {
+                RejectReason::InsufficientStock {
+                    product_id: id,
+                    available,
+                }
+            }
+            ReserveOutcome::InvalidOrder => RejectReason::Malformed {
+                error: "el pedido no tiene productos o alguna cantidad no es positiva".to_string(),
+            },
+            ReserveOutcome::ShuttingDown => RejectReason::Overloaded,
+        };
+        StoreResponse::rejected_order(reason)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use store_protocol::Envelope;
+
+    #[test]
+    fn rejected_orders_say_why() {
+        let response = StoreResponse::from(ReserveOutcome::InsufficientStock {
+            id: 3,
+            available: 1,
+        });
+        assert_eq!(
+            serde_json::to_string(&Envelope::new(5, response)).unwrap(),
+            r#"{"id":5,"type":"order_result","accepted":false,"order_id":null,"remaining":[],"reason":{"code":"insufficient_stock","product_id":3,"available":1}}"#
+        );
+        let response = StoreResponse::from(ReserveOutcome::Reserved { order_id: 2 });
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"type":"order_result","accepted":true,"order_id":2,"remaining":[]}"#
+        );
+        let response = StoreResponse::from(ReserveOutcome::ShuttingDown);
+        assert_eq!(
+            response,
+            StoreResponse::rejected_order(RejectReason::Overloaded)
+        );
+    }
+}
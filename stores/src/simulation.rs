@@ -0,0 +1,79 @@
+use rand::rngs::StdRng;
+use serde::Deserialize;
+use sim_core::Clock;
+
+// Secuencia de números aleatorios del archivo de pedidos locales.
+pub const ORDERS_STREAM: u64 = 1;
+// Secuencia del inventario aleatorio que se usa si no se indica uno.
+pub const INVENTORY_STREAM: u64 = 2;
+// Primera secuencia de los deliverys: el delivery `n` usa `DELIVERY_STREAM + n`.
+pub const DELIVERY_STREAM: u64 = 100;
+
+// Parámetros que hacen reproducible una simulación del store.
+//
+// Cada parte de la simulación que sortea algo (los tiempos entre pedidos locales, el inventario
+// aleatorio, el tiempo y el resultado de cada entrega) usa su propio generador, derivado de la
+// semilla y de un número de secuencia. Así lo que sortea una parte no depende de cuántas veces
+// sortearon las demás.
+//
+// Atributos:
+// * `seed`: Semilla de los generadores. Si no se indica, cada ejecución usa una semilla al azar.
+// * `virtual_time`: Si el reloj es virtual. Las esperas no pasan en tiempo real: cuando el store no
+//   tiene nada que hacer, el reloj salta directamente al próximo timer.
+// * `clock`: Reloj con el que esperan los deliverys, los reintentos, el aging, los pedidos locales y
+//   el proveedor. No se lee del archivo: lo crea `start_clock` según `virtual_time`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Simulation {
+    pub seed: Option<u64>,
+    pub virtual_time: bool,
+    #[serde(skip)]
+    pub clock: Clock,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation {
+            seed: None,
+            virtual_time: false,
+            clock: Clock::real(),
+        }
+    }
+}
+
+impl Simulation {
+    // Crea el generador de números aleatorios de la secuencia `stream`.
+    pub fn rng(&self, stream: u64) -> StdRng {
+        sim_core::seeded_rng(self.seed, stream)
+    }
+
+    // Arranca el reloj de la simulación, virtual si se pidió tiempo virtual. Tiene que llamarse
+    // antes de lanzar los actores, que se quedan con una copia del reloj.
+    pub fn start_clock(&mut self) {
+        self.clock = if self.virtual_time {
+            Clock::virtual_time()
+        } else {
+            Clock::real()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn same_seed_and_stream_give_the_same_numbers() {
+        let simulation = Simulation {
+            seed: Some(42),
+            ..Simulation::default()
+        };
+        let sample = |stream| -> Vec<u32> {
+            let mut rng = simulation.rng(stream);
+            (0..5).map(|_| rng.gen()).collect()
+        };
+        assert_eq!(sample(ORDERS_STREAM), sample(ORDERS_STREAM));
+        assert_ne!(sample(ORDERS_STREAM), sample(INVENTORY_STREAM));
+    }
+}
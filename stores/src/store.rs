@@ -1,186 +1,1212 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Condvar, Mutex},
-    thread,
-    time::Duration,
-};
+use std::collections::{HashMap, VecDeque};
 
-use crate::messages::{BlockProduct, ReceiveOrder};
-use crate::product::Product;
-use actix::{Actor, Context, Handler};
-use rand::{
-    distributions::{Bernoulli, Distribution},
-    Rng,
+use crate::config::{DeliveryTime, RetryPolicy, StoreConfig};
+use crate::delivery::DeliveryWorker;
+use crate::delivery_queue::DeliveryQueue;
+use crate::journal::{Journal, JournalEntry, StoreSnapshot};
+use crate::messages::{
+    AdjustStock, BlockedOrderStatus, Deliver, DeliveryFinished, DeliveryReport, GetBlockedOrders,
+    GetDeliveryWorkers, GetLowStock, GetOrder, QueryStock, ReceiveOrder, ReserveOrder,
+    ReserveOutcome, Restock, SetDeliveryWorkers, Shutdown, StockLevel, StopDelivery,
+    SubscribeReports, UnsentReport, WorkerStatus,
 };
+use crate::metrics::StoreMetrics;
+use crate::order::{
+    BlockedOrder, Order, OrderId, OrderOrigin, OrderRegistry, OrderState, Priority,
+};
+use crate::product::{describe_items, Product};
+use crate::simulation::{Simulation, DELIVERY_STREAM};
+use actix::{
+    Actor, ActorFutureExt, Addr, AsyncContext, Context, Handler, Recipient, ResponseFuture,
+    WrapFuture,
+};
+use log::{debug, info, warn};
+use rand::distributions::Bernoulli;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
 
-use rand::thread_rng;
-
-// Constante para calcular si se entrego o no un pedido
-const PROBABILITY_OF_SUCCESS_DELIVERY: f64 = 0.8;
-
-// Constante para determinar la cantidad de procesos dedicados a realizar el delivery
-const AMAOUNT_OF_DELIVERY_PROCESS: u32 = 5;
+// Avisos de delivery que se guardan mientras no hay ninguna conexión que los reciba.
+const MAX_PENDING_REPORTS: usize = 10_000;
 
 pub struct Store {
-    products: Arc<Mutex<HashMap<i32, Product>>>,
-    orders_blocked: Arc<Mutex<Vec<Product>>>, //Productos bloqueados para ser retirados
-    condv_orders: Arc<Condvar>, //Vamos a estar notificando a los procesos cuando se ponga un nuevo producto para hacer delivery
-    delivery_process: Vec<thread::JoinHandle<()>>, //Pool de threads encargados de hacer el delivery
+    products: HashMap<i32, Product>,
+    orders_blocked: DeliveryQueue, //Pedidos bloqueados esperando un delivery, por prioridad
+    awaiting_retry: HashMap<OrderId, BlockedOrder>, //Pedidos cuya entrega fallo y esperan para reintentarse
+    in_delivery: HashMap<usize, (OrderId, Vec<Product>)>, //Pedido que esta entregando cada delivery
+    orders: OrderRegistry, //Registro de los pedidos en curso y los ultimos terminados
+    delivery_workers: HashMap<usize, Addr<DeliveryWorker>>, //Pool de actores encargados de hacer el delivery
+    idle_workers: Vec<usize>, //Deliverys que no estan entregando ningun pedido
+    target_workers: usize,    //Cantidad de deliverys que se quiere tener en el pool
+    next_worker_id: usize,
+    shutting_down: bool, //Se activa al cerrar el store para que los deliverys terminen
+    shutdown_waiters: Vec<oneshot::Sender<HashMap<i32, Product>>>, //Quienes esperan a que terminen los deliverys
     bernoulli_dist: Bernoulli,
+    delivery_time: DeliveryTime,
+    retry_policy: RetryPolicy,
+    simulation: Simulation, //De aca salen los generadores de numeros aleatorios de los deliverys
+    journal: Arc<Journal>, //Registro de las modificaciones del stock para recuperarlo ante una caida
+    metrics: Arc<StoreMetrics>,
+    report_subscribers: Vec<Recipient<DeliveryReport>>, //Conexiones anotadas para recibir avisos, la ultima es la mas nueva
+    pending_reports: VecDeque<DeliveryReport>, //Avisos que no se pudieron enviar porque no habia conexion
 }
 
 impl Store {
-    pub fn new() -> Store {
-        let mut store = Store {
-            products: Arc::new(Mutex::new(HashMap::new())),
-            orders_blocked: Arc::new(Mutex::new(Vec::new())),
-            condv_orders: Arc::new(Condvar::new()),
-            delivery_process: Vec::new(),
-            bernoulli_dist: Bernoulli::new(PROBABILITY_OF_SUCCESS_DELIVERY).unwrap(),
-        };
-
-        //Se implemento así porque Store no impmlementa el metodo clone.
-        for i in 0..AMAOUNT_OF_DELIVERY_PROCESS {
-            let products: Arc<Mutex<HashMap<i32, Product>>> = store.products.clone();
-            let orders_blocked: Arc<Mutex<Vec<Product>>> = store.orders_blocked.clone();
-            let condv_orders: Arc<Condvar> = store.condv_orders.clone();
-            store.delivery_process.push(thread::spawn(move || {
-                delivery_logic(
-                    i,
-                    products,
-                    orders_blocked,
-                    condv_orders,
-                    store.bernoulli_dist,
-                )
-            }));
-        }
-
-        //Insertamos algunos productos dentro de mi stock. Es mi stock inicial.
-        {
-            let mut products_guard = store.products.lock().unwrap();
-            for product_id in 0..10{
-                let random_amount: i32 = thread_rng().gen_range(5..15);
-                products_guard.insert(product_id, Product { id: product_id, amount: random_amount });
+    // Crea el store a partir de su estado inicial. El pool de deliverys se lanza al iniciar el actor.
+    // Los pedidos bloqueados del estado (recuperados del journal) se vuelven a poner en la cola de delivery.
+    // La cantidad de deliveries, su probabilidad de éxito y el tiempo de entrega salen de `config`.
+    pub fn new(
+        config: StoreConfig,
+        initial_state: StoreSnapshot,
+        journal: Arc<Journal>,
+        metrics: Arc<StoreMetrics>,
+    ) -> Store {
+        let mut orders = OrderRegistry::new(initial_state.next_order_id, journal.clone());
+        let mut orders_blocked = DeliveryQueue::new(
+            Duration::from_secs_f64(config.standard_aging_secs),
+            config.simulation.clock.clone(),
+        );
+        for blocked in initial_state.orders_blocked {
+            orders.restore(&blocked);
+            orders_blocked.push(blocked);
+        }
+        Store {
+            products: initial_state.products,
+            orders_blocked,
+            awaiting_retry: HashMap::new(),
+            in_delivery: HashMap::new(),
+            orders,
+            delivery_workers: HashMap::new(),
+            idle_workers: Vec::new(),
+            target_workers: config.delivery_workers,
+            next_worker_id: 0,
+            shutting_down: false,
+            shutdown_waiters: Vec::new(),
+            bernoulli_dist: Bernoulli::new(config.delivery_success_probability)
+                .expect("Error al crear la distribucion de Bernoulli"),
+            delivery_time: config.delivery_time,
+            retry_policy: config.delivery_retry,
+            simulation: config.simulation,
+            journal,
+            metrics,
+            report_subscribers: Vec::new(),
+            pending_reports: VecDeque::new(),
+        }
+    }
+
+    // Avisa el resultado del delivery de un pedido a la conexión por la que llegó. Si esa conexión
+    // ya se cerró (o el pedido se recuperó del journal) se le avisa a la conexión anotada más nueva,
+    // y si no hay ninguna el aviso se guarda hasta que se anote una, para que el ecommerce no pierda
+    // los avisos cuando se reconecta.
+    fn report(&mut self, order: &BlockedOrder, delivered: bool) {
+        let report = DeliveryReport {
+            order_id: order.order_id,
+            delivered,
+        };
+        if let Some(notify) = order.notify.as_ref().filter(|notify| notify.connected()) {
+            notify.do_send(report);
+            return;
+        }
+        self.forward_report(report, None);
+    }
+
+    // Envía un aviso de delivery a la conexión anotada más nueva, salvo a `skip`, o lo guarda hasta
+    // que se anote una si no hay ninguna.
+    fn forward_report(&mut self, report: DeliveryReport, skip: Option<&Recipient<DeliveryReport>>) {
+        self.report_subscribers
+            .retain(|subscriber| subscriber.connected() && Some(subscriber) != skip);
+        match self.report_subscribers.last() {
+            Some(subscriber) => subscriber.do_send(report),
+            None => {
+                debug!(order_id = report.order_id; "No hay conexión para avisar, guardo el aviso");
+                if self.pending_reports.len() == MAX_PENDING_REPORTS {
+                    if let Some(dropped) = self.pending_reports.pop_front() {
+                        warn!(
+                            order_id = dropped.order_id;
+                            "Demasiados avisos guardados, descarto el más viejo"
+                        );
+                    }
+                }
+                self.pending_reports.push_back(report);
             }
         }
-        store.bernoulli_dist = Bernoulli::new(PROBABILITY_OF_SUCCESS_DELIVERY)
-            .expect("Error al crear la distribucion de Bernoulli");
-        store
     }
 
-    //Me devuelve si el producto esta disponible. En el caso de que este lo elimino del stock.
-    pub fn get_product(&mut self, id: i32, amount: i32) -> bool {
-        let mut products_guard = self.products.lock().unwrap();
-        if let Some(product) = products_guard.get(&id) {
-            println!("\x1b[34m[ACTOR STORE] Se encontro el producto\x1b[0m");
-            if product.amount >= amount {
-                let new_amount = product.amount - amount;
-                products_guard.insert(
-                    id,
-                    Product {
-                        id,
-                        amount: new_amount,
-                    },
-                );
-                println!("\x1b[32m[ACTOR STORE] Producto disponible para entregar\x1b[0m \n");
-                true
-            } else {
-                println!("\x1b[31m[ACTOR STORE] No hay la cantidad requerida\x1b[0m \n");
-                false
+    //Me devuelve si los productos estan disponibles. En el caso de que esten los elimino del stock.
+    pub fn get_products(&mut self, order_id: OrderId, items: Vec<Product>) -> bool {
+        matches!(
+            self.take_items(order_id, items, None),
+            ReserveOutcome::Reserved { .. }
+        )
+    }
+
+    // Busca los productos del pedido y, si hay la cantidad pedida de todos, la descuenta del stock.
+    // Si falta alguno no se descuenta ninguno.
+    // Si se indica `block` (a quién avisarle el resultado y con qué prioridad) además deja el pedido
+    // en `orders_blocked` para que lo entregue el delivery. Si no, el pedido se considera retirado en el local.
+    fn take_items(
+        &mut self,
+        order_id: OrderId,
+        items: Vec<Product>,
+        block: Option<(Recipient<DeliveryReport>, Priority)>,
+    ) -> ReserveOutcome {
+        if let Err(outcome) = self.check_stock(&items) {
+            match &outcome {
+                ReserveOutcome::NotFound { id } => info!(
+                    order_id = order_id, product_id = id;
+                    "No se encontro el producto. Pedido rechazado"
+                ),
+                ReserveOutcome::InsufficientStock { id, available } => info!(
+                    order_id = order_id, product_id = id, available = available;
+                    "No hay la cantidad requerida del producto. Pedido rechazado"
+                ),
+                _ => info!(order_id = order_id; "El pedido no es valido. Pedido rechazado"),
             }
+            self.orders.transition(order_id, OrderState::Rejected);
+            return outcome;
+        }
+        debug!(order_id = order_id; "Se encontraron todos los productos");
+
+        for item in &items {
+            if let Some(product) = self.products.get_mut(&item.id) {
+                product.amount -= item.amount;
+            }
+        }
+        let stock = stock_of(&self.products, &items);
+        self.orders.transition(order_id, OrderState::Reserved);
+        info!(order_id = order_id; "Productos disponibles para entregar. Pedido reservado");
+        if let Some((notify, priority)) = block {
+            self.orders_blocked.push(BlockedOrder::new(
+                order_id,
+                items.clone(),
+                Some(notify),
+                priority,
+            ));
+            self.journal.record(JournalEntry::Reserved {
+                order: order_id,
+                items,
+                stock,
+                priority,
+            });
+            debug!(order_id = order_id, priority:? = priority; "Productos bloqueados");
+            self.dispatch_deliveries();
         } else {
-            println!("\x1b[31m[ACTOR STORE] No se encontro el producto\x1b[0m \n");
-            false
+            self.journal.record(JournalEntry::Sold {
+                order: order_id,
+                items,
+                stock,
+            });
+            self.orders.transition(order_id, OrderState::Delivered);
+        }
+        ReserveOutcome::Reserved { order_id }
+    }
+
+    // Verifica que el pedido tenga productos con cantidades positivas y que haya stock de todos.
+    // Si un producto aparece más de una vez en el pedido se suman sus cantidades.
+    fn check_stock(&self, items: &[Product]) -> Result<(), ReserveOutcome> {
+        if items.is_empty() || items.iter().any(|item| item.amount <= 0) {
+            return Err(ReserveOutcome::InvalidOrder);
+        }
+        let mut requested: HashMap<i32, i32> = HashMap::new();
+        for item in items {
+            *requested.entry(item.id).or_insert(0) += item.amount;
+        }
+        for item in items {
+            let Some(product) = self.products.get(&item.id) else {
+                return Err(ReserveOutcome::NotFound { id: item.id });
+            };
+            if product.amount < requested[&item.id] {
+                return Err(ReserveOutcome::InsufficientStock {
+                    id: item.id,
+                    available: product.amount,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // Separa los productos del pedido en la parte que hay en stock y la que falta.
+    // Si no hay stock de ningún producto (o el pedido no es válido) se deja el pedido entero para que
+    // `take_items` lo rechace indicando el motivo.
+    fn split_available(&self, items: Vec<Product>) -> (Vec<Product>, Vec<Product>) {
+        if items.is_empty() || items.iter().any(|item| item.amount <= 0) {
+            return (items, Vec::new());
+        }
+        let mut stock: HashMap<i32, i32> = HashMap::new();
+        let mut available = Vec::new();
+        let mut remaining = Vec::new();
+        for item in &items {
+            let left = stock.entry(item.id).or_insert_with(|| {
+                self.products
+                    .get(&item.id)
+                    .map_or(0, |product| product.amount)
+            });
+            let taken = item.amount.min(*left);
+            *left -= taken;
+            if taken > 0 {
+                available.push(Product {
+                    id: item.id,
+                    amount: taken,
+                });
+            }
+            if taken < item.amount {
+                remaining.push(Product {
+                    id: item.id,
+                    amount: item.amount - taken,
+                });
+            }
+        }
+        if available.is_empty() {
+            return (items, Vec::new());
+        }
+        (available, remaining)
+    }
+
+    // Le asigna pedidos bloqueados a los deliverys libres mientras haya de ambos, en orden de prioridad.
+    // Si la política de reintentos lo pide, un reintento no lo hace el mismo delivery que el intento
+    // anterior, salvo que sea el único del pool.
+    fn dispatch_deliveries(&mut self) {
+        let avoid_last_worker =
+            self.retry_policy.different_worker && self.delivery_workers.len() > 1;
+        while !self.idle_workers.is_empty() {
+            let idle_workers = &self.idle_workers;
+            let free_worker_for = |order: &BlockedOrder| {
+                let avoid = if avoid_last_worker {
+                    order.last_worker
+                } else {
+                    None
+                };
+                idle_workers
+                    .iter()
+                    .rposition(|worker| Some(*worker) != avoid)
+            };
+            // Saco el próximo pedido que pueda tomar algún delivery libre.
+            let Some(mut order) = self
+                .orders_blocked
+                .pop_first(|order| free_worker_for(order).is_some())
+            else {
+                return;
+            };
+            let idle_index = free_worker_for(&order).unwrap();
+            let worker = self.idle_workers.remove(idle_index);
+            order.last_worker = Some(worker);
+            self.orders
+                .transition(order.order_id, OrderState::OutForDelivery);
+            self.in_delivery
+                .insert(worker, (order.order_id, order.items.clone()));
+            self.delivery_workers[&worker].do_send(Deliver { order });
+        }
+    }
+
+    // Vuelve a poner en la cola de delivery un pedido que terminó de esperar su reintento.
+    fn retry_delivery(&mut self, order_id: OrderId) {
+        if let Some(order) = self.awaiting_retry.remove(&order_id) {
+            info!(order_id = order_id, attempt = order.attempts + 1; "Reintento la entrega del pedido");
+            self.orders_blocked.push(order);
+            self.dispatch_deliveries();
+        }
+    }
+
+    // Agranda o achica el pool de deliverys hasta `target_workers`.
+    // Si se achica, primero se van los deliverys libres; los que están entregando se van al terminar.
+    fn resize_delivery_pool(&mut self, ctx: &mut Context<Self>) {
+        while self.delivery_workers.len() < self.target_workers {
+            let id = self.next_worker_id;
+            self.next_worker_id += 1;
+            let worker = DeliveryWorker::new(
+                id,
+                ctx.address().recipient(),
+                self.bernoulli_dist,
+                self.delivery_time,
+                self.simulation.rng(DELIVERY_STREAM + id as u64),
+                self.simulation.clock.clone(),
+            )
+            .start();
+            self.delivery_workers.insert(id, worker);
+            self.idle_workers.push(id);
+        }
+        while self.delivery_workers.len() > self.target_workers {
+            let Some(worker) = self.idle_workers.pop() else {
+                break;
+            };
+            self.retire_worker(worker);
+        }
+        self.dispatch_deliveries();
+    }
+
+    fn retire_worker(&mut self, worker: usize) {
+        if let Some(addr) = self.delivery_workers.remove(&worker) {
+            addr.do_send(StopDelivery);
         }
     }
-    
-    /*
-    pub fn wait_for_delivery_completion(&mut self) {
-        // Iterar sobre los threads de entrega sin mover el vector completo
-        while let Some(handle) = self.delivery_process.pop() {
-            handle.join().unwrap();
+
+    // Cancela los pedidos que todavía no tomó ningún delivery (o que esperan un reintento) y devuelve
+    // sus productos al stock.
+    // Se les avisa a quienes hicieron los pedidos que no se van a entregar.
+    fn cancel_pending_deliveries(&mut self) {
+        let mut pending = self.orders_blocked.drain();
+        pending.extend(self.awaiting_retry.drain().map(|(_, order)| order));
+        for blocked in pending {
+            info!(order_id = blocked.order_id; "Se cancela el pedido y vuelve al stock");
+            let stock = return_items(&mut self.products, &blocked.items);
+            self.journal.record(JournalEntry::Cancelled {
+                order: blocked.order_id,
+                stock,
+            });
+            self.orders
+                .transition(blocked.order_id, OrderState::Cancelled);
+            self.report(&blocked, false);
+        }
+    }
+
+    // Si el store se está cerrando y ya no quedan deliverys, les devuelve el inventario final
+    // a quienes están esperando el cierre.
+    fn finish_shutdown_if_idle(&mut self) {
+        if !self.shutting_down || !self.delivery_workers.is_empty() {
+            return;
+        }
+        for waiter in self.shutdown_waiters.drain(..) {
+            let _ = waiter.send(self.products.clone());
         }
     }
-    */
 }
 
 impl Actor for Store {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.resize_delivery_pool(ctx);
+    }
 }
 
 impl Handler<ReceiveOrder> for Store {
     type Result = bool;
 
     fn handle(&mut self, msg: ReceiveOrder, _ctx: &mut Self::Context) -> Self::Result {
-        let id = msg.id;
-        let amount = msg.amount;
-        println!(
-            "\x1b[34m[ACTOR STORE] Recibi un pedido de {} con una cantidad {}\x1b[0m",
-            id, amount
+        let order_id = self.orders.create(msg.items.clone(), OrderOrigin::Local);
+        info!(
+            order_id = order_id, origin = "local", items:% = describe_items(&msg.items);
+            "Recibi un pedido"
         );
         //Busco si tengo stock
-        self.get_product(id, amount)
+        let accepted = self.get_products(order_id, msg.items);
+        self.metrics.order_processed(OrderOrigin::Local, accepted);
+        accepted
+    }
+}
+
+// Me llega un pedido de ecomerce: verifico el stock, lo descuento y lo mando a delivery en un solo paso
+impl Handler<ReserveOrder> for Store {
+    type Result = ReserveOutcome;
+
+    fn handle(&mut self, msg: ReserveOrder, _ctx: &mut Self::Context) -> Self::Result {
+        let (items, remaining) = if msg.allow_partial && !self.shutting_down {
+            self.split_available(msg.items)
+        } else {
+            (msg.items, Vec::new())
+        };
+        let order_id = self.orders.create(items.clone(), OrderOrigin::Ecommerce);
+        info!(
+            order_id = order_id, origin = "ecommerce", items:% = describe_items(&items);
+            "Recibi un pedido"
+        );
+        if self.shutting_down {
+            info!(order_id = order_id; "El store se esta cerrando. Pedido rechazado");
+            self.orders.transition(order_id, OrderState::Rejected);
+            self.metrics.order_processed(OrderOrigin::Ecommerce, false);
+            return ReserveOutcome::ShuttingDown;
+        }
+        let outcome = self.take_items(order_id, items, Some((msg.notify, msg.priority)));
+        self.metrics.order_processed(
+            OrderOrigin::Ecommerce,
+            matches!(outcome, ReserveOutcome::Reserved { .. }),
+        );
+        match outcome {
+            ReserveOutcome::Reserved { order_id } if !remaining.is_empty() => {
+                info!(
+                    order_id = order_id, remaining:% = describe_items(&remaining);
+                    "El pedido se reservo parcialmente"
+                );
+                ReserveOutcome::PartiallyReserved {
+                    order_id,
+                    remaining,
+                }
+            }
+            outcome => outcome,
+        }
+    }
+}
+
+// Anota una conexión para recibir los avisos de delivery y le envía los que estaban guardados.
+impl Handler<SubscribeReports> for Store {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeReports, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.pending_reports.is_empty() {
+            info!(
+                reports = self.pending_reports.len();
+                "Envío los avisos de delivery guardados a la conexión nueva"
+            );
+        }
+        for report in self.pending_reports.drain(..) {
+            msg.recipient.do_send(report);
+        }
+        self.report_subscribers
+            .retain(|subscriber| subscriber.connected());
+        self.report_subscribers.push(msg.recipient);
     }
 }
 
-// Me llega un pedido de ecomerce lo bloqueo y lo mando a delivery
-impl Handler<BlockProduct> for Store {
+// Recibe un aviso que una conexión no pudo escribir. Esa conexión deja de recibir avisos y el aviso
+// se le envía a otra o se guarda para la próxima que se anote.
+impl Handler<UnsentReport> for Store {
     type Result = ();
 
-    fn handle(&mut self, msg: BlockProduct, _ctx: &mut Self::Context) -> Self::Result {
-        self.orders_blocked.lock().unwrap().push(Product {
+    fn handle(&mut self, msg: UnsentReport, _ctx: &mut Self::Context) -> Self::Result {
+        warn!(
+            order_id = msg.report.order_id;
+            "No se pudo enviar el aviso de delivery, lo reenvío por otra conexión"
+        );
+        self.forward_report(msg.report, Some(&msg.from));
+    }
+}
+
+impl Handler<GetOrder> for Store {
+    type Result = Option<Order>;
+
+    fn handle(&mut self, msg: GetOrder, _ctx: &mut Self::Context) -> Self::Result {
+        self.orders.get(msg.id).cloned()
+    }
+}
+
+// Un delivery terminó una entrega: registro el resultado, le aviso al ecommerce y le doy otro pedido.
+// Si la entrega falló y quedan intentos, el pedido se reintenta después de esperar el backoff.
+impl Handler<DeliveryFinished> for Store {
+    type Result = ();
+
+    fn handle(&mut self, msg: DeliveryFinished, ctx: &mut Self::Context) -> Self::Result {
+        let mut order = msg.order;
+        let order_id = order.order_id;
+        self.in_delivery.remove(&msg.worker);
+        self.metrics.delivery_finished(msg.delivered);
+        if msg.delivered {
+            self.journal
+                .record(JournalEntry::Delivered { order: order_id });
+            self.orders.transition(order_id, OrderState::Delivered);
+            self.report(&order, true);
+        } else {
+            order.attempts += 1;
+            if order.attempts < self.retry_policy.max_attempts && !self.shutting_down {
+                let backoff = self.retry_policy.backoff(order.attempts);
+                warn!(
+                    order_id = order_id,
+                    attempt = order.attempts,
+                    max_attempts = self.retry_policy.max_attempts,
+                    backoff_secs = backoff.as_secs_f64();
+                    "Falló la entrega del pedido, se reintenta"
+                );
+                self.orders.transition(order_id, OrderState::AwaitingRetry);
+                self.awaiting_retry.insert(order_id, order);
+                let wait = self.simulation.clock.sleep(backoff).into_actor(self);
+                ctx.spawn(wait.map(move |_, act, _ctx| act.retry_delivery(order_id)));
+            } else {
+                // Se agotaron los intentos: devuelvo los productos al stock
+                warn!(
+                    order_id = order_id, attempts = order.attempts;
+                    "No se pudo entregar el pedido. Vuelve al stock"
+                );
+                let stock = return_items(&mut self.products, &order.items);
+                self.journal.record(JournalEntry::DeliveryFailed {
+                    order: order_id,
+                    stock,
+                });
+                self.orders.transition(order_id, OrderState::DeliveryFailed);
+                self.orders.transition(order_id, OrderState::Restocked);
+                self.report(&order, false);
+            }
+        }
+
+        if self.delivery_workers.len() > self.target_workers {
+            self.retire_worker(msg.worker);
+            self.finish_shutdown_if_idle();
+        } else {
+            self.idle_workers.push(msg.worker);
+            self.dispatch_deliveries();
+        }
+    }
+}
+
+// Cambia el tamaño del pool de deliverys mientras el store está funcionando
+impl Handler<SetDeliveryWorkers> for Store {
+    type Result = usize;
+
+    fn handle(&mut self, msg: SetDeliveryWorkers, ctx: &mut Self::Context) -> Self::Result {
+        if self.shutting_down {
+            return 0;
+        }
+        info!(
+            from = self.target_workers, to = msg.count;
+            "Cambio la cantidad de deliverys"
+        );
+        self.target_workers = msg.count;
+        self.resize_delivery_pool(ctx);
+        self.target_workers
+    }
+}
+
+// Cierre del store: los deliveries terminan la entrega que tienen en curso, los pedidos que no
+// llegaron a tomar vuelven al stock y se espera a que terminen todos los deliverys.
+impl Handler<Shutdown> for Store {
+    type Result = ResponseFuture<HashMap<i32, Product>>;
+
+    fn handle(&mut self, _msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        info!("Cerrando el store, espero a los deliverys");
+        self.shutting_down = true;
+        self.cancel_pending_deliveries();
+        self.target_workers = 0;
+        self.resize_delivery_pool(ctx);
+
+        let (waiter, inventory) = oneshot::channel();
+        self.shutdown_waiters.push(waiter);
+        self.finish_shutdown_if_idle();
+        Box::pin(async move { inventory.await.unwrap_or_default() })
+    }
+}
+
+// Reposición de stock, ya sea del proveedor o recibida por la red
+impl Handler<Restock> for Store {
+    type Result = Option<i32>;
+
+    fn handle(&mut self, msg: Restock, _ctx: &mut Self::Context) -> Self::Result {
+        if msg.amount <= 0 {
+            warn!(product_id = msg.id, amount = msg.amount; "Reposición inválida");
+            return None;
+        }
+        let stock = add_stock(&mut self.products, &self.journal, msg.id, msg.amount);
+        info!(
+            product_id = msg.id, amount = msg.amount, stock = stock;
+            "Se repuso stock del producto"
+        );
+        Some(stock)
+    }
+}
+
+// Corrección manual del stock disponible de un producto
+impl Handler<AdjustStock> for Store {
+    type Result = Option<i32>;
+
+    fn handle(&mut self, msg: AdjustStock, _ctx: &mut Self::Context) -> Self::Result {
+        if msg.amount < 0 {
+            warn!(product_id = msg.id, amount = msg.amount; "Corrección de stock inválida");
+            return None;
+        }
+        let product = self.products.entry(msg.id).or_insert(Product {
+            id: msg.id,
+            amount: 0,
+        });
+        info!(
+            product_id = msg.id, from = product.amount, to = msg.amount;
+            "Se corrige el stock del producto"
+        );
+        product.amount = msg.amount;
+        self.journal.record(JournalEntry::Stock {
             id: msg.id,
             amount: msg.amount,
         });
-        println!("\x1b[33m[ACTOR STORE] Producto bloqueado\x1b[0m");
-        self.condv_orders.notify_all();
+        Some(msg.amount)
     }
 }
 
-fn delivery_logic(
-    i: u32,
-    products: Arc<Mutex<HashMap<i32, Product>>>,
-    orders_blocked: Arc<Mutex<Vec<Product>>>,
-    condv_orders: Arc<Condvar>,
-    bernoulli_dist: Bernoulli,
-) {
-    loop {
-        let product_to_deliver;
-        {
-            //Espero hasta que tenga algun producto para realizar el delivery
-            let mut _guard = condv_orders
-                .wait_while(orders_blocked.lock().unwrap(), |orders| {
-                    // Espero mientras no haya productos para ser entregados
-                    orders.is_empty()
-                })
-                .unwrap();
-            // Saco un producto de la lista de ordenes.
-            product_to_deliver = _guard.pop().unwrap();
-        }
-        println!(
-            "\x1b[33m[DELIVERY {}] Comenzamos el delivery del producto\x1b[0m",
-            i
-        );
-        thread::sleep(Duration::from_secs(thread_rng().gen_range(5..10) as u64));
-        // Decidir si se resuelve el envio  o no
-        let delivery_success = bernoulli_dist.sample(&mut thread_rng());
-        if delivery_success {
-            // Si se entrega correctamente el delivery
-            println!(
-                "\x1b[33m[DELIVERY {}] Se pudo entregar correctamente el producto {}\x1b[0m",
-                i, product_to_deliver.id
-            );
+impl Handler<GetBlockedOrders> for Store {
+    type Result = Vec<BlockedOrderStatus>;
+
+    fn handle(&mut self, _msg: GetBlockedOrders, _ctx: &mut Self::Context) -> Self::Result {
+        let status = |order: &BlockedOrder, state| BlockedOrderStatus {
+            order_id: order.order_id,
+            items: order.items.clone(),
+            priority: order.priority,
+            attempts: order.attempts,
+            state,
+        };
+        let mut awaiting_retry: Vec<&BlockedOrder> = self.awaiting_retry.values().collect();
+        awaiting_retry.sort_by_key(|order| order.order_id);
+        self.orders_blocked
+            .iter()
+            .map(|order| status(order, OrderState::Reserved))
+            .chain(
+                awaiting_retry
+                    .into_iter()
+                    .map(|order| status(order, OrderState::AwaitingRetry)),
+            )
+            .collect()
+    }
+}
+
+impl Handler<GetDeliveryWorkers> for Store {
+    type Result = Vec<WorkerStatus>;
+
+    fn handle(&mut self, _msg: GetDeliveryWorkers, _ctx: &mut Self::Context) -> Self::Result {
+        let mut ids: Vec<usize> = self.delivery_workers.keys().copied().collect();
+        ids.sort();
+        ids.into_iter()
+            .map(|id| match self.in_delivery.get(&id) {
+                Some((order_id, _)) => WorkerStatus::Delivering {
+                    id,
+                    order_id: *order_id,
+                },
+                None => WorkerStatus::Idle { id },
+            })
+            .collect()
+    }
+}
+
+// Consulta de stock: lo disponible sale de `products`, lo reservado de los pedidos que esperan un
+// delivery o un reintento y lo que está en delivery de los pedidos que tienen los deliverys.
+impl Handler<QueryStock> for Store {
+    type Result = Vec<StockLevel>;
+
+    fn handle(&mut self, msg: QueryStock, _ctx: &mut Self::Context) -> Self::Result {
+        let mut reserved: HashMap<i32, i32> = HashMap::new();
+        let waiting = self
+            .orders_blocked
+            .iter()
+            .chain(self.awaiting_retry.values());
+        for item in waiting.flat_map(|order| &order.items) {
+            *reserved.entry(item.id).or_insert(0) += item.amount;
+        }
+        let mut in_delivery: HashMap<i32, i32> = HashMap::new();
+        for item in self.in_delivery.values().flat_map(|(_, items)| items) {
+            *in_delivery.entry(item.id).or_insert(0) += item.amount;
+        }
+
+        let mut ids: Vec<i32> = if msg.ids.is_empty() {
+            self.products.keys().copied().collect()
         } else {
-            // En el caso de que no se pudo entregar el producto lo devuelvo al stock
-            println!(
-                "\x1b[33m[DELIVERY {}] No se pudo entregar el pedido {}\x1b[0m",
-                i, product_to_deliver.id
-            );
-            if let Some(product_to_restore) =
-                products.lock().unwrap().get_mut(&product_to_deliver.id)
-            {
-                product_to_restore.amount += product_to_deliver.amount;
+            msg.ids
+        };
+        ids.sort();
+        ids.dedup();
+        ids.into_iter()
+            .filter_map(|id| self.products.get(&id))
+            .map(|product| StockLevel {
+                id: product.id,
+                available: product.amount,
+                reserved: reserved.get(&product.id).copied().unwrap_or(0),
+                in_delivery: in_delivery.get(&product.id).copied().unwrap_or(0),
+            })
+            .collect()
+    }
+}
+
+impl Handler<GetLowStock> for Store {
+    type Result = Vec<Product>;
+
+    fn handle(&mut self, msg: GetLowStock, _ctx: &mut Self::Context) -> Self::Result {
+        self.products
+            .values()
+            .filter(|product| product.amount < msg.reorder_point)
+            .cloned()
+            .collect()
+    }
+}
+
+// Suma `amount` unidades al stock del producto `id`, creándolo si no existe, y lo registra en el journal.
+//
+// Retorna:
+// El stock del producto luego de sumarle las unidades.
+fn add_stock(products: &mut HashMap<i32, Product>, journal: &Journal, id: i32, amount: i32) -> i32 {
+    let product = products.entry(id).or_insert(Product { id, amount: 0 });
+    product.amount += amount;
+    journal.record(JournalEntry::Stock {
+        id,
+        amount: product.amount,
+    });
+    product.amount
+}
+
+// Devuelve al stock los productos de un pedido que no se entregó. No lo registra en el journal: el
+// stock resultante va en el mismo evento que el fallo o la cancelación, para que sea atómico.
+//
+// Retorna:
+// El stock de cada producto del pedido luego de devolverle las unidades.
+fn return_items(products: &mut HashMap<i32, Product>, items: &[Product]) -> Vec<Product> {
+    for item in items {
+        let product = products.entry(item.id).or_insert(Product {
+            id: item.id,
+            amount: 0,
+        });
+        product.amount += item.amount;
+    }
+    stock_of(products, items)
+}
+
+// Stock actual de los productos de un pedido, una vez cada uno y ordenados por id.
+fn stock_of(products: &HashMap<i32, Product>, items: &[Product]) -> Vec<Product> {
+    let mut ids: Vec<i32> = items.iter().map(|item| item.id).collect();
+    ids.sort();
+    ids.dedup();
+    ids.iter().map(|id| products[id].clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with(products: Vec<Product>) -> Store {
+        store_with_config(StoreConfig::default(), products)
+    }
+
+    fn store_with_config(config: StoreConfig, products: Vec<Product>) -> Store {
+        let products = products.into_iter().map(|p| (p.id, p)).collect();
+        Store::new(
+            config,
+            StoreSnapshot::new(products),
+            Arc::new(Journal::disabled()),
+            Arc::new(StoreMetrics::new()),
+        )
+    }
+
+    // Hace de ecommerce en los tests, ignora los avisos de delivery.
+    struct Ecommerce;
+
+    impl Actor for Ecommerce {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<DeliveryReport> for Ecommerce {
+        type Result = ();
+
+        fn handle(&mut self, _msg: DeliveryReport, _ctx: &mut Self::Context) {}
+    }
+
+    #[actix_rt::test]
+    async fn restock_adds_to_existing_and_creates_missing_products() {
+        let addr = store_with(vec![Product { id: 1, amount: 2 }]).start();
+        assert_eq!(
+            addr.send(Restock { id: 1, amount: 3 }).await.unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            addr.send(Restock { id: 7, amount: 4 }).await.unwrap(),
+            Some(4)
+        );
+        assert_eq!(addr.send(Restock { id: 7, amount: 0 }).await.unwrap(), None);
+
+        let low = addr.send(GetLowStock { reorder_point: 5 }).await.unwrap();
+        assert_eq!(low.len(), 1);
+        assert_eq!(low[0].id, 7);
+    }
+
+    #[actix_rt::test]
+    async fn cart_orders_reserve_all_items_or_none() {
+        let config = StoreConfig {
+            delivery_workers: 0,
+            ..StoreConfig::default()
+        };
+        let products = vec![Product { id: 1, amount: 5 }, Product { id: 2, amount: 1 }];
+        let addr = store_with_config(config, products).start();
+        let notify = Ecommerce.start().recipient();
+
+        // Falta stock del producto 2: no se descuenta nada del producto 1
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 2 }, Product { id: 2, amount: 2 }],
+                notify: notify.clone(),
+                priority: Priority::Standard,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            ReserveOutcome::InsufficientStock {
+                id: 2,
+                available: 1
+            }
+        );
+
+        // Un mismo producto repetido suma sus cantidades
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 3 }, Product { id: 1, amount: 3 }],
+                notify: notify.clone(),
+                priority: Priority::Standard,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            ReserveOutcome::InsufficientStock {
+                id: 1,
+                available: 5
+            }
+        );
+
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 2 }, Product { id: 2, amount: 1 }],
+                notify,
+                priority: Priority::Standard,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(outcome, ReserveOutcome::Reserved { order_id: 3 });
+        let low = addr.send(GetLowStock { reorder_point: 4 }).await.unwrap();
+        assert_eq!(low.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn partial_orders_reserve_what_is_available() {
+        let config = StoreConfig {
+            delivery_workers: 0,
+            ..StoreConfig::default()
+        };
+        let products = vec![Product { id: 1, amount: 3 }, Product { id: 2, amount: 4 }];
+        let addr = store_with_config(config, products).start();
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![
+                    Product { id: 1, amount: 5 },
+                    Product { id: 2, amount: 1 },
+                    Product { id: 9, amount: 2 },
+                ],
+                notify: Ecommerce.start().recipient(),
+                priority: Priority::Standard,
+                allow_partial: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            ReserveOutcome::PartiallyReserved {
+                order_id: 1,
+                remaining: vec![Product { id: 1, amount: 2 }, Product { id: 9, amount: 2 }],
             }
+        );
+        let order = addr.send(GetOrder { id: 1 }).await.unwrap().unwrap();
+        assert_eq!(
+            order.items,
+            vec![Product { id: 1, amount: 3 }, Product { id: 2, amount: 1 }]
+        );
+
+        // Sin stock de nada el pedido se rechaza igual que sin reservas parciales
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 1 }],
+                notify: Ecommerce.start().recipient(),
+                priority: Priority::Standard,
+                allow_partial: true,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            ReserveOutcome::InsufficientStock {
+                id: 1,
+                available: 0
+            }
+        );
+    }
+
+    #[actix_rt::test]
+    async fn query_stock_reports_available_reserved_and_in_delivery() {
+        // El único delivery tarda mucho, así que el segundo pedido queda esperando
+        let config = StoreConfig {
+            delivery_workers: 1,
+            delivery_time: DeliveryTime::Fixed { secs: 60.0 },
+            ..StoreConfig::default()
+        };
+        let products = vec![Product { id: 1, amount: 10 }, Product { id: 2, amount: 4 }];
+        let addr = store_with_config(config, products).start();
+        let notify = Ecommerce.start().recipient();
+        for amount in [3, 2] {
+            addr.send(ReserveOrder {
+                items: vec![Product { id: 1, amount }],
+                notify: notify.clone(),
+                priority: Priority::Standard,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        }
+
+        let stock = addr.send(QueryStock { ids: vec![1, 7] }).await.unwrap();
+        assert_eq!(
+            stock,
+            vec![StockLevel {
+                id: 1,
+                available: 5,
+                reserved: 2,
+                in_delivery: 3
+            }]
+        );
+        let all = addr.send(QueryStock { ids: Vec::new() }).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].available, 4);
+    }
+
+    #[actix_rt::test]
+    async fn reports_blocked_orders_and_what_each_worker_is_doing() {
+        let config = StoreConfig {
+            delivery_workers: 1,
+            delivery_time: DeliveryTime::Fixed { secs: 60.0 },
+            ..StoreConfig::default()
+        };
+        let addr = store_with_config(config, vec![Product { id: 1, amount: 10 }]).start();
+        let notify = Ecommerce.start().recipient();
+        for priority in [Priority::Standard, Priority::Express] {
+            addr.send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 2 }],
+                notify: notify.clone(),
+                priority,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        }
+
+        let workers = addr.send(GetDeliveryWorkers).await.unwrap();
+        assert_eq!(
+            workers,
+            vec![WorkerStatus::Delivering { id: 0, order_id: 1 }]
+        );
+        let blocked = addr.send(GetBlockedOrders).await.unwrap();
+        assert_eq!(
+            blocked,
+            vec![BlockedOrderStatus {
+                order_id: 2,
+                items: vec![Product { id: 1, amount: 2 }],
+                priority: Priority::Express,
+                attempts: 0,
+                state: OrderState::Reserved,
+            }]
+        );
+
+        // Corregir el stock no toca lo reservado
+        assert_eq!(
+            addr.send(AdjustStock { id: 1, amount: 3 }).await.unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            addr.send(AdjustStock { id: 1, amount: -1 }).await.unwrap(),
+            None
+        );
+        let stock = addr.send(QueryStock { ids: vec![1] }).await.unwrap();
+        assert_eq!((stock[0].available, stock[0].reserved), (3, 2));
+    }
+
+    #[actix_rt::test]
+    async fn shutdown_returns_pending_deliveries_to_stock() {
+        // Sin deliverys el pedido queda esperando hasta que se cierra el store
+        let config = StoreConfig {
+            delivery_workers: 0,
+            ..StoreConfig::default()
+        };
+        let addr = store_with_config(config, vec![Product { id: 1, amount: 5 }]).start();
+        let notify = Ecommerce.start().recipient();
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 2 }],
+                notify: notify.clone(),
+                priority: Priority::Express,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(outcome, ReserveOutcome::Reserved { order_id: 1 });
+
+        let inventory = addr.send(Shutdown).await.unwrap();
+        assert_eq!(inventory[&1].amount, 5);
+        let order = addr.send(GetOrder { id: 1 }).await.unwrap().unwrap();
+        assert_eq!(order.state, OrderState::Cancelled);
+
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 2 }],
+                notify,
+                priority: Priority::Standard,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(outcome, ReserveOutcome::ShuttingDown);
+    }
+
+    #[actix_rt::test]
+    async fn delivery_workers_deliver_orders_and_scale_at_runtime() {
+        let config = StoreConfig {
+            delivery_workers: 1,
+            delivery_success_probability: 1.0,
+            delivery_time: DeliveryTime::Fixed { secs: 0.0 },
+            ..StoreConfig::default()
+        };
+        let addr = store_with_config(config, vec![Product { id: 1, amount: 5 }]).start();
+        let notify = Ecommerce.start().recipient();
+        for _ in 0..2 {
+            let msg = ReserveOrder {
+                items: vec![Product { id: 1, amount: 1 }],
+                notify: notify.clone(),
+                priority: Priority::Standard,
+                allow_partial: false,
+            };
+            addr.send(msg).await.unwrap();
         }
+        assert_eq!(addr.send(SetDeliveryWorkers { count: 3 }).await.unwrap(), 3);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        for id in 1..=2 {
+            let order = addr.send(GetOrder { id }).await.unwrap().unwrap();
+            assert_eq!(order.state, OrderState::Delivered);
+        }
+        assert_eq!(addr.send(SetDeliveryWorkers { count: 0 }).await.unwrap(), 0);
+        let inventory = addr.send(Shutdown).await.unwrap();
+        assert_eq!(inventory[&1].amount, 3);
+    }
+
+    #[actix_rt::test]
+    async fn failed_deliveries_are_retried_before_restocking() {
+        let config = StoreConfig {
+            delivery_workers: 2,
+            delivery_success_probability: 0.0,
+            delivery_time: DeliveryTime::Fixed { secs: 0.0 },
+            delivery_retry: RetryPolicy {
+                max_attempts: 3,
+                initial_backoff_secs: 0.01,
+                different_worker: true,
+                ..RetryPolicy::default()
+            },
+            ..StoreConfig::default()
+        };
+        let addr = store_with_config(config, vec![Product { id: 1, amount: 5 }]).start();
+        let msg = ReserveOrder {
+            items: vec![Product { id: 1, amount: 2 }],
+            notify: Ecommerce.start().recipient(),
+            priority: Priority::Standard,
+            allow_partial: false,
+        };
+        addr.send(msg).await.unwrap();
+
+        // Entre intentos el producto sigue reservado
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        let order = addr.send(GetOrder { id: 1 }).await.unwrap().unwrap();
+        assert_eq!(order.state, OrderState::AwaitingRetry);
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let order = addr.send(GetOrder { id: 1 }).await.unwrap().unwrap();
+        assert_eq!(order.state, OrderState::Restocked);
+        let low = addr.send(GetLowStock { reorder_point: 6 }).await.unwrap();
+        assert_eq!(low[0].amount, 5);
+    }
+
+    // Hace de ecommerce y anota los avisos de delivery en el orden en que llegan.
+    struct Recorder(Arc<std::sync::Mutex<Vec<DeliveryReport>>>);
+
+    impl Actor for Recorder {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<DeliveryReport> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, msg: DeliveryReport, _ctx: &mut Self::Context) {
+            self.0.lock().unwrap().push(msg);
+        }
+    }
+
+    // Corre una simulación con tiempos y resultados de entrega aleatorios y devuelve los avisos
+    // que recibió el ecommerce y el stock final.
+    async fn simulate(seed: u64) -> (Vec<(OrderId, bool)>, i32) {
+        let mut simulation = Simulation {
+            seed: Some(seed),
+            virtual_time: true,
+            ..Simulation::default()
+        };
+        simulation.start_clock();
+        let clock = simulation.clock.clone();
+        let config = StoreConfig {
+            delivery_workers: 3,
+            delivery_success_probability: 0.5,
+            delivery_time: DeliveryTime::Uniform {
+                min_secs: 5.0,
+                max_secs: 10.0,
+            },
+            simulation,
+            ..StoreConfig::default()
+        };
+        let addr = store_with_config(config, vec![Product { id: 1, amount: 20 }]).start();
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let notify = Recorder(reports.clone()).start().recipient();
+        for _ in 0..10 {
+            let msg = ReserveOrder {
+                items: vec![Product { id: 1, amount: 1 }],
+                notify: notify.clone(),
+                priority: Priority::Standard,
+                allow_partial: false,
+            };
+            addr.send(msg).await.unwrap();
+        }
+        // Alcanza para todos los intentos de entrega y sus esperas
+        clock.sleep(Duration::from_secs(600)).await;
+
+        let stock = addr.send(Shutdown).await.unwrap()[&1].amount;
+        let reports = reports
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|report| (report.order_id, report.delivered))
+            .collect();
+        (reports, stock)
+    }
+
+    #[actix_rt::test]
+    async fn seeded_simulation_replays_the_same_in_virtual_time() {
+        let started = std::time::Instant::now();
+
+        let (reports, stock) = simulate(7).await;
+        assert_eq!(reports.len(), 10);
+        let delivered = reports.iter().filter(|(_, delivered)| *delivered).count();
+        assert_eq!(stock, 20 - delivered as i32);
+        assert_eq!(simulate(7).await, (reports, stock));
+        // Diez pedidos con entregas de 5 a 10 segundos y reintentos terminan sin esperarlos
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[actix_rt::test]
+    async fn reports_of_closed_connections_wait_for_the_next_one() {
+        let config = StoreConfig {
+            delivery_workers: 0,
+            ..StoreConfig::default()
+        };
+        let addr = store_with_config(config, vec![Product { id: 1, amount: 5 }]).start();
+        // La conexión por la que llegó el pedido se cierra antes de que se entregue
+        let closed = Ecommerce::create(|ctx| {
+            actix::ActorContext::stop(ctx);
+            Ecommerce
+        });
+        let outcome = addr
+            .send(ReserveOrder {
+                items: vec![Product { id: 1, amount: 2 }],
+                notify: closed.recipient(),
+                priority: Priority::Standard,
+                allow_partial: false,
+            })
+            .await
+            .unwrap();
+        let ReserveOutcome::Reserved { order_id } = outcome else {
+            panic!("el pedido debía reservarse: {:?}", outcome);
+        };
+        // Al cerrar el store el pedido se cancela sin haberse entregado
+        addr.send(Shutdown).await.unwrap();
+
+        // Se reconecta el ecommerce y recibe el aviso guardado
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recipient = Recorder(reports.clone()).start().recipient();
+        addr.send(SubscribeReports { recipient }).await.unwrap();
+        tokio::task::yield_now().await;
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].order_id, order_id);
     }
 }
@@ -1,113 +1,326 @@
-use crate::messages::{BlockProduct, ReceiveOrder};
-use crate::product::Product;
-use actix::{Actor, Addr, Context, StreamHandler};
-use serde_json::{self};
-use std::sync::Arc;
+use crate::messages::{
+    DeliveryReport, GetOrder, QueryStock, ReserveOrder, ReserveOutcome, Restock,
+    SetDeliveryWorkers, SubscribeReports, UnsentReport,
+};
+use crate::product::describe_items;
+use actix::{Actor, ActorContext, Addr, AsyncContext, Context, Handler, StreamHandler};
+use log::{debug, error, info, warn};
 use std::io;
-use tokio::io::AsyncWriteExt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use store_protocol::{
+    write_message, CartRequest, Decoded, Envelope, RejectReason, RequestId, StoreRequest,
+    StoreResponse, NO_REQUEST,
+};
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 
 use crate::Store;
 // Definición del actor StoreServer
 // Representa la lógica para manejar una conexión de cliente.
+//
+// La conexión se cierra cuando el cliente pasa `heartbeat_timeout` sin enviar nada, medido en tiempo
+// real aunque la simulación use un reloj virtual. Los clientes envían heartbeats periódicos para que
+// no pase mientras no tienen pedidos.
 pub struct StoreServer {
     write: Arc<Mutex<tokio::io::WriteHalf<TcpStream>>>,
     store_addr: Addr<Store>,
+    connections: Arc<AtomicUsize>, //Cantidad de conexiones abiertas, la comparten todos los StoreServer
+    heartbeat_timeout: Duration,
+    last_seen: Instant, //Cuándo llegó el último mensaje del cliente
 }
 
 impl StoreServer {
     pub fn new(
         write: Arc<Mutex<tokio::io::WriteHalf<TcpStream>>>,
         store_addr: Addr<Store>,
+        connections: Arc<AtomicUsize>,
+        heartbeat_timeout: Duration,
     ) -> StoreServer {
         StoreServer {
             write,
             store_addr,
+            connections,
+            heartbeat_timeout,
+            last_seen: Instant::now(),
+        }
+    }
+
+    // Cierra la conexión si el cliente lleva más de `timeout` sin enviar nada.
+    fn check_heartbeat(&mut self, timeout: Duration, ctx: &mut Context<Self>) {
+        let silent = self.last_seen.elapsed();
+        if silent > timeout {
+            warn!(
+                silent_secs = silent.as_secs_f64();
+                "El cliente no envía nada hace demasiado. Doy la conexión por muerta"
+            );
+            ctx.stop();
         }
     }
+
+    // Le pide al store que reserve todos los productos del pedido y le informa al ecommerce si se
+    // pudo, respondiendo con el mismo `request_id` con el que llegó el pedido.
+    fn reserve_cart(&self, request_id: RequestId, cart: CartRequest, ctx: &mut Context<Self>) {
+        // Ahora tenes el pedido con los productos, sus cantidades y la prioridad
+        info!(
+            request_id = request_id,
+            items:% = describe_items(&cart.items),
+            priority:? = cart.priority;
+            "Pedido del ecommerce"
+        );
+
+        let order = ReserveOrder {
+            items: cart.items,
+            notify: ctx.address().recipient(),
+            priority: cart.priority,
+            allow_partial: cart.allow_partial,
+        };
+        let store_addr = self.store_addr.clone();
+        //Se agrego el spawn de esta task porque necesitaba esperar por la respuesta de si se pudo
+        //reservar el pedido sin bloquear al actor
+        let write_guard = self.write.clone();
+        tokio::spawn(async move {
+            let response = match store_addr.send(order).await {
+                Ok(outcome) => {
+                    match &outcome {
+                        ReserveOutcome::Reserved { order_id } => {
+                            info!(order_id = order_id; "Pedido bloqueado exitosamente");
+                        }
+                        ReserveOutcome::PartiallyReserved {
+                            order_id,
+                            remaining,
+                        } => {
+                            info!(
+                                order_id = order_id, remaining:% = describe_items(remaining);
+                                "Pedido bloqueado parcialmente"
+                            );
+                        }
+                        _ => info!(outcome:? = outcome; "No se pudo tomar el pedido"),
+                    }
+                    //Le informo al ecommerce si puedo tomar el pedido, qué le faltó o por qué no
+                    StoreResponse::from(outcome)
+                }
+                Err(mailbox_error) => {
+                    error!(
+                        request_id = request_id, error:% = mailbox_error;
+                        "Error al enviar el mensaje para reservar el pedido"
+                    );
+                    StoreResponse::rejected_order(RejectReason::Overloaded)
+                }
+            };
+            write_response(write_guard, request_id, response).await;
+        });
+    }
+}
+
+// Respuesta a un mensaje que no se pudo atender porque el actor `Store` no respondió.
+fn overloaded() -> StoreResponse {
+    StoreResponse::Rejected {
+        reason: RejectReason::Overloaded,
+    }
 }
 
 impl Actor for StoreServer {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.connections.fetch_add(1, Ordering::SeqCst);
+        //Me anoto para recibir los avisos de los pedidos de conexiones anteriores que ya se cerraron
+        self.store_addr.do_send(SubscribeReports {
+            recipient: ctx.address().recipient(),
+        });
+        let timeout = self.heartbeat_timeout;
+        ctx.run_interval(timeout / 3, move |act, ctx| {
+            act.check_heartbeat(timeout, ctx)
+        });
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
-// Implementa el manejo de los mensajes entrantes
-impl StreamHandler<Result<String, io::Error>> for StoreServer {
-    fn handle(&mut self, msg: Result<String, io::Error>, _ctx: &mut Self::Context) {
+// Escribe una respuesta al ecommerce en un frame, con el id del pedido al que responde.
+//
+// Retorna:
+// Si se pudo escribir la respuesta.
+async fn write_response(
+    write: Arc<Mutex<tokio::io::WriteHalf<TcpStream>>>,
+    request_id: RequestId,
+    response: StoreResponse,
+) -> bool {
+    let envelope = Envelope::new(request_id, response);
+    match write_message(&mut *write.lock().await, &envelope).await {
+        Ok(()) => true,
+        Err(e) => {
+            error!(response:? = envelope, error:% = e; "Error al enviar la respuesta");
+            false
+        }
+    }
+}
+
+// Reenvía al ecommerce el resultado del delivery de uno de sus pedidos. Si no se puede escribir en
+// la conexión, el aviso vuelve al store para que lo envíe por otra.
+impl Handler<DeliveryReport> for StoreServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: DeliveryReport, ctx: &mut Self::Context) -> Self::Result {
+        info!(
+            order_id = msg.order_id, delivered = msg.delivered;
+            "Aviso al ecommerce el resultado del pedido"
+        );
+        let response = if msg.delivered {
+            StoreResponse::Delivered {
+                order_id: msg.order_id,
+            }
+        } else {
+            StoreResponse::DeliveryFailed {
+                order_id: msg.order_id,
+            }
+        };
+        let write = self.write.clone();
+        let store_addr = self.store_addr.clone();
+        let from = ctx.address().recipient();
+        tokio::spawn(async move {
+            if !write_response(write, NO_REQUEST, response).await {
+                store_addr.do_send(UnsentReport { report: msg, from });
+            }
+        });
+    }
+}
+
+// Implementa el manejo de los mensajes entrantes, cada uno en un frame ya leído por el codec
+impl StreamHandler<Result<Decoded<StoreRequest>, io::Error>> for StoreServer {
+    fn handle(&mut self, msg: Result<Decoded<StoreRequest>, io::Error>, ctx: &mut Self::Context) {
         // Aquí manejas los mensajes entrantes, por ejemplo, pedidos de e-commerce
-        let pedido = msg.expect("Error cuando se recibe el mensaje");
-        println!("[ACTOR STORE SERVER] Recibi un mensaje: {}", pedido);
-        //Suponiendo que recibo una linea de texto formato id,cantidad
-        // Deserializar JSON a la estructura Product
-        match serde_json::from_str::<Product>(&pedido) {
-            Ok(product) => {
-                // Ahora tenes una instancia de Product
-                println!(
-                    "[ACTOR STORE SERVER] ID: {}, Amount: {}",
-                    product.id, product.amount
+        let pedido = match msg {
+            Ok(pedido) => pedido,
+            Err(e) => {
+                //Si se pierde el framing no se puede seguir leyendo la conexion
+                error!(error:% = e; "Error al leer de la conexión, la cierro");
+                ctx.stop();
+                return;
+            }
+        };
+        debug!(message:? = pedido; "Recibi un mensaje");
+        self.last_seen = Instant::now();
+        let Envelope {
+            id: request_id,
+            message,
+        } = match pedido {
+            Ok(envelope) => envelope,
+            Err(malformed) => {
+                warn!(
+                    request_id:? = malformed.id, error = malformed.error;
+                    "Error al deserializar el mensaje"
                 );
-
-                let order = ReceiveOrder {
-                    id: product.id,
-                    amount: product.amount,
+                //Si se pudo leer el id le respondo al pedido, si no aviso sin id
+                let response = StoreResponse::Rejected {
+                    reason: RejectReason::Malformed {
+                        error: malformed.error,
+                    },
                 };
+                let request_id = malformed.id.unwrap_or(NO_REQUEST);
+                tokio::spawn(write_response(self.write.clone(), request_id, response));
+                return;
+            }
+        };
+        match message {
+            StoreRequest::Heartbeat => {
+                tokio::spawn(write_response(
+                    self.write.clone(),
+                    request_id,
+                    StoreResponse::Heartbeat,
+                ));
+            }
+            StoreRequest::Order(order) => self.reserve_cart(request_id, order.into(), ctx),
+            StoreRequest::Cart(cart) => self.reserve_cart(request_id, cart, ctx),
+            StoreRequest::QueryOrder { order_id } => {
                 let store_addr = self.store_addr.clone();
-                //Se agrego el spawn de esta task porque necesitaba esperar por la respuesta de si se encontraba disponible o no
-                //el producto para bloquearlo y derivarlo al delivery
                 let write_guard = self.write.clone();
                 tokio::spawn(async move {
-                    let send_result = store_addr.send(order).await;
-                    if send_result.unwrap() {
-                        let block_result: Result<(), actix::prelude::MailboxError> = store_addr
-                            .send(BlockProduct {
-                                id: product.id,
-                                amount: product.amount,
-                            })
-                            .await;
-                        match block_result {
-                            Ok(()) => {
-                                println!("[ACTOR STORE SERVER] Pedido bloqueado exitosamente");
-                                //Mando al ecommerce que puedo tomar el pedido
-                                let response: u8 = true as u8;
-                                write_guard
-                                    .lock()
-                                    .await
-                                    .write_u8(response)
-                                    .await
-                                    .expect("Se tuvo que mandar");
-                            }
-                            Err(mailbox_error) => {
-                                println!(
-                                    "\x1b[31m[ACTOR STORE SERVER] Error al enviar el mensaje para bloquear el producto: {}\x1b[0m",
-                                    mailbox_error
-                                );
-                            }
+                    match store_addr.send(GetOrder { id: order_id }).await {
+                        Ok(order) => {
+                            let response = StoreResponse::OrderStatus { order };
+                            write_response(write_guard, request_id, response).await;
+                        }
+                        Err(mailbox_error) => {
+                            error!(
+                                order_id = order_id, error:% = mailbox_error;
+                                "Error al consultar el pedido"
+                            );
+                            write_response(write_guard, request_id, overloaded()).await;
                         }
-                    } else {
-                        //Mando al ecommerce que no puedo tomar el pedido
-                        let response: u8 = false as u8;
-                        write_guard
-                            .lock()
-                            .await
-                            .write_u8(response)
-                            .await
-                            .expect("Se tuvo que mandar");
                     }
                 });
             }
-            Err(e) => {
-                eprintln!(
-                    "\x1b[31m[ACTOR STORE SERVER] Error al deserializar el mensaje: {}\x1b[0m",
-                    e
-                );
+            StoreRequest::QueryStock { ids } => {
+                let store_addr = self.store_addr.clone();
+                let write_guard = self.write.clone();
+                tokio::spawn(async move {
+                    match store_addr.send(QueryStock { ids }).await {
+                        Ok(products) => {
+                            let response = StoreResponse::Stock { products };
+                            write_response(write_guard, request_id, response).await;
+                        }
+                        Err(mailbox_error) => {
+                            error!(error:% = mailbox_error; "Error al consultar el stock");
+                            write_response(write_guard, request_id, overloaded()).await;
+                        }
+                    }
+                });
+            }
+            StoreRequest::Restock { product_id, amount } => {
+                let store_addr = self.store_addr.clone();
+                let write_guard = self.write.clone();
+                tokio::spawn(async move {
+                    let msg = Restock {
+                        id: product_id,
+                        amount,
+                    };
+                    match store_addr.send(msg).await {
+                        Ok(stock) => {
+                            let response = StoreResponse::RestockResult { product_id, stock };
+                            write_response(write_guard, request_id, response).await;
+                        }
+                        Err(mailbox_error) => {
+                            error!(
+                                product_id = product_id, error:% = mailbox_error;
+                                "Error al reponer el producto"
+                            );
+                            write_response(write_guard, request_id, overloaded()).await;
+                        }
+                    }
+                });
+            }
+            StoreRequest::ScaleDelivery { delivery_workers } => {
+                let store_addr = self.store_addr.clone();
+                let write_guard = self.write.clone();
+                tokio::spawn(async move {
+                    let msg = SetDeliveryWorkers {
+                        count: delivery_workers,
+                    };
+                    match store_addr.send(msg).await {
+                        Ok(count) => {
+                            let response = StoreResponse::DeliveryWorkers { count };
+                            write_response(write_guard, request_id, response).await;
+                        }
+                        Err(mailbox_error) => {
+                            error!(error:% = mailbox_error; "Error al cambiar los deliverys");
+                            write_response(write_guard, request_id, overloaded()).await;
+                        }
+                    }
+                });
             }
         }
     }
 
-    fn finished(&mut self, _ctx: &mut Self::Context) {
-        // Maneja la desconexión
-        println!("\x1b[31m[ACTOR STORE SERVER] Se desconecto\x1b[0m");
+    fn finished(&mut self, ctx: &mut Self::Context) {
+        // Maneja la desconexión. Hay que frenar el actor a mano porque el control de los
+        // heartbeats lo mantiene vivo
+        info!("Se desconecto el ecommerce");
+        ctx.stop();
     }
 }
 /*
@@ -118,3 +331,76 @@ async fn process_store_orders(file_path: &Path) -> io::Result<()> {
 */
 
 // Aquí puedes añadir más funciones, como la lectura y procesamiento de pedidos de un archivo
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use crate::journal::{Journal, StoreSnapshot};
+    use crate::metrics::StoreMetrics;
+    use crate::order::OrderId;
+    use std::collections::HashMap;
+    use tokio::io::{split, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Hace de conexión nueva del ecommerce y anota los avisos que recibe.
+    struct Recorder(Arc<std::sync::Mutex<Vec<(OrderId, bool)>>>);
+
+    impl Actor for Recorder {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<DeliveryReport> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, msg: DeliveryReport, _ctx: &mut Self::Context) {
+            self.0.lock().unwrap().push((msg.order_id, msg.delivered));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn reports_that_cannot_be_written_go_back_to_the_store() {
+        let store = Store::new(
+            StoreConfig::default(),
+            StoreSnapshot::new(HashMap::new()),
+            Arc::new(Journal::disabled()),
+            Arc::new(StoreMetrics::new()),
+        )
+        .start();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (_read, write) = split(stream);
+        let write = Arc::new(Mutex::new(write));
+        // No lee de la conexión, así que no se entera de que el ecommerce la cerró
+        let server = StoreServer::new(
+            write.clone(),
+            store.clone(),
+            Arc::new(AtomicUsize::new(0)),
+            Duration::from_secs(60),
+        )
+        .start();
+
+        // El ecommerce cierra su lado y lo primero que se escribe hace que el sistema lo note
+        drop(client);
+        let _ = write.lock().await.write_all(b"\0").await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        server
+            .send(DeliveryReport {
+                order_id: 7,
+                delivered: true,
+            })
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // El aviso no se perdió: lo recibe la próxima conexión que se anota
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recipient = Recorder(reports.clone()).start().recipient();
+        store.send(SubscribeReports { recipient }).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*reports.lock().unwrap(), vec![(7, true)]);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::messages::{GetLowStock, Restock};
+use crate::store::Store;
+use actix::{Actor, ActorFutureExt, Addr, AsyncContext, Context, WrapFuture};
+use log::{error, info};
+use sim_core::Clock;
+use std::time::Duration;
+
+// Configuración del proveedor que repone el stock del store.
+//
+// Atributos:
+// * `interval`: Cada cuánto tiempo pasa el proveedor.
+// * `reorder_point`: Si se indica, solo se reponen los productos con menos unidades que este valor.
+//   Si no, en cada pasada se reponen todos los productos.
+// * `reorder_amount`: Cantidad de unidades que se agregan a cada producto repuesto.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupplierConfig {
+    pub interval: Duration,
+    pub reorder_point: Option<i32>,
+    pub reorder_amount: i32,
+}
+
+// Actor que simula un proveedor: cada `interval` le pregunta al store qué productos
+// necesitan reposición y le envía un `Restock` por cada uno. El intervalo se mide con `clock`, el
+// reloj de la simulación.
+pub struct Supplier {
+    config: SupplierConfig,
+    store_addr: Addr<Store>,
+    clock: Clock,
+}
+
+impl Supplier {
+    pub fn new(config: SupplierConfig, store_addr: Addr<Store>, clock: Clock) -> Supplier {
+        Supplier {
+            config,
+            store_addr,
+            clock,
+        }
+    }
+
+    // Espera un intervalo, repone y vuelve a programar la próxima pasada.
+    fn schedule(&mut self, ctx: &mut Context<Self>) {
+        let wait = self.clock.sleep(self.config.interval).into_actor(self);
+        ctx.spawn(wait.map(|_, act, ctx| {
+            act.supply(ctx);
+            act.schedule(ctx);
+        }));
+    }
+
+    // Consulta los productos a reponer y los repone.
+    fn supply(&mut self, ctx: &mut Context<Self>) {
+        let reorder_point = self.config.reorder_point.unwrap_or(i32::MAX);
+        let reorder_amount = self.config.reorder_amount;
+        let store_addr = self.store_addr.clone();
+        let low_stock = self.store_addr.send(GetLowStock { reorder_point });
+        ctx.spawn(low_stock.into_actor(self).map(move |result, _act, _ctx| {
+            let products = match result {
+                Ok(products) => products,
+                Err(e) => {
+                    error!(error:% = e; "Error al consultar el stock");
+                    return;
+                }
+            };
+            for product in products {
+                info!(
+                    product_id = product.id, amount = reorder_amount, stock = product.amount;
+                    "Repongo stock del producto"
+                );
+                store_addr.do_send(Restock {
+                    id: product.id,
+                    amount: reorder_amount,
+                });
+            }
+        }));
+    }
+}
+
+impl Actor for Supplier {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        info!(interval_secs = self.config.interval.as_secs(); "Proveedor activo");
+        self.schedule(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use crate::journal::{Journal, StoreSnapshot};
+    use crate::metrics::StoreMetrics;
+    use crate::product::Product;
+    use std::sync::Arc;
+
+    #[actix_rt::test]
+    async fn restocks_only_the_low_products_after_each_interval() {
+        let products = [
+            (1, Product { id: 1, amount: 2 }),
+            (2, Product { id: 2, amount: 10 }),
+        ]
+        .into_iter()
+        .collect();
+        let store = Store::new(
+            StoreConfig::default(),
+            StoreSnapshot::new(products),
+            Arc::new(Journal::disabled()),
+            Arc::new(StoreMetrics::new()),
+        )
+        .start();
+        let clock = Clock::virtual_time();
+        let config = SupplierConfig {
+            interval: Duration::from_secs(60),
+            reorder_point: Some(5),
+            reorder_amount: 10,
+        };
+        Supplier::new(config, store.clone(), clock.clone()).start();
+        let stock = || async {
+            let mut products = store
+                .send(GetLowStock {
+                    reorder_point: i32::MAX,
+                })
+                .await
+                .unwrap();
+            products.sort_by_key(|product| product.id);
+            products
+                .iter()
+                .map(|product| product.amount)
+                .collect::<Vec<_>>()
+        };
+
+        // Antes de que pase el primer intervalo no repone nada
+        clock.sleep(Duration::from_secs(30)).await;
+        assert_eq!(stock().await, vec![2, 10]);
+
+        // En la primera pasada repone el producto con poco stock y deja el otro como estaba
+        clock.sleep(Duration::from_secs(60)).await;
+        assert_eq!(stock().await, vec![12, 10]);
+
+        // Ya ninguno está por debajo del punto de reposición
+        clock.sleep(Duration::from_secs(60)).await;
+        assert_eq!(stock().await, vec![12, 10]);
+    }
+}